@@ -0,0 +1,160 @@
+use crate::{Cosmogony, Zone, ZoneIndex};
+use serde_derive::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// Differences between two cosmogonies
+///
+/// The zones are identified by their osm_id
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CosmogonyDiff {
+    /// osm_id of the zones only in the new cosmogony
+    pub added: Vec<String>,
+    /// osm_id of the zones only in the old cosmogony
+    pub removed: Vec<String>,
+    /// osm_id of the zones in both cosmogonies, but with a different content
+    pub modified: Vec<String>,
+}
+
+impl CosmogonyDiff {
+    /// number of changed zones
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.modified.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// the zones' indexes are specific to a cosmogony, so the zones are compared
+// without their indexes, and the parents are compared using their osm_id
+fn zones_by_osm_id(cosmogony: &Cosmogony) -> BTreeMap<&str, &Zone> {
+    cosmogony
+        .zones
+        .iter()
+        .map(|z| (z.osm_id.as_str(), z))
+        .collect()
+}
+
+fn osm_ids_by_index(cosmogony: &Cosmogony) -> HashMap<ZoneIndex, &str> {
+    cosmogony
+        .zones
+        .iter()
+        .map(|z| (z.id, z.osm_id.as_str()))
+        .collect()
+}
+
+fn parent_osm_id<'a>(zone: &Zone, osm_ids: &HashMap<ZoneIndex, &'a str>) -> Option<&'a str> {
+    zone.parent.and_then(|parent| osm_ids.get(&parent).copied())
+}
+
+fn without_indexes(zone: &Zone) -> Zone {
+    Zone {
+        id: ZoneIndex { index: 0 },
+        parent: None,
+        candidate_parents: vec![],
+        ..zone.clone()
+    }
+}
+
+impl Cosmogony {
+    /// Compute the differences between this cosmogony (the old one) and another one (the new one)
+    ///
+    /// The zones are matched by osm_id, and compared field by field
+    /// (the parents being compared by osm_id, since the indexes are specific to a cosmogony).
+    pub fn diff(&self, other: &Cosmogony) -> CosmogonyDiff {
+        let old_zones = zones_by_osm_id(self);
+        let new_zones = zones_by_osm_id(other);
+        let old_osm_ids = osm_ids_by_index(self);
+        let new_osm_ids = osm_ids_by_index(other);
+
+        let added = new_zones
+            .keys()
+            .filter(|osm_id| !old_zones.contains_key(*osm_id))
+            .map(|osm_id| osm_id.to_string())
+            .collect();
+        let removed = old_zones
+            .keys()
+            .filter(|osm_id| !new_zones.contains_key(*osm_id))
+            .map(|osm_id| osm_id.to_string())
+            .collect();
+        let modified = old_zones
+            .iter()
+            .filter_map(|(osm_id, old_zone)| {
+                let new_zone = new_zones.get(osm_id)?;
+                let is_modified = parent_osm_id(old_zone, &old_osm_ids)
+                    != parent_osm_id(new_zone, &new_osm_ids)
+                    || without_indexes(old_zone) != without_indexes(new_zone);
+                if is_modified {
+                    Some(osm_id.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        CosmogonyDiff {
+            added,
+            removed,
+            modified,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_zone(idx: usize, osm_id: &str, parent: Option<usize>) -> Zone {
+        Zone {
+            id: ZoneIndex { index: idx },
+            osm_id: osm_id.into(),
+            name: osm_id.into(),
+            parent: parent.map(|p| ZoneIndex { index: p }),
+            ..Default::default()
+        }
+    }
+
+    fn make_cosmogony(zones: Vec<Zone>) -> Cosmogony {
+        Cosmogony {
+            zones,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn diff_test() {
+        let old = make_cosmogony(vec![
+            make_zone(0, "relation:0", None),
+            make_zone(1, "relation:1", Some(0)),
+            make_zone(2, "relation:2", Some(1)),
+            make_zone(3, "relation:3", Some(1)),
+        ]);
+        let mut renamed = make_zone(12, "relation:1", Some(10));
+        renamed.name = "new name".into();
+        let new = make_cosmogony(vec![
+            // same zones but with different indexes
+            make_zone(10, "relation:0", None),
+            renamed,
+            make_zone(11, "relation:2", Some(12)),
+            // the parent has changed
+            make_zone(13, "relation:3", Some(10)),
+            make_zone(14, "relation:4", Some(10)),
+        ]);
+
+        assert!(old.diff(&old).is_empty());
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec!["relation:4".to_string()]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.modified,
+            vec!["relation:1".to_string(), "relation:3".to_string()]
+        );
+
+        let diff = new.diff(&old);
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed, vec!["relation:4".to_string()]);
+        assert_eq!(diff.len(), 3);
+    }
+}
@@ -1,32 +1,84 @@
 use anyhow::{anyhow, Error};
+use serde_derive::{Deserialize, Serialize};
 use std::path::Path;
+use std::str::FromStr;
 
-#[derive(PartialEq, Clone)]
+/// Version of the zones' schema, to be bumped when the `Zone` format changes
+///
+/// The files written before the introduction of the schema version are version 0.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The first line of a json stream, holding its schema version
+#[derive(Serialize, Deserialize)]
+struct SchemaMarker {
+    #[serde(rename = "_cosmogony_schema")]
+    schema_version: u32,
+}
+
+/// Write the schema version marker, to be written as the first line of a json stream
+pub fn write_schema_marker(mut writer: impl std::io::Write) -> Result<(), Error> {
+    serde_json::to_writer(
+        &mut writer,
+        &SchemaMarker {
+            schema_version: SCHEMA_VERSION,
+        },
+    )?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Get the schema version of a json stream line, if it is a schema version marker
+pub(crate) fn parse_schema_marker(line: &str) -> Option<u32> {
+    if !line.starts_with("{\"_cosmogony_schema\"") {
+        return None;
+    }
+    serde_json::from_str::<SchemaMarker>(line)
+        .ok()
+        .map(|m| m.schema_version)
+}
+
+#[derive(PartialEq, Clone, Debug)]
 pub enum OutputFormat {
     Json,
     JsonGz,
     JsonStream,
     JsonStreamGz,
+    FlatGeobuf,
+    /// newline-delimited GeoJSON, each line being a GeoJSON Feature
+    GeoJsonSeq,
+    GeoPackage,
+    /// json compressed with zstd
+    JsonZst,
+    /// json stream compressed with zstd
+    JsonStreamZst,
 }
 
-static ALL_EXTENSIONS: [(&str, OutputFormat); 4] = [
+static ALL_EXTENSIONS: [(&str, OutputFormat); 10] = [
     (".json", OutputFormat::Json),
     (".jsonl", OutputFormat::JsonStream),
     (".json.gz", OutputFormat::JsonGz),
     (".jsonl.gz", OutputFormat::JsonStreamGz),
+    (".json.zst", OutputFormat::JsonZst),
+    (".jsonl.zst", OutputFormat::JsonStreamZst),
+    (".fgb", OutputFormat::FlatGeobuf),
+    (".geojsonl", OutputFormat::GeoJsonSeq),
+    (".geojsonseq", OutputFormat::GeoJsonSeq),
+    (".gpkg", OutputFormat::GeoPackage),
 ];
 
 impl OutputFormat {
     pub fn from_filename(filename: impl AsRef<Path>) -> Result<OutputFormat, Error> {
+        // the extensions are matched case-insensitively, and the longest matching one wins
+        // (so that 'foo.json.gz' is not detected as json if '.json' were to be matched first)
+        let lowercase_name = filename
+            .as_ref()
+            .file_name()
+            .and_then(|f| f.to_str())
+            .map(str::to_lowercase);
         ALL_EXTENSIONS
             .iter()
-            .find(|&(e, _)| {
-                filename
-                    .as_ref()
-                    .file_name()
-                    .and_then(|f| f.to_str())
-                    .map_or(false, |f| f.ends_with(e))
-            })
+            .filter(|&(e, _)| lowercase_name.as_ref().map_or(false, |f| f.ends_with(e)))
+            .max_by_key(|(e, _)| e.len())
             .map(|(_, f)| f.clone())
             .ok_or_else(|| {
                 let extensions_str = ALL_EXTENSIONS
@@ -42,4 +94,130 @@ impl OutputFormat {
                 )
             })
     }
+
+    /// The extension of the files of this format, with its leading dot (eg: '.jsonl.gz')
+    pub fn extension(&self) -> &'static str {
+        ALL_EXTENSIONS
+            .iter()
+            .find(|(_, f)| f == self)
+            .map(|(e, _)| *e)
+            .expect("all the formats have an extension")
+    }
+}
+
+/// Parse a format from its extension, with or without the leading dot (eg: 'jsonl.gz')
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let extension = format!(".{}", s.trim_start_matches('.').to_lowercase());
+        ALL_EXTENSIONS
+            .iter()
+            .find(|(e, _)| *e == extension)
+            .map(|(_, f)| f.clone())
+            .ok_or_else(|| anyhow!("Unknown format '{}'", s))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OutputFormat;
+
+    #[test]
+    fn test_extension() {
+        assert_eq!(OutputFormat::JsonStreamGz.extension(), ".jsonl.gz");
+        assert_eq!(OutputFormat::GeoJsonSeq.extension(), ".geojsonl");
+        for (_, format) in super::ALL_EXTENSIONS.iter() {
+            assert_eq!(
+                &OutputFormat::from_filename(format!("foo{}", format.extension())).unwrap(),
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_filename() {
+        assert_eq!(
+            OutputFormat::from_filename("foo.json").unwrap(),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            OutputFormat::from_filename("/tmp/foo.jsonl").unwrap(),
+            OutputFormat::JsonStream
+        );
+        assert!(OutputFormat::from_filename("foo.geojson.txt").is_err());
+        assert_eq!(
+            OutputFormat::from_filename("foo.fgb").unwrap(),
+            OutputFormat::FlatGeobuf
+        );
+        assert_eq!(
+            OutputFormat::from_filename("foo.geojsonl").unwrap(),
+            OutputFormat::GeoJsonSeq
+        );
+        assert_eq!(
+            OutputFormat::from_filename("foo.geojsonseq").unwrap(),
+            OutputFormat::GeoJsonSeq
+        );
+        assert_eq!(
+            OutputFormat::from_filename("foo.gpkg").unwrap(),
+            OutputFormat::GeoPackage
+        );
+        assert!(OutputFormat::from_filename("foo").is_err());
+    }
+
+    #[test]
+    fn test_from_filename_mixed_case() {
+        assert_eq!(
+            OutputFormat::from_filename("MyFile.JSON").unwrap(),
+            OutputFormat::Json
+        );
+        assert_eq!(
+            OutputFormat::from_filename("OUTPUT.JSONL.GZ").unwrap(),
+            OutputFormat::JsonStreamGz
+        );
+        assert_eq!(
+            OutputFormat::from_filename("output.Json.Gz").unwrap(),
+            OutputFormat::JsonGz
+        );
+    }
+
+    #[test]
+    fn test_from_filename_longest_extension_wins() {
+        assert_eq!(
+            OutputFormat::from_filename("foo.json.gz").unwrap(),
+            OutputFormat::JsonGz
+        );
+        assert_eq!(
+            OutputFormat::from_filename("foo.jsonl.gz").unwrap(),
+            OutputFormat::JsonStreamGz
+        );
+        assert_eq!(
+            OutputFormat::from_filename("foo.json.zst").unwrap(),
+            OutputFormat::JsonZst
+        );
+        assert_eq!(
+            OutputFormat::from_filename("foo.jsonl.zst").unwrap(),
+            OutputFormat::JsonStreamZst
+        );
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!(
+            ".JSONL.gz".parse::<OutputFormat>().unwrap(),
+            OutputFormat::JsonStreamGz
+        );
+        assert!("geojson".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_schema_marker() {
+        let mut buf = vec![];
+        super::write_schema_marker(&mut buf).unwrap();
+        let line = String::from_utf8(buf).unwrap();
+        assert_eq!(line, "{\"_cosmogony_schema\":1}\n");
+        assert_eq!(super::parse_schema_marker(line.trim_end()), Some(1));
+        assert_eq!(super::parse_schema_marker("{\"id\":0}"), None);
+    }
 }
@@ -1,9 +1,20 @@
+mod diff;
 pub mod file_format;
 mod model;
 pub mod mutable_slice;
+mod name_index;
 mod read;
 mod zone;
 
-pub use model::{Cosmogony, CosmogonyMetadata, CosmogonyStats};
-pub use read::{load_cosmogony_from_file, read_zones_from_file};
-pub use zone::{Coord, Zone, ZoneIndex, ZoneType};
+pub use diff::CosmogonyDiff;
+pub use file_format::SCHEMA_VERSION;
+pub use model::{
+    reindex_zones, retain_zones, Cosmogony, CosmogonyMetadata, CosmogonyStats,
+    MANY_HOLES_THRESHOLD, UNHANDLED_LEVEL_EXAMPLES,
+};
+pub use name_index::{normalize_name, NameIndex};
+pub use read::{
+    load_cosmogony, load_cosmogony_from_file, load_cosmogony_with_options, read_zones_from_file,
+    read_zones_from_reader, read_zones_from_reader_with_options, LoadOptions, MalformedLine,
+};
+pub use zone::{AdminLevelSource, Coord, VoronoiDebug, Zone, ZoneIndex, ZoneType};
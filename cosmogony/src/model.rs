@@ -1,22 +1,267 @@
-use crate::zone::Zone;
+use crate::zone::{Zone, ZoneIndex};
+use anyhow::{anyhow, Error};
+use geo::GeodesicDistance;
+use geo_types::{Coord, Rect};
+use log::warn;
 use serde_derive::*;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Cosmogony {
     pub zones: Vec<Zone>,
     pub meta: CosmogonyMetadata,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+impl Cosmogony {
+    /// Create a cosmogony from some zones, with their stats computed
+    ///
+    /// Only the stats that can be computed from the zones are set (the zone types,
+    /// admin levels and wikidata counts, ...), not the ones of the build (like the skipped zones).
+    pub fn from_zones(zones: Vec<Zone>, osm_filename: String) -> Self {
+        let mut stats = CosmogonyStats::default();
+        stats.compute(&zones);
+        Self {
+            zones,
+            meta: CosmogonyMetadata {
+                schema_version: crate::SCHEMA_VERSION,
+                osm_filename,
+                stats,
+            },
+        }
+    }
+
+    /// Compute the stats of the current zones, without updating `meta.stats`
+    ///
+    /// Only the stats that can be computed from the zones are computed again (see
+    /// [`Cosmogony::from_zones`]), the ones of the build (like the skipped zones)
+    /// are kept from `meta.stats`.
+    pub fn stats(&self) -> CosmogonyStats {
+        let mut stats = CosmogonyStats {
+            level_counts: BTreeMap::new(),
+            zone_type_counts: BTreeMap::new(),
+            wikidata_counts: BTreeMap::new(),
+            duplicate_wikidata: vec![],
+            zones_with_many_holes: BTreeMap::new(),
+            zone_type_counts_by_country: BTreeMap::new(),
+            ..self.meta.stats.clone()
+        };
+        stats.compute(&self.zones);
+        stats
+    }
+
+    /// Compute the stats of the current zones again, in `meta.stats`
+    ///
+    /// This keeps the stats truthful after the zones have been changed
+    /// (like after a [`Cosmogony::retain`], or for a cosmogony loaded from a json stream,
+    /// which has no stats).
+    pub fn recompute_stats(&mut self) {
+        self.meta.stats = self.stats();
+    }
+
+    /// Compute the bounding box of the whole cosmogony
+    ///
+    /// This is the union of all the zones' bbox (zones without bbox are skipped),
+    /// so it's cheap to compute, even on a cosmogony loaded from a file.
+    /// Returns None if no zone has a bbox.
+    pub fn bounding_rect(&self) -> Option<Rect<f64>> {
+        self.zones
+            .iter()
+            .filter_map(|z| z.bbox)
+            .reduce(|acc, bbox| {
+                Rect::new(
+                    Coord {
+                        x: acc.min().x.min(bbox.min().x),
+                        y: acc.min().y.min(bbox.min().y),
+                    },
+                    Coord {
+                        x: acc.max().x.max(bbox.max().x),
+                        y: acc.max().y.max(bbox.max().y),
+                    },
+                )
+            })
+    }
+
+    /// Build an index of the zones by their wikidata id
+    pub fn wikidata_index(&self) -> HashMap<String, ZoneIndex> {
+        self.zones
+            .iter()
+            .filter_map(|z| z.wikidata.as_ref().map(|wd| (wd.clone(), z.id)))
+            .collect()
+    }
+
+    /// Find a zone by its wikidata id (eg: "Q1842")
+    ///
+    /// Note: this does a full scan of the zones, use `wikidata_index` for repeated lookups
+    pub fn zone_by_wikidata(&self, qid: &str) -> Option<&Zone> {
+        self.zones
+            .iter()
+            .find(|z| z.wikidata.as_deref() == Some(qid))
+    }
+
+    /// Find a zone by its id
+    ///
+    /// The ids of the zones are not always their position in `zones` (some zones are
+    /// removed during the build), so the position is only tried first.
+    pub fn zone_by_id(&self, idx: ZoneIndex) -> Option<&Zone> {
+        self.zones
+            .get(idx.index)
+            .filter(|z| z.id == idx)
+            .or_else(|| self.zones.iter().find(|z| z.id == idx))
+    }
+
+    /// The geodesic distance (in meters) between the centers of two zones
+    ///
+    /// Returns None if a zone does not exist or has no center.
+    pub fn distance_between(&self, a: ZoneIndex, b: ZoneIndex) -> Option<f64> {
+        let center_a = self.zone_by_id(a)?.center?;
+        let center_b = self.zone_by_id(b)?.center?;
+        Some(center_a.geodesic_distance(&center_b))
+    }
+
+    /// The ancestors of a zone, from its parent to the root of the hierarchy
+    ///
+    /// The walk stops at a non existing parent, or if a zone is met twice (cycle).
+    pub fn ancestors(&self, idx: ZoneIndex) -> Vec<&Zone> {
+        let mut ancestors = vec![];
+        let mut visited = BTreeSet::from([idx]);
+        let mut current = self.zone_by_id(idx).and_then(|z| z.parent);
+        while let Some(parent_idx) = current {
+            if !visited.insert(parent_idx) {
+                warn!("cycle detected in the hierarchy of zone {}", idx.index);
+                break;
+            }
+            let parent = match self.zone_by_id(parent_idx) {
+                Some(parent) => parent,
+                None => break,
+            };
+            ancestors.push(parent);
+            current = parent.parent;
+        }
+        ancestors
+    }
+
+    /// Check that the zones' hierarchy is consistent
+    ///
+    /// Every parent must reference an existing zone, and the hierarchy must not contain any cycle.
+    /// The returned error lists the osm_id of the offending zones.
+    pub fn validate_hierarchy(&self) -> Result<(), Error> {
+        let zones_by_id: BTreeMap<ZoneIndex, &Zone> =
+            self.zones.iter().map(|z| (z.id, z)).collect();
+
+        let mut dangling_parents = vec![];
+        let mut cycles = vec![];
+        for zone in &self.zones {
+            if let Some(parent) = zone.parent {
+                if !zones_by_id.contains_key(&parent) {
+                    dangling_parents.push(zone.osm_id.as_str());
+                    continue;
+                }
+            }
+            let mut visited = BTreeSet::new();
+            let mut current = zone.parent;
+            while let Some(idx) = current {
+                if idx == zone.id {
+                    cycles.push(zone.osm_id.as_str());
+                    break;
+                }
+                if !visited.insert(idx) {
+                    // there is a cycle above this zone, it will be reported by the zones in it
+                    break;
+                }
+                current = zones_by_id.get(&idx).and_then(|z| z.parent);
+            }
+        }
+
+        let mut errors = vec![];
+        if !dangling_parents.is_empty() {
+            errors.push(format!(
+                "{} zone(s) with a non existing parent: {}",
+                dangling_parents.len(),
+                dangling_parents.join(", ")
+            ));
+        }
+        if !cycles.is_empty() {
+            errors.push(format!(
+                "{} zone(s) in a cyclic hierarchy: {}",
+                cycles.len(),
+                cycles.join(", ")
+            ));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid hierarchy, {}", errors.join(" and ")))
+        }
+    }
+
+    /// Only keep the zones matching a predicate, with a consistent hierarchy
+    ///
+    /// The parent of a kept zone becomes its nearest kept ancestor (None if there is none).
+    ///
+    /// WARNING: the kept zones are reindexed (their ids become their positions in `zones`),
+    /// so the ids known before the call are no longer valid.
+    /// Note: the labels and the depths are not changed, they still reflect the whole hierarchy
+    pub fn retain(&mut self, pred: impl Fn(&Zone) -> bool) {
+        retain_zones(&mut self.zones, pred);
+    }
+}
+
+/// Only keep the zones matching a predicate, see [`Cosmogony::retain`]
+pub fn retain_zones(zones: &mut Vec<Zone>, pred: impl Fn(&Zone) -> bool) {
+    let by_id: HashMap<ZoneIndex, &Zone> = zones.iter().map(|z| (z.id, z)).collect();
+    let nearest_kept_ancestor = |z: &Zone| {
+        let mut parent = z.parent;
+        // the walk is bounded by the number of zones, in case the hierarchy has a cycle
+        for _ in 0..by_id.len() {
+            match parent.and_then(|p| by_id.get(&p)) {
+                Some(p) if !pred(p) => parent = p.parent,
+                Some(_) => return parent,
+                None => return None,
+            }
+        }
+        None
+    };
+    let new_parents: HashMap<ZoneIndex, Option<ZoneIndex>> = zones
+        .iter()
+        .filter(|z| pred(z))
+        .map(|z| (z.id, nearest_kept_ancestor(z)))
+        .collect();
+
+    zones.retain(|z| new_parents.contains_key(&z.id));
+    zones.iter_mut().for_each(|z| z.parent = new_parents[&z.id]);
+    reindex_zones(zones);
+}
+
+/// Set the zones' ids to their position in the vector, and update the references to them
+pub fn reindex_zones(zones: &mut [Zone]) {
+    let new_ids: HashMap<ZoneIndex, ZoneIndex> = zones
+        .iter()
+        .enumerate()
+        .map(|(index, z)| (z.id, ZoneIndex { index }))
+        .collect();
+    for z in zones.iter_mut() {
+        z.id = new_ids[&z.id];
+        z.parent = z.parent.and_then(|p| new_ids.get(&p).copied());
+        z.candidate_parents = z
+            .candidate_parents
+            .iter()
+            .filter_map(|c| new_ids.get(c).copied())
+            .collect();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct CosmogonyMetadata {
+    /// version of the zones' schema (see `SCHEMA_VERSION`), 0 for the files written before it
+    #[serde(default)]
+    pub schema_version: u32,
     pub osm_filename: String,
     pub stats: CosmogonyStats,
     // errors:
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct CosmogonyStats {
     pub level_counts: BTreeMap<u32, u64>,
     pub zone_type_counts: BTreeMap<String, u64>,
@@ -24,25 +269,151 @@ pub struct CosmogonyStats {
     pub zone_with_unkwown_country_rules: BTreeMap<String, usize>,
     pub unhandled_admin_level: BTreeMap<String, BTreeMap<u32, usize>>,
     pub zone_without_country: usize,
+    /// wikidata ids shared by several zones (with the osm_id of those zones),
+    /// this usually indicates a tagging error
+    #[serde(default)]
+    pub duplicate_wikidata: Vec<(String, Vec<String>)>,
+    /// number of boundaries that were not valid multipolygons and had to be normalized
+    #[serde(default)]
+    pub normalized_boundaries: usize,
+    /// number of admin_level 2 zones without the ISO3166-1:alpha2 tag considered as countries
+    /// thanks to the country code found in their other tags
+    #[serde(default)]
+    pub rescued_countries: usize,
+    /// number of zones with a boundary with almost no area
+    #[serde(default)]
+    pub degenerate_boundaries: usize,
+    /// number of disputed boundaries skipped (see the `--include-disputed` option)
+    #[serde(default)]
+    pub disputed_skipped: usize,
+    /// number of zones skipped since their country is not in the allowed countries
+    /// (see the `--countries` option)
+    #[serde(default)]
+    pub zone_outside_countries: usize,
+    /// number of zones with a raw osm admin_level above the max, clamped to the max
+    /// (see the `--max-raw-admin-level` option)
+    #[serde(default)]
+    pub clamped_admin_levels: usize,
+    /// number of zones dropped since their raw osm admin_level is above the max
+    #[serde(default)]
+    pub dropped_admin_levels: usize,
+    /// zones contained by several admin zones of the same type (with the osm_id of those zones),
+    /// their parent is chosen arbitrarily. This usually indicates overlapping boundaries
+    #[serde(default)]
+    pub containment_conflicts: Vec<(String, Vec<String>)>,
+    /// number of zones merged into an adjacent zone with the same name, type and parent
+    /// (see the `--merge-same-name-siblings` option)
+    #[serde(default)]
+    pub merged_siblings: usize,
+    /// number of administrative boundaries without admin_level whose level has been inferred
+    /// from their other tags (see the `--infer-missing-admin-level` option)
+    #[serde(default)]
+    pub inferred_admin_levels: usize,
+    /// number of administrative boundaries that cannot be built (even by closing their
+    /// nearly closed rings), by country code (the zones without country are under the "??" key)
+    #[serde(default)]
+    pub boundary_build_failures: BTreeMap<String, u64>,
+    /// number of zones dropped because their center is not in the country
+    /// (see the `--only-country` option)
+    #[serde(default)]
+    pub out_of_country_zones: usize,
+    /// number of osm objects skipped because their osm id is excluded
+    /// (see the `--exclude-osm-ids` option)
+    #[serde(default)]
+    pub excluded_zones: usize,
+    /// the zones with more than `MANY_HOLES_THRESHOLD` holes in their boundary (by osm_id),
+    /// with their number of holes (this usually comes from a tagging or an import error)
+    #[serde(default)]
+    pub zones_with_many_holes: BTreeMap<String, usize>,
+    /// number of zones dropped because they are outside of the clip polygon
+    /// (see the `--clip` option)
+    #[serde(default)]
+    pub clipped_out_zones: usize,
+    /// the zones (by osm_id) whose center node (admin_centre or label) is outside of
+    /// their boundary, usually a sign of a bad tagging or of a broken boundary
+    #[serde(default)]
+    pub center_outside_boundary: Vec<String>,
+    /// some osm_ids of the zones counted in `unhandled_admin_level`, by country and level
+    /// (at most `UNHANDLED_LEVEL_EXAMPLES` by level), to investigate the unhandled levels
+    #[serde(default)]
+    pub unhandled_admin_level_examples: BTreeMap<String, BTreeMap<u32, Vec<String>>>,
+    /// zone types counts by country code (the zones without country are under the "??" key)
+    #[serde(default)]
+    pub zone_type_counts_by_country: BTreeMap<String, BTreeMap<String, u64>>,
 }
 
+/// Number of holes above which a zone is reported in `CosmogonyStats::zones_with_many_holes`
+pub const MANY_HOLES_THRESHOLD: usize = 100;
+
+/// Maximum number of osm_ids kept by country and level in
+/// `CosmogonyStats::unhandled_admin_level_examples`
+pub const UNHANDLED_LEVEL_EXAMPLES: usize = 5;
+
 impl CosmogonyStats {
+    /// Count a zone whose admin level has no rule in its country, keeping its osm_id
+    /// as an example if there are not already `UNHANDLED_LEVEL_EXAMPLES` of them
+    pub fn add_unhandled_admin_level(&mut self, country: String, level: u32, osm_id: &str) {
+        *self
+            .unhandled_admin_level
+            .entry(country.clone())
+            .or_default()
+            .entry(level)
+            .or_insert(0) += 1;
+        let examples = self
+            .unhandled_admin_level_examples
+            .entry(country)
+            .or_default()
+            .entry(level)
+            .or_default();
+        if examples.len() < UNHANDLED_LEVEL_EXAMPLES {
+            examples.push(osm_id.to_string());
+        }
+    }
+
     pub fn compute(&mut self, zones: &[Zone]) {
+        let mut zones_by_wikidata: BTreeMap<&str, Vec<String>> = BTreeMap::new();
         for zone in zones {
             let type_ = zone
                 .zone_type
                 .map(|t| format!("{:?}", t))
                 .unwrap_or_else(|| "None".into());
+            *self
+                .zone_type_counts_by_country
+                .entry(zone.country_code.clone().unwrap_or_else(|| "??".into()))
+                .or_default()
+                .entry(type_.clone())
+                .or_insert(0) += 1;
             let count = self.zone_type_counts.entry(type_).or_insert(0);
             *count += 1;
             let level = zone.admin_level.unwrap_or(0);
             let count = self.level_counts.entry(level).or_insert(0);
             *count += 1;
-            if zone.wikidata.is_some() {
+            if zone.num_holes > MANY_HOLES_THRESHOLD {
+                warn!("{} has {} holes", zone.osm_id, zone.num_holes);
+                self.zones_with_many_holes
+                    .insert(zone.osm_id.clone(), zone.num_holes);
+            }
+            if let Some(ref wikidata) = zone.wikidata {
                 let wd_count = self.wikidata_counts.entry(level).or_insert(0);
                 *wd_count += 1;
+                zones_by_wikidata
+                    .entry(wikidata)
+                    .or_default()
+                    .push(zone.osm_id.clone());
             };
         }
+        self.duplicate_wikidata = zones_by_wikidata
+            .into_iter()
+            .filter(|(_, osm_ids)| osm_ids.len() > 1)
+            .map(|(wikidata, osm_ids)| {
+                warn!(
+                    "the wikidata id {} is shared by several zones: {}",
+                    wikidata,
+                    osm_ids.join(", ")
+                );
+                (wikidata.to_string(), osm_ids)
+            })
+            .collect();
     }
 }
 
@@ -56,7 +427,395 @@ impl fmt::Display for CosmogonyStats {
         for (zone_type, count) in &self.zone_type_counts {
             writeln!(f, "{:?}: {} element(s)", zone_type, count)?;
         }
+        writeln!(f, "{} boundaries normalized", self.normalized_boundaries)?;
+        writeln!(f, "{} degenerate boundaries", self.degenerate_boundaries)?;
+        for (country_code, count) in &self.boundary_build_failures {
+            writeln!(f, "{}: {} boundaries cannot be built", country_code, count)?;
+        }
+        writeln!(
+            f,
+            "{} zones with a center outside of their boundary",
+            self.center_outside_boundary.len()
+        )?;
+        for (osm_id, num_holes) in &self.zones_with_many_holes {
+            writeln!(f, "{} has {} holes", osm_id, num_holes)?;
+        }
+        for (country_code, levels) in &self.unhandled_admin_level {
+            for (level, count) in levels {
+                let examples = self
+                    .unhandled_admin_level_examples
+                    .get(country_code)
+                    .and_then(|e| e.get(level))
+                    .map_or_else(String::new, |e| format!(" (eg: {})", e.join(", ")));
+                writeln!(
+                    f,
+                    "{}: {} zones with the unhandled admin level {}{}",
+                    country_code, count, level, examples
+                )?;
+            }
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZoneType;
+
+    fn make_zone(idx: usize, parent: Option<usize>) -> Zone {
+        Zone {
+            id: ZoneIndex { index: idx },
+            osm_id: format!("relation:{}", idx),
+            parent: parent.map(|p| ZoneIndex { index: p }),
+            ..Default::default()
+        }
+    }
+
+    fn make_cosmogony(zones: Vec<Zone>) -> Cosmogony {
+        Cosmogony {
+            zones,
+            ..Default::default()
+        }
+    }
+
+    fn osm_ids<'a>(zones: &[&'a Zone]) -> Vec<&'a str> {
+        zones.iter().map(|z| z.osm_id.as_str()).collect()
+    }
+
+    #[test]
+    fn zone_type_counts_by_country() {
+        let zone = |zone_type, country_code: Option<&str>| Zone {
+            zone_type: Some(zone_type),
+            country_code: country_code.map(Into::into),
+            ..Default::default()
+        };
+        let mut stats = CosmogonyStats::default();
+        stats.compute(&[
+            zone(ZoneType::City, Some("FR")),
+            zone(ZoneType::City, Some("FR")),
+            zone(ZoneType::Country, Some("FR")),
+            zone(ZoneType::City, Some("LU")),
+            zone(ZoneType::City, None),
+        ]);
+
+        let counts = |country: &str| -> Vec<(&str, u64)> {
+            stats.zone_type_counts_by_country[country]
+                .iter()
+                .map(|(t, c)| (t.as_str(), *c))
+                .collect()
+        };
+        assert_eq!(counts("FR"), vec![("City", 2), ("Country", 1)]);
+        assert_eq!(counts("LU"), vec![("City", 1)]);
+        assert_eq!(counts("??"), vec![("City", 1)]);
+        assert_eq!(stats.zone_type_counts["City"], 4);
+    }
+
+    #[test]
+    fn from_zones() {
+        let zone = |idx, zone_type, admin_level| Zone {
+            zone_type: Some(zone_type),
+            admin_level: Some(admin_level),
+            wikidata: Some(format!("Q{}", idx)),
+            ..make_zone(idx, None)
+        };
+        let cosmogony = Cosmogony::from_zones(
+            vec![zone(0, ZoneType::Country, 2), zone(1, ZoneType::City, 8)],
+            "test.osm.pbf".into(),
+        );
+
+        assert_eq!(cosmogony.zones.len(), 2);
+        assert_eq!(cosmogony.meta.osm_filename, "test.osm.pbf");
+        assert_eq!(cosmogony.meta.schema_version, crate::SCHEMA_VERSION);
+        let stats = &cosmogony.meta.stats;
+        assert_eq!(stats.zone_type_counts["Country"], 1);
+        assert_eq!(stats.zone_type_counts["City"], 1);
+        assert_eq!(stats.level_counts[&2], 1);
+        assert_eq!(stats.level_counts[&8], 1);
+        assert_eq!(stats.wikidata_counts[&8], 1);
+        assert!(stats.duplicate_wikidata.is_empty());
+    }
+
+    #[test]
+    fn recompute_stats() {
+        let zone = |idx, zone_type, admin_level, parent| Zone {
+            zone_type: Some(zone_type),
+            admin_level: Some(admin_level),
+            wikidata: Some(format!("Q{}", idx)),
+            ..make_zone(idx, parent)
+        };
+        let mut cosmogony = Cosmogony::from_zones(
+            vec![
+                zone(0, ZoneType::Country, 2, None),
+                zone(1, ZoneType::City, 8, Some(0)),
+                zone(2, ZoneType::City, 8, Some(0)),
+            ],
+            "test.osm.pbf".into(),
+        );
+        cosmogony.meta.stats.excluded_zones = 3;
+        assert_eq!(cosmogony.meta.stats.zone_type_counts["City"], 2);
+
+        cosmogony.retain(|z| z.osm_id != "relation:2");
+        // the stats are stale until they are computed again
+        assert_eq!(cosmogony.meta.stats.zone_type_counts["City"], 2);
+        let stats = cosmogony.stats();
+        assert_eq!(stats.zone_type_counts["City"], 1);
+        assert_eq!(cosmogony.meta.stats.zone_type_counts["City"], 2);
+
+        cosmogony.recompute_stats();
+        let stats = &cosmogony.meta.stats;
+        assert_eq!(stats.zone_type_counts["Country"], 1);
+        assert_eq!(stats.zone_type_counts["City"], 1);
+        assert_eq!(stats.level_counts[&8], 1);
+        assert_eq!(stats.wikidata_counts[&8], 1);
+        assert_eq!(stats.zone_type_counts_by_country["??"]["City"], 1);
+        // the build's stats are kept
+        assert_eq!(stats.excluded_zones, 3);
+    }
+
+    #[test]
+    fn unhandled_admin_level_examples() {
+        let mut stats = CosmogonyStats::default();
+        for idx in 0..UNHANDLED_LEVEL_EXAMPLES + 2 {
+            stats.add_unhandled_admin_level("FR".into(), 11, &format!("relation:{}", idx));
+        }
+        stats.add_unhandled_admin_level("FR".into(), 12, "relation:42");
+
+        assert_eq!(
+            stats.unhandled_admin_level["FR"][&11],
+            UNHANDLED_LEVEL_EXAMPLES + 2
+        );
+        assert_eq!(stats.unhandled_admin_level["FR"][&12], 1);
+        let examples = &stats.unhandled_admin_level_examples["FR"];
+        assert_eq!(examples[&11].len(), UNHANDLED_LEVEL_EXAMPLES);
+        assert_eq!(examples[&11][0], "relation:0");
+        assert_eq!(examples[&12], vec!["relation:42"]);
+        assert!(stats
+            .to_string()
+            .contains("FR: 1 zones with the unhandled admin level 12 (eg: relation:42)"));
+
+        // the examples are not mandatory in the serialized stats
+        let mut json = serde_json::to_value(&stats).unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .remove("unhandled_admin_level_examples");
+        let stats: CosmogonyStats = serde_json::from_value(json).unwrap();
+        assert!(stats.unhandled_admin_level_examples.is_empty());
+    }
+
+    #[test]
+    fn zones_with_many_holes() {
+        let zone = |idx, num_holes| Zone {
+            num_holes,
+            ..make_zone(idx, None)
+        };
+        let mut stats = CosmogonyStats::default();
+        stats.compute(&[
+            zone(0, 0),
+            zone(1, MANY_HOLES_THRESHOLD),
+            zone(2, MANY_HOLES_THRESHOLD + 1),
+        ]);
+
+        assert_eq!(
+            stats.zones_with_many_holes.into_iter().collect::<Vec<_>>(),
+            vec![("relation:2".to_string(), MANY_HOLES_THRESHOLD + 1)]
+        );
+    }
+
+    #[test]
+    fn distance_between() {
+        let mut paris = make_zone(0, None);
+        paris.center = Some(geo_types::Point::new(2.3522, 48.8566));
+        let mut london = make_zone(1, None);
+        london.center = Some(geo_types::Point::new(-0.1276, 51.5072));
+        let cosmo = make_cosmogony(vec![paris, london, make_zone(2, None)]);
+        let distance =
+            |a, b| cosmo.distance_between(ZoneIndex { index: a }, ZoneIndex { index: b });
+
+        // Paris - London is about 344km
+        let d = distance(0, 1).unwrap();
+        assert!((340_000. ..350_000.).contains(&d), "{}", d);
+        assert_eq!(distance(1, 0), Some(d));
+        assert_eq!(distance(0, 0), Some(0.));
+        // no distance without center or zone
+        assert_eq!(distance(0, 2), None);
+        assert_eq!(distance(0, 42), None);
+    }
+
+    #[test]
+    fn ancestors() {
+        let cosmo = make_cosmogony(vec![
+            make_zone(0, None),
+            make_zone(1, Some(0)),
+            make_zone(2, Some(1)),
+            make_zone(3, Some(42)),
+        ]);
+        let ancestors = |idx| osm_ids(&cosmo.ancestors(ZoneIndex { index: idx }));
+
+        assert_eq!(ancestors(2), vec!["relation:1", "relation:0"]);
+        assert_eq!(ancestors(0), Vec::<&str>::new());
+        // the walk stops at a non existing parent
+        assert_eq!(ancestors(3), Vec::<&str>::new());
+        assert_eq!(ancestors(42), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn ancestors_ids_are_not_positions() {
+        // like after a build, some zones have been removed
+        let cosmo = make_cosmogony(vec![make_zone(3, None), make_zone(7, Some(3))]);
+        assert_eq!(
+            osm_ids(&cosmo.ancestors(ZoneIndex { index: 7 })),
+            vec!["relation:3"]
+        );
+    }
+
+    #[test]
+    fn ancestors_with_cycle() {
+        let cosmo = make_cosmogony(vec![
+            make_zone(0, Some(2)),
+            make_zone(1, Some(0)),
+            make_zone(2, Some(1)),
+        ]);
+        assert_eq!(
+            osm_ids(&cosmo.ancestors(ZoneIndex { index: 0 })),
+            vec!["relation:2", "relation:1"]
+        );
+    }
+
+    #[test]
+    fn wikidata_lookup() {
+        let mut zones = vec![
+            make_zone(0, None),
+            make_zone(1, Some(0)),
+            make_zone(2, None),
+        ];
+        zones[0].wikidata = Some("Q1".into());
+        zones[1].wikidata = Some("Q2".into());
+        zones[2].wikidata = Some("Q1".into());
+        let mut cosmo = make_cosmogony(zones);
+
+        assert_eq!(
+            cosmo.zone_by_wikidata("Q2").map(|z| z.osm_id.as_str()),
+            Some("relation:1")
+        );
+        assert!(cosmo.zone_by_wikidata("Q3").is_none());
+        assert_eq!(
+            cosmo.wikidata_index().get("Q2"),
+            Some(&ZoneIndex { index: 1 })
+        );
+
+        cosmo.meta.stats.compute(&cosmo.zones);
+        assert_eq!(
+            cosmo.meta.stats.duplicate_wikidata,
+            vec![(
+                "Q1".to_string(),
+                vec!["relation:0".to_string(), "relation:2".to_string()]
+            )]
+        );
+    }
+
+    #[test]
+    fn valid_hierarchy() {
+        let cosmo = make_cosmogony(vec![
+            make_zone(0, None),
+            make_zone(1, Some(0)),
+            make_zone(2, Some(1)),
+        ]);
+        assert!(cosmo.validate_hierarchy().is_ok());
+    }
+
+    #[test]
+    fn hierarchy_with_non_existing_parent() {
+        let cosmo = make_cosmogony(vec![make_zone(0, None), make_zone(1, Some(42))]);
+        let err = cosmo.validate_hierarchy().unwrap_err().to_string();
+        assert!(err.contains("non existing parent: relation:1"), "{}", err);
+    }
+
+    #[test]
+    fn hierarchy_with_cycle() {
+        let cosmo = make_cosmogony(vec![
+            make_zone(0, Some(2)),
+            make_zone(1, Some(0)),
+            make_zone(2, Some(1)),
+            make_zone(3, Some(2)),
+        ]);
+        let err = cosmo.validate_hierarchy().unwrap_err().to_string();
+        assert!(
+            err.contains("3 zone(s) in a cyclic hierarchy: relation:0, relation:1, relation:2"),
+            "{}",
+            err
+        );
+    }
+
+    // the (osm_id, parent's osm_id) of the zones, checking that the ids are the positions
+    fn hierarchy(cosmogony: &Cosmogony) -> Vec<(&str, Option<&str>)> {
+        cosmogony
+            .zones
+            .iter()
+            .enumerate()
+            .map(|(pos, z)| {
+                assert_eq!(z.id.index, pos);
+                let parent = z.parent.map(|p| cosmogony.zones[p.index].osm_id.as_str());
+                (z.osm_id.as_str(), parent)
+            })
+            .collect()
+    }
+
+    // 0 <- 1 <- 2 <- 3
+    //        <- 4
+    fn make_tree() -> Cosmogony {
+        make_cosmogony(vec![
+            make_zone(0, None),
+            make_zone(1, Some(0)),
+            make_zone(2, Some(1)),
+            make_zone(3, Some(2)),
+            make_zone(4, Some(1)),
+        ])
+    }
+
+    #[test]
+    fn retain_drop_middle_layer() {
+        let mut cosmogony = make_tree();
+        cosmogony.retain(|z| z.osm_id != "relation:1");
+        assert_eq!(
+            hierarchy(&cosmogony),
+            vec![
+                ("relation:0", None),
+                ("relation:2", Some("relation:0")),
+                ("relation:3", Some("relation:2")),
+                ("relation:4", Some("relation:0")),
+            ]
+        );
+        assert!(cosmogony.validate_hierarchy().is_ok());
+    }
+
+    #[test]
+    fn retain_drop_leaves() {
+        let mut cosmogony = make_tree();
+        cosmogony.retain(|z| z.osm_id != "relation:3" && z.osm_id != "relation:4");
+        assert_eq!(
+            hierarchy(&cosmogony),
+            vec![
+                ("relation:0", None),
+                ("relation:1", Some("relation:0")),
+                ("relation:2", Some("relation:1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn retain_drop_root() {
+        let mut cosmogony = make_tree();
+        cosmogony.retain(|z| z.osm_id != "relation:0");
+        assert_eq!(
+            hierarchy(&cosmogony),
+            vec![
+                ("relation:1", None),
+                ("relation:2", Some("relation:1")),
+                ("relation:3", Some("relation:2")),
+                ("relation:4", Some("relation:1")),
+            ]
+        );
+    }
+}
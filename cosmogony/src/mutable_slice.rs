@@ -22,6 +22,15 @@ impl<'a> MutableSlice<'a> {
         (s, &mut z[0])
     }
 
+    /// number of zones in the slice, including the middle one
+    pub fn len(&self) -> usize {
+        self.left.len() + self.right.len() + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
     pub fn get(&self, zindex: &ZoneIndex) -> &Zone {
         let idx = zindex.index;
         match idx {
@@ -0,0 +1,118 @@
+use crate::model::Cosmogony;
+use crate::zone::Zone;
+use std::collections::BTreeMap;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Normalize a name for the lookups: lowercased, without accents, and with the
+/// punctuation replaced by spaces (eg: `Esch-sur-Alzette` gives `esch sur alzette`)
+pub fn normalize_name(name: &str) -> String {
+    let stripped: String = name
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Index of the zones of a cosmogony by their normalized name
+///
+/// The index borrows the cosmogony, so the zones cannot be changed while it is used
+/// (build it again after a change).
+#[derive(Debug, Clone)]
+pub struct NameIndex<'a> {
+    zones: &'a [Zone],
+    positions: BTreeMap<String, Vec<usize>>,
+}
+
+impl<'a> NameIndex<'a> {
+    pub fn build(cosmogony: &'a Cosmogony) -> Self {
+        Self::from_zones(&cosmogony.zones)
+    }
+
+    fn from_zones(zones: &'a [Zone]) -> Self {
+        let mut positions: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (pos, z) in zones.iter().enumerate() {
+            positions
+                .entry(normalize_name(&z.name))
+                .or_default()
+                .push(pos);
+        }
+        Self { zones, positions }
+    }
+
+    /// Find the zones with a given name
+    ///
+    /// The names are normalized (lowercased, without accents nor punctuation, see
+    /// [`normalize_name`]), so `esch sur alzette` finds `Esch-sur-Alzette`.
+    pub fn find(&self, name: &str) -> Vec<&'a Zone> {
+        self.positions
+            .get(&normalize_name(name))
+            .into_iter()
+            .flatten()
+            .map(|pos| &self.zones[*pos])
+            .collect()
+    }
+
+    /// Find the zones whose normalized name starts with a given prefix
+    ///
+    /// The zones are sorted by normalized name
+    pub fn find_by_prefix(&self, prefix: &str) -> Vec<&'a Zone> {
+        let prefix = normalize_name(prefix);
+        self.positions
+            .range(prefix.clone()..)
+            .take_while(|(name, _)| name.starts_with(&prefix))
+            .flat_map(|(_, positions)| positions)
+            .map(|pos| &self.zones[*pos])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_zone(name: &str) -> Zone {
+        Zone {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    fn names<'a>(zones: &[&'a Zone]) -> Vec<&'a str> {
+        zones.iter().map(|z| z.name.as_str()).collect()
+    }
+
+    #[test]
+    fn normalize_names() {
+        assert_eq!(normalize_name("Esch-sur-Alzette"), "esch sur alzette");
+        assert_eq!(normalize_name("  Saint-Étienne "), "saint etienne");
+        assert_eq!(normalize_name("Zürich"), "zurich");
+        assert_eq!(normalize_name("L'Haÿ-les-Roses"), "l hay les roses");
+    }
+
+    #[test]
+    fn find_zones() {
+        let zones = vec![
+            make_zone("Esch-sur-Alzette"),
+            make_zone("Esch-sur-Sûre"),
+            make_zone("Eschweiler"),
+            make_zone("esch sur alzette"),
+            make_zone("Luxembourg"),
+        ];
+        let index = NameIndex::from_zones(&zones);
+
+        assert_eq!(
+            names(&index.find("ESCH SUR ALZETTE")),
+            vec!["Esch-sur-Alzette", "esch sur alzette"]
+        );
+        assert!(index.find("esch").is_empty());
+        assert_eq!(
+            names(&index.find_by_prefix("Esch-sur")),
+            vec!["Esch-sur-Alzette", "esch sur alzette", "Esch-sur-Sûre"]
+        );
+        assert_eq!(index.find_by_prefix("esch").len(), 4);
+        assert!(index.find_by_prefix("paris").is_empty());
+    }
+}
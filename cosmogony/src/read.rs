@@ -1,35 +1,282 @@
-use crate::file_format::OutputFormat;
-use crate::{Cosmogony, Zone};
+use crate::file_format::{parse_schema_marker, OutputFormat};
+use crate::{Cosmogony, CosmogonyMetadata, Zone};
 use anyhow::{anyhow, Error};
+use std::fmt;
+use std::io::Read;
 use std::path::Path;
 
-// Stream Cosmogony's Zone from a Reader
+/// Options of the loading of a cosmogony
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Skip the malformed lines of a json stream (they are reported) instead of failing
+    /// on the first one. A stream that cannot be read anymore (like a truncated gzip file)
+    /// is loaded up to its last readable line.
+    pub skip_malformed_lines: bool,
+    /// Read the json stream as a sequence of json values, whatever their newlines, instead of
+    /// one zone per line. It is slower, but the pretty-printed zones spanning several lines
+    /// can be read. Once a record is not valid json, the rest of the stream cannot be read.
+    pub multiline_records: bool,
+}
+
+/// A line of a json stream that cannot be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedLine {
+    /// number of the line in the stream, starting at 1
+    pub line_number: usize,
+    /// the reading error
+    pub error: String,
+}
+
+impl fmt::Display for MalformedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line_number, self.error)
+    }
+}
+
+// the beginning of a line, to give some context in the errors
+fn snippet(line: &str) -> String {
+    const MAX_CHARS: usize = 80;
+    match line.char_indices().nth(MAX_CHARS) {
+        Some((pos, _)) => format!("{}...", &line[..pos]),
+        None => line.to_string(),
+    }
+}
+
+fn parse_zone(line_number: usize, line: &str) -> Result<Zone, MalformedLine> {
+    serde_json::from_str(line).map_err(|err| MalformedLine {
+        line_number,
+        error: format!("{} (the line starts with '{}')", err, snippet(line)),
+    })
+}
+
+// Stream Cosmogony's Zone from a Reader, the first line of the reader being `first_line_number`
+// (the schema version marker is skipped)
 fn read_zones(
     reader: impl std::io::BufRead,
-) -> impl std::iter::Iterator<Item = Result<Zone, Error>> {
+    first_line_number: usize,
+) -> impl std::iter::Iterator<Item = Result<Zone, MalformedLine>> {
+    let mut unreadable = false;
     reader
         .lines()
-        .map(|l| l.map_err(|err| err.into()))
-        .map(|l| l.and_then(|l| serde_json::from_str(&l).map_err(|err| anyhow!("{}", err))))
+        .enumerate()
+        .map_while(move |(pos, l)| {
+            // once the reader has failed (eg: on a truncated gzip file), nothing more can be read
+            if unreadable {
+                return None;
+            }
+            let line_number = first_line_number + pos;
+            match l {
+                Ok(l) if parse_schema_marker(&l).is_some() => Some(None),
+                Ok(l) => Some(Some(parse_zone(line_number, &l))),
+                Err(err) => {
+                    unreadable = true;
+                    Some(Some(Err(MalformedLine {
+                        line_number,
+                        error: err.to_string(),
+                    })))
+                }
+            }
+        })
+        .flatten()
+}
+
+// A reader counting the newlines read
+struct LineCounter<R> {
+    reader: R,
+    lines: usize,
 }
 
-fn from_json_stream(reader: impl std::io::BufRead) -> Result<Cosmogony, Error> {
-    let zones = read_zones(reader).collect::<Result<_, _>>()?;
+impl<R: Read> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let nb_read = self.reader.read(buf)?;
+        self.lines += buf[..nb_read].iter().filter(|b| **b == b'\n').count();
+        Ok(nb_read)
+    }
+}
+
+// Skip the json whitespaces, returning the number of newlines skipped
+// and whether there is something left to read
+fn skip_whitespaces(reader: &mut impl std::io::BufRead) -> std::io::Result<(usize, bool)> {
+    let mut nb_lines = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok((nb_lines, false));
+        }
+        let nb_whitespaces = buf
+            .iter()
+            .take_while(|b| matches!(b, b' ' | b'\n' | b'\r' | b'\t'))
+            .count();
+        nb_lines += buf[..nb_whitespaces]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count();
+        let remaining = nb_whitespaces < buf.len();
+        reader.consume(nb_whitespaces);
+        if remaining {
+            return Ok((nb_lines, true));
+        }
+    }
+}
 
-    Ok(Cosmogony {
-        zones,
-        ..Default::default()
+// Stream Cosmogony's Zone from a Reader as a sequence of json values, whatever their newlines
+// (the schema version markers are skipped)
+// The line number of a malformed zone is the line where it starts.
+fn read_multiline_zones(
+    mut reader: impl std::io::BufRead,
+    first_line_number: usize,
+) -> impl std::iter::Iterator<Item = Result<Zone, MalformedLine>> {
+    let mut line_number = first_line_number;
+    // the values cannot be delimited after an error, so nothing more is read
+    let mut unreadable = false;
+    std::iter::from_fn(move || {
+        if unreadable {
+            return None;
+        }
+        match skip_whitespaces(&mut reader) {
+            Ok((nb_lines, true)) => line_number += nb_lines,
+            Ok((_, false)) => return None,
+            Err(err) => {
+                unreadable = true;
+                return Some(Some(Err(MalformedLine {
+                    line_number,
+                    error: err.to_string(),
+                })));
+            }
+        }
+        // the value is read up to its last byte, the following newlines are left in the reader
+        let mut counter = LineCounter {
+            reader: &mut reader,
+            lines: 0,
+        };
+        let value: Result<serde_json::Value, _> = serde::Deserialize::deserialize(
+            &mut serde_json::Deserializer::from_reader(&mut counter),
+        );
+        let value_line_number = line_number;
+        line_number += counter.lines;
+        match value {
+            Ok(value) if value.get("_cosmogony_schema").is_some() => Some(None),
+            Ok(value) => Some(Some(serde_json::from_value::<Zone>(value).map_err(|err| {
+                MalformedLine {
+                    line_number: value_line_number,
+                    error: err.to_string(),
+                }
+            }))),
+            Err(err) => {
+                unreadable = true;
+                Some(Some(Err(MalformedLine {
+                    line_number: value_line_number + err.line().max(1) - 1,
+                    error: err.to_string(),
+                })))
+            }
+        }
     })
+    .flatten()
+}
+
+// Collect the zones, skipping the malformed ones if asked to
+fn collect_zones(
+    zones: impl std::iter::Iterator<Item = Result<Zone, MalformedLine>>,
+    options: &LoadOptions,
+) -> Result<(Vec<Zone>, Vec<MalformedLine>), Error> {
+    let mut valid_zones = vec![];
+    let mut malformed_lines = vec![];
+    for zone in zones {
+        match zone {
+            Ok(zone) => valid_zones.push(zone),
+            Err(malformed_line) if options.skip_malformed_lines => {
+                malformed_lines.push(malformed_line)
+            }
+            Err(malformed_line) => return Err(anyhow!("{}", malformed_line)),
+        }
+    }
+    Ok((valid_zones, malformed_lines))
+}
+
+fn from_json_stream(
+    mut reader: impl std::io::BufRead,
+    options: &LoadOptions,
+) -> Result<(Cosmogony, Vec<MalformedLine>), Error> {
+    // the schema version is on the first line, if there is no marker it's an old file (version 0)
+    let mut first_line = String::new();
+    if let Err(err) = reader.read_line(&mut first_line) {
+        if !options.skip_malformed_lines {
+            return Err(anyhow!("line 1: {}", err));
+        }
+        let malformed_line = MalformedLine {
+            line_number: 1,
+            error: err.to_string(),
+        };
+        return Ok((Cosmogony::default(), vec![malformed_line]));
+    }
+    let schema_version = parse_schema_marker(first_line.trim_end());
+    let (zones, malformed_lines) = if options.multiline_records {
+        // the first line may be the beginning of a zone
+        let (first_line, first_line_number) = match schema_version {
+            Some(_) => (String::new(), 2),
+            None => (first_line, 1),
+        };
+        let reader = std::io::Cursor::new(first_line).chain(reader);
+        collect_zones(read_multiline_zones(reader, first_line_number), options)?
+    } else {
+        let first_zone = match schema_version {
+            Some(_) => None,
+            None if first_line.trim().is_empty() => None,
+            None => Some(parse_zone(1, first_line.trim_end())),
+        };
+        collect_zones(first_zone.into_iter().chain(read_zones(reader, 2)), options)?
+    };
+
+    Ok((
+        Cosmogony {
+            zones,
+            meta: CosmogonyMetadata {
+                schema_version: schema_version.unwrap_or(0),
+                ..Default::default()
+            },
+        },
+        malformed_lines,
+    ))
 }
 
 /// Load a cosmogony from a file
+///
+/// The zones' hierarchy is validated, an error is returned if it is inconsistent
 pub fn load_cosmogony_from_file(input: impl AsRef<Path>) -> Result<Cosmogony, Error> {
     let format = OutputFormat::from_filename(input.as_ref())?;
     let f = std::fs::File::open(&input)?;
-    let f = std::io::BufReader::new(f);
     load_cosmogony(f, format)
 }
 
+/// Load a cosmogony from a reader, in the given format
+///
+/// The zones' hierarchy is validated, an error is returned if it is inconsistent
+pub fn load_cosmogony(
+    reader: impl std::io::Read,
+    format: OutputFormat,
+) -> Result<Cosmogony, Error> {
+    load_cosmogony_with_options(reader, format, &LoadOptions::default())
+        .map(|(cosmogony, _)| cosmogony)
+}
+
+/// Load a cosmogony from a reader, in the given format, with some [`LoadOptions`]
+///
+/// The malformed lines skipped (if `options.skip_malformed_lines` is true) are returned
+/// with the cosmogony. The zones' hierarchy is validated, unless some lines have been
+/// skipped (the parents of some zones may be missing then).
+pub fn load_cosmogony_with_options(
+    reader: impl std::io::Read,
+    format: OutputFormat,
+    options: &LoadOptions,
+) -> Result<(Cosmogony, Vec<MalformedLine>), Error> {
+    let (cosmogony, malformed_lines) =
+        parse_cosmogony(std::io::BufReader::new(reader), format, options)?;
+    if malformed_lines.is_empty() {
+        cosmogony.validate_hierarchy()?;
+    }
+    Ok((cosmogony, malformed_lines))
+}
+
 /// Return an iterator on the zones
 /// if the input file is a jsonstream, the zones are streamed
 /// if the input file is a json, the whole cosmogony is loaded
@@ -38,34 +285,320 @@ pub fn read_zones_from_file(
 ) -> Result<Box<dyn Iterator<Item = Result<Zone, Error>> + Send + Sync>, Error> {
     let format = OutputFormat::from_filename(input.as_ref())?;
     let f = std::fs::File::open(input.as_ref())?;
-    let f = std::io::BufReader::new(f);
+    read_zones_from_reader(f, format)
+}
+
+/// Return an iterator on the zones of a reader, in the given format
+/// if the format is a jsonstream, the zones are streamed
+/// if the format is a json, the whole cosmogony is loaded
+pub fn read_zones_from_reader(
+    reader: impl std::io::Read + Send + Sync + 'static,
+    format: OutputFormat,
+) -> Result<Box<dyn Iterator<Item = Result<Zone, Error>> + Send + Sync>, Error> {
+    read_zones_from_reader_with_options(reader, format, &LoadOptions::default())
+}
+
+/// Return an iterator on the zones of a reader, in the given format, with some [`LoadOptions`]
+///
+/// Only `options.multiline_records` is used, the malformed zones are returned as errors.
+pub fn read_zones_from_reader_with_options(
+    reader: impl std::io::Read + Send + Sync + 'static,
+    format: OutputFormat,
+    options: &LoadOptions,
+) -> Result<Box<dyn Iterator<Item = Result<Zone, Error>> + Send + Sync>, Error> {
+    let reader = std::io::BufReader::new(reader);
+    let multiline = options.multiline_records;
     match format {
-        OutputFormat::JsonGz | OutputFormat::Json => {
-            let cosmo = load_cosmogony(f, format)?;
+        OutputFormat::JsonGz | OutputFormat::Json | OutputFormat::JsonZst => {
+            let (cosmo, _) = parse_cosmogony(reader, format, &LoadOptions::default())?;
             Ok(Box::new(cosmo.zones.into_iter().map(Ok)))
         }
-        OutputFormat::JsonStream => Ok(Box::new(read_zones(f))),
+        OutputFormat::JsonStream => Ok(stream_zones(reader, multiline)),
         OutputFormat::JsonStreamGz => {
-            let r = flate2::bufread::GzDecoder::new(f);
+            // the appended merges are made of several gzip members
+            let r = flate2::bufread::MultiGzDecoder::new(reader);
             let r = std::io::BufReader::new(r);
-            Ok(Box::new(read_zones(r)))
+            Ok(stream_zones(r, multiline))
+        }
+        OutputFormat::JsonStreamZst => {
+            // the appended merges are made of several zstd frames, all read by the decoder
+            let r = zstd::stream::read::Decoder::with_buffer(reader)?;
+            let r = std::io::BufReader::new(SyncReader(std::sync::Mutex::new(r)));
+            Ok(stream_zones(r, multiline))
         }
+        OutputFormat::FlatGeobuf => Err(anyhow!("reading a flatgeobuf file is not supported")),
+        OutputFormat::GeoJsonSeq => Err(anyhow!("reading a geojson file is not supported")),
+        OutputFormat::GeoPackage => Err(anyhow!("reading a geopackage file is not supported")),
     }
 }
 
-// Load a cosmogony from a reader and a file_format
-fn load_cosmogony(reader: impl std::io::BufRead, format: OutputFormat) -> Result<Cosmogony, Error> {
+// A reader which is Sync as long as the wrapped one is Send (like the zstd decoder),
+// the zones' iterators being Sync
+struct SyncReader<R>(std::sync::Mutex<R>);
+
+impl<R: Read> Read for SyncReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // the mutex is never locked, so it cannot be poisoned
+        self.0
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .read(buf)
+    }
+}
+
+// Stream the zones of a json stream, line by line or as a sequence of json values
+fn stream_zones(
+    reader: impl std::io::BufRead + Send + Sync + 'static,
+    multiline: bool,
+) -> Box<dyn Iterator<Item = Result<Zone, Error>> + Send + Sync> {
+    if multiline {
+        Box::new(read_multiline_zones(reader, 1).map(|z| z.map_err(|m| anyhow!("{}", m))))
+    } else {
+        Box::new(read_zones(reader, 1).map(|z| z.map_err(|m| anyhow!("{}", m))))
+    }
+}
+
+// Parse a cosmogony from a reader and a file_format, with the skipped malformed lines
+// (the options only apply to the json streams)
+fn parse_cosmogony(
+    reader: impl std::io::BufRead,
+    format: OutputFormat,
+    options: &LoadOptions,
+) -> Result<(Cosmogony, Vec<MalformedLine>), Error> {
     match format {
         OutputFormat::JsonGz => {
             let r = flate2::read::GzDecoder::new(reader);
-            serde_json::from_reader(r).map_err(|err| anyhow!("{}", err))
+            serde_json::from_reader(r)
+                .map(|c| (c, vec![]))
+                .map_err(|err| anyhow!("{}", err))
         }
-        OutputFormat::Json => serde_json::from_reader(reader).map_err(|err| anyhow!("{}", err)),
-        OutputFormat::JsonStream => from_json_stream(reader),
+        OutputFormat::Json => serde_json::from_reader(reader)
+            .map(|c| (c, vec![]))
+            .map_err(|err| anyhow!("{}", err)),
+        OutputFormat::JsonZst => {
+            let r = zstd::stream::read::Decoder::with_buffer(reader)?;
+            serde_json::from_reader(r)
+                .map(|c| (c, vec![]))
+                .map_err(|err| anyhow!("{}", err))
+        }
+        OutputFormat::JsonStream => from_json_stream(reader, options),
         OutputFormat::JsonStreamGz => {
-            let r = flate2::bufread::GzDecoder::new(reader);
+            let r = flate2::bufread::MultiGzDecoder::new(reader);
+            let r = std::io::BufReader::new(r);
+            from_json_stream(r, options)
+        }
+        OutputFormat::JsonStreamZst => {
+            let r = zstd::stream::read::Decoder::with_buffer(reader)?;
             let r = std::io::BufReader::new(r);
-            from_json_stream(r)
+            from_json_stream(r, options)
         }
+        OutputFormat::FlatGeobuf => Err(anyhow!("reading a flatgeobuf file is not supported")),
+        OutputFormat::GeoJsonSeq => Err(anyhow!("reading a geojson file is not supported")),
+        OutputFormat::GeoPackage => Err(anyhow!("reading a geopackage file is not supported")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_format::write_schema_marker;
+    use crate::ZoneIndex;
+    use std::io::Cursor;
+
+    fn json_stream() -> Vec<u8> {
+        let mut buf = vec![];
+        write_schema_marker(&mut buf).unwrap();
+        for (idx, parent) in [(0, None), (1, Some(0))] {
+            let zone = Zone {
+                id: ZoneIndex { index: idx },
+                osm_id: format!("relation:{}", idx),
+                parent: parent.map(|index| ZoneIndex { index }),
+                ..Default::default()
+            };
+            serde_json::to_writer(&mut buf, &zone).unwrap();
+            buf.push(b'\n');
+        }
+        buf
+    }
+
+    #[test]
+    fn load_cosmogony_from_reader() {
+        let cosmo = load_cosmogony(Cursor::new(json_stream()), OutputFormat::JsonStream).unwrap();
+        assert_eq!(cosmo.zones.len(), 2);
+        assert_eq!(cosmo.meta.schema_version, crate::SCHEMA_VERSION);
+        assert_eq!(cosmo.zones[1].parent, Some(ZoneIndex { index: 0 }));
+
+        // the json format is also supported
+        let json = serde_json::to_vec(&cosmo).unwrap();
+        let cosmo_from_json = load_cosmogony(json.as_slice(), OutputFormat::Json).unwrap();
+        assert_eq!(cosmo_from_json, cosmo);
+    }
+
+    #[test]
+    fn read_zones_from_a_reader() {
+        let zones: Vec<_> =
+            read_zones_from_reader(Cursor::new(json_stream()), OutputFormat::JsonStream)
+                .unwrap()
+                .map(|z| z.unwrap().osm_id)
+                .collect();
+        assert_eq!(zones, vec!["relation:0", "relation:1"]);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let mut stream = json_stream();
+        stream.extend(b"{\"id\": 2, \"osm_id\": \"relation:2\", \"na\n");
+
+        // the error gives the line number and the beginning of the line
+        let err = load_cosmogony(Cursor::new(stream.clone()), OutputFormat::JsonStream)
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("line 4: "), "{}", err);
+        assert!(err.contains("'{\"id\": 2, \"osm_id\": \"relation:2\", \"na'"));
+        let err = read_zones_from_reader(Cursor::new(stream.clone()), OutputFormat::JsonStream)
+            .unwrap()
+            .find_map(|z| z.err())
+            .unwrap();
+        assert!(err.to_string().starts_with("line 4: "));
+
+        // the malformed lines can be skipped
+        let options = LoadOptions {
+            skip_malformed_lines: true,
+            ..Default::default()
+        };
+        let (cosmo, malformed_lines) =
+            load_cosmogony_with_options(Cursor::new(stream), OutputFormat::JsonStream, &options)
+                .unwrap();
+        assert_eq!(cosmo.zones.len(), 2);
+        assert_eq!(malformed_lines.len(), 1);
+        assert_eq!(malformed_lines[0].line_number, 4);
+    }
+
+    #[test]
+    fn truncated_gzip_stream() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&json_stream()).unwrap();
+        let mut gz = encoder.finish().unwrap();
+        // the end of the gzip trailer is missing
+        gz.truncate(gz.len() - 4);
+
+        assert!(load_cosmogony(Cursor::new(gz.clone()), OutputFormat::JsonStreamGz).is_err());
+
+        let options = LoadOptions {
+            skip_malformed_lines: true,
+            ..Default::default()
+        };
+        let (cosmo, malformed_lines) =
+            load_cosmogony_with_options(Cursor::new(gz), OutputFormat::JsonStreamGz, &options)
+                .unwrap();
+        assert_eq!(cosmo.zones.len(), 2);
+        assert_eq!(malformed_lines.len(), 1);
+        assert_eq!(malformed_lines[0].line_number, 4);
+    }
+
+    #[test]
+    fn zstd_stream() {
+        let stream = json_stream();
+        let zst = zstd::encode_all(&stream[..], 0).unwrap();
+        let cosmo = load_cosmogony(Cursor::new(zst), OutputFormat::JsonStreamZst).unwrap();
+        assert_eq!(cosmo.zones.len(), 2);
+
+        // an appended stream is made of several zstd frames
+        let (head, tail) = stream.split_at(stream.iter().position(|b| *b == b'\n').unwrap() + 1);
+        let mut zst = zstd::encode_all(head, 0).unwrap();
+        zst.extend(zstd::encode_all(tail, 0).unwrap());
+        let zones: Vec<Zone> =
+            read_zones_from_reader(Cursor::new(zst), OutputFormat::JsonStreamZst)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(zones.len(), 2);
+    }
+
+    #[test]
+    fn multiline_records() {
+        let mut stream = json_stream();
+        let zone = Zone {
+            id: ZoneIndex { index: 2 },
+            osm_id: "relation:2".into(),
+            parent: Some(ZoneIndex { index: 0 }),
+            ..Default::default()
+        };
+        serde_json::to_writer_pretty(&mut stream, &zone).unwrap();
+        stream.extend(b"\n");
+        serde_json::to_writer(
+            &mut stream,
+            &Zone {
+                id: ZoneIndex { index: 3 },
+                osm_id: "relation:3".into(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        stream.extend(b"\n");
+
+        // the pretty-printed zone cannot be read line by line
+        assert!(load_cosmogony(Cursor::new(stream.clone()), OutputFormat::JsonStream).is_err());
+
+        let options = LoadOptions {
+            multiline_records: true,
+            ..Default::default()
+        };
+        let (cosmo, malformed_lines) = load_cosmogony_with_options(
+            Cursor::new(stream.clone()),
+            OutputFormat::JsonStream,
+            &options,
+        )
+        .unwrap();
+        assert!(malformed_lines.is_empty());
+        assert_eq!(cosmo.meta.schema_version, crate::SCHEMA_VERSION);
+        let osm_ids: Vec<_> = cosmo.zones.iter().map(|z| z.osm_id.as_str()).collect();
+        assert_eq!(
+            osm_ids,
+            vec!["relation:0", "relation:1", "relation:2", "relation:3"]
+        );
+        assert_eq!(cosmo.zones[2], zone);
+
+        let zones: Vec<Zone> = read_zones_from_reader_with_options(
+            Cursor::new(stream.clone()),
+            OutputFormat::JsonStream,
+            &options,
+        )
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(zones, cosmo.zones);
+
+        // the zones can also be on a single line, without any marker
+        let zones_line: Vec<u8> = cosmo
+            .zones
+            .iter()
+            .flat_map(|z| serde_json::to_vec(z).unwrap())
+            .collect();
+        let (cosmo_line, _) = load_cosmogony_with_options(
+            Cursor::new(zones_line),
+            OutputFormat::JsonStream,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(cosmo_line.zones, cosmo.zones);
+        assert_eq!(cosmo_line.meta.schema_version, 0);
+
+        // a zone with a wrong type is reported with the line where it starts
+        stream.extend(b"{\n  \"id\": \"four\"\n}\n");
+        let options = LoadOptions {
+            multiline_records: true,
+            skip_malformed_lines: true,
+        };
+        let (cosmo, malformed_lines) =
+            load_cosmogony_with_options(Cursor::new(stream), OutputFormat::JsonStream, &options)
+                .unwrap();
+        assert_eq!(cosmo.zones.len(), 4);
+        assert_eq!(malformed_lines.len(), 1);
+        let nb_lines = 3 + serde_json::to_string_pretty(&zone).unwrap().lines().count() + 1;
+        assert_eq!(malformed_lines[0].line_number, nb_lines + 1);
     }
 }
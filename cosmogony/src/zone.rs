@@ -1,4 +1,5 @@
 use crate::mutable_slice::MutableSlice;
+use geo::Intersects;
 use geo_types::{Geometry, MultiPolygon, Point, Rect};
 use log::warn;
 use osmpbfreader::objects::Tags;
@@ -6,12 +7,16 @@ use serde::Serialize;
 use serde_derive::*;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::str::FromStr;
 
 pub type Coord = Point<f64>;
 
 #[derive(Serialize, Deserialize, Copy, Debug, Clone, Eq, Hash, PartialEq, Ord, PartialOrd)]
 #[serde(rename_all = "snake_case")]
 pub enum ZoneType {
+    /// a subdivision of a suburb (osm `admin_level` 11 and more in some countries)
+    #[serde(alias = "neighborhood")]
+    Neighbourhood,
     Suburb,
     CityDistrict,
     City,
@@ -20,11 +25,40 @@ pub enum ZoneType {
     CountryRegion,
     Country,
     NonAdministrative,
+    /// a maritime zone, like an exclusive economic zone (osm `boundary=maritime`)
+    ///
+    /// They are only built with the `--include-maritime` option. They are not in the hierarchy
+    /// of the land zones (they have no parent and are the parent of no zone),
+    /// and they can overlap the land zones.
+    Maritime,
 }
 
 impl ZoneType {
+    /// The rank of the type in the zones' hierarchy, from the smallest zones (the neighbourhoods)
+    /// to the largest ones (the countries): a zone can only be the child of a zone
+    /// with a higher rank.
+    ///
+    /// The hierarchy relies on this rank rather than on the order of the variants.
+    /// The non administrative and the maritime zones are not in the hierarchy,
+    /// they have the highest ranks.
+    pub fn rank(&self) -> u8 {
+        match *self {
+            ZoneType::Neighbourhood => 0,
+            ZoneType::Suburb => 1,
+            ZoneType::CityDistrict => 2,
+            ZoneType::City => 3,
+            ZoneType::StateDistrict => 4,
+            ZoneType::State => 5,
+            ZoneType::CountryRegion => 6,
+            ZoneType::Country => 7,
+            ZoneType::NonAdministrative => u8::MAX - 1,
+            ZoneType::Maritime => u8::MAX,
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match *self {
+            ZoneType::Neighbourhood => "neighbourhood",
             ZoneType::Suburb => "suburb",
             ZoneType::CityDistrict => "city_district",
             ZoneType::City => "city",
@@ -33,9 +67,14 @@ impl ZoneType {
             ZoneType::CountryRegion => "country_region",
             ZoneType::Country => "country",
             ZoneType::NonAdministrative => "non_administrative",
+            ZoneType::Maritime => "maritime",
         }
     }
 
+    /// Parse an osm `place` value (like `town`) or a zone type name
+    ///
+    /// The `neighbourhood` places are considered as suburbs (the voronoi built for those places
+    /// give them the `Neighbourhood` type), use [`FromStr`] to parse the zone type names only.
     pub fn parse(s: &str) -> Option<Self> {
         Some(match s {
             "suburb" | "quarter" | "neighbourhood" => Self::Suburb,
@@ -46,21 +85,93 @@ impl ZoneType {
             "country_region" => Self::CountryRegion,
             "country" => Self::Country,
             "non_administrative" => Self::NonAdministrative,
+            "maritime" => Self::Maritime,
             _ => return None,
         })
     }
 }
 
+impl fmt::Display for ZoneType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parse the snake_case names given by `as_str` (eg: `city_district`)
+///
+/// Unlike [`ZoneType::parse`], the osm `place` values (like `town`) are not accepted.
+impl FromStr for ZoneType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "neighbourhood" => Self::Neighbourhood,
+            "suburb" => Self::Suburb,
+            "city_district" => Self::CityDistrict,
+            "city" => Self::City,
+            "state_district" => Self::StateDistrict,
+            "state" => Self::State,
+            "country_region" => Self::CountryRegion,
+            "country" => Self::Country,
+            "non_administrative" => Self::NonAdministrative,
+            "maritime" => Self::Maritime,
+            _ => return Err(anyhow::anyhow!("unknown zone type '{}'", s)),
+        })
+    }
+}
+
+/// How the `admin_level` of a zone has been determined
+#[derive(Serialize, Deserialize, Copy, Debug, Clone, Eq, Hash, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminLevelSource {
+    /// the `admin_level` tag of the relation
+    RelationTag,
+    /// the `admin_level` tag of the relation's label node,
+    /// used when the relation has no valid `admin_level`
+    /// (see the `--admin-level-from-label-node` option)
+    LabelNode,
+    /// a default value, used instead of an `admin_level` out of range
+    /// (see the `--clamp-raw-admin-level` option)
+    Defaulted,
+    /// inferred from the other tags of a relation without `admin_level`
+    /// (see the `--infer-missing-admin-level` option)
+    Inferred,
+}
+
+/// How the boundary of a zone generated from a place (with a voronoi)
+/// has been cut by the existing zones
+#[derive(Serialize, Deserialize, Copy, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VoronoiDebug {
+    /// the boundary has been modified by the subtraction of the existing zones
+    pub modified: bool,
+    /// number of existing zones subtracted from the boundary
+    pub subtracted_zones: usize,
+}
+
 #[derive(Copy, Debug, Clone, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub struct ZoneIndex {
     pub index: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Zone {
     pub id: ZoneIndex,
     pub osm_id: String,
+    /// version of the osm object the zone is built from
+    ///
+    /// It is only populated with the `--keep-osm-meta` option, like `osm_timestamp`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm_version: Option<i32>,
+    /// ISO 8601 timestamp of the last edit of the osm object the zone is built from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osm_timestamp: Option<String>,
     pub admin_level: Option<u32>,
+    /// how the admin_level has been determined (None if there is no admin_level)
+    ///
+    /// This is for debugging purpose only (to audit the typing of the zones),
+    /// it is not kept in normal builds (see the `--keep-provenance` option)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_level_source: Option<AdminLevelSource>,
     pub zone_type: Option<ZoneType>,
     pub name: String,
     pub loc_name: String,
@@ -69,9 +180,13 @@ pub struct Zone {
     pub label: String,
     #[serde(default)]
     pub international_labels: BTreeMap<String, String>,
-    // we do not serialize the internal_names,
-    // it's only used temporary to build the international_labels
-    #[serde(skip)]
+    /// the names of the zone by lang, used to build the international_labels
+    ///
+    /// It is only serialized if not empty, and it is emptied at the end of a build
+    /// unless the names are explicitly kept (see the `--keep-international-names` option),
+    /// so that the labels can be recomputed from a loaded cosmogony.
+    /// Note: keeping them significantly increases the output size.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub international_names: BTreeMap<String, String>, // TODO can we store it outside the zone ?
     pub zip_codes: Vec<String>,
     #[serde(
@@ -99,11 +214,45 @@ pub struct Zone {
     pub center_tags: Tags,
 
     pub parent: Option<ZoneIndex>,
+    /// depth of the zone in the hierarchy, 0 for the zones without parent (like the countries)
+    #[serde(default)]
+    pub depth: u32,
     pub wikidata: Option<String>,
+    /// the wikipedia article, in the `lang:Title` form of the osm tag (eg: `en:Luxembourg`)
+    #[serde(default)]
+    pub wikipedia: Option<String>,
+    #[serde(default)]
+    pub website: Option<String>,
+    /// geodesic length (in meters) of the outer rings of the boundary,
+    /// the holes are not counted in the perimeter
+    pub perimeter: Option<f64>,
+    /// area (in square meters) of the boundary, the holes are not counted in the area
+    ///
+    /// It is computed on a sphere or on the WGS84 ellipsoid, depending on the build's
+    /// `--area-model` (the two differ by well under 1% for most zones)
+    #[serde(default)]
+    pub area: Option<f64>,
+    /// number of holes (interior rings) of the boundary
+    ///
+    /// A lot of holes usually comes from a tagging or an import error
+    #[serde(default)]
+    pub num_holes: usize,
     // pub links: Vec<ZoneIndex>
     #[serde(default)]
     pub is_generated: bool,
     pub country_code: Option<String>,
+    /// all the zones containing this zone, ie the candidates to be its parent
+    ///
+    /// This is for debugging purpose only (to understand why a zone got a given parent),
+    /// it is not populated in normal builds (see the `--keep-inclusions` option)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidate_parents: Vec<ZoneIndex>,
+    /// how the voronoi boundary of a generated zone has been cut by the existing zones
+    ///
+    /// This is for debugging purpose only (to diagnose the voronoi artifacts, like slivers),
+    /// it is not kept in normal builds (see the `--keep-voronoi-debug` option)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voronoi_debug: Option<VoronoiDebug>,
 }
 
 impl Default for Zone {
@@ -111,7 +260,10 @@ impl Default for Zone {
         Zone {
             id: ZoneIndex { index: 0 },
             osm_id: "".into(),
+            osm_version: None,
+            osm_timestamp: None,
             admin_level: None,
+            admin_level_source: None,
             zone_type: None,
             name: "".into(),
             loc_name: "".into(),
@@ -123,37 +275,125 @@ impl Default for Zone {
             boundary: None,
             bbox: None,
             parent: None,
+            depth: 0,
             tags: Tags::new(),
             center_tags: Tags::new(),
             wikidata: None,
+            wikipedia: None,
+            website: None,
+            perimeter: None,
+            area: None,
+            num_holes: 0,
             zip_codes: vec![],
             is_generated: true,
             country_code: None,
+            candidate_parents: vec![],
+            voronoi_debug: None,
         }
     }
 }
 
 impl Zone {
     pub fn is_admin(&self) -> bool {
-        matches!(self.zone_type, Some(t) if t!= ZoneType::NonAdministrative)
+        self.admin_type().is_some()
     }
 
     pub fn admin_type(&self) -> Option<ZoneType> {
         match self.zone_type {
-            Some(t) if t != ZoneType::NonAdministrative => Some(t),
-            _ => None,
+            Some(ZoneType::NonAdministrative | ZoneType::Maritime) => None,
+            t => t,
         }
     }
 
+    /// a maritime zone is not in the hierarchy of the land zones
+    pub fn is_maritime(&self) -> bool {
+        self.zone_type == Some(ZoneType::Maritime)
+    }
+
     pub fn set_parent(&mut self, idx: Option<ZoneIndex>) {
         self.parent = idx;
     }
 
+    /// Check if a point (in WGS84) is in the zone's boundary
+    ///
+    /// A point on the boundary is considered in the zone.
+    /// A zone without boundary contains no point.
+    pub fn contains_point(&self, lon: f64, lat: f64) -> bool {
+        self.boundary
+            .as_ref()
+            .map_or(false, |b| b.intersects(&Point::new(lon, lat)))
+    }
+
+    /// A point in the zone's boundary, to place the zone's label on a map
+    ///
+    /// This is the zone's center if it is in the boundary (with bad data, the center can be
+    /// an `admin_centre` node outside of it), else a point on the boundary's surface.
+    /// The center is returned as is for a zone without boundary.
+    pub fn representative_point(&self) -> Option<Point<f64>> {
+        use geo::InteriorPoint;
+
+        match (&self.boundary, self.center) {
+            (Some(boundary), Some(center)) if !self.contains_point(center.x(), center.y()) => {
+                boundary.interior_point()
+            }
+            (Some(boundary), None) => boundary.interior_point(),
+            (_, center) => center,
+        }
+    }
+
+    /// The properties of the zone when exported as a feature (GeoJSON, FlatGeobuf, ...)
+    ///
+    /// The properties are the main fields of the zone.
+    /// The international labels are flattened as `name:<lang>` properties.
+    pub fn geojson_properties(&self) -> serde_json::Map<String, serde_json::Value> {
+        use serde_json::json;
+
+        let mut properties = serde_json::Map::new();
+        properties.insert("id".into(), json!(self.id.index.to_string()));
+        properties.insert("osm_id".into(), json!(self.osm_id));
+        properties.insert("name".into(), json!(self.name));
+        properties.insert("label".into(), json!(self.label));
+        properties.insert(
+            "zone_type".into(),
+            json!(self.zone_type.map(|t| t.as_str())),
+        );
+        properties.insert("admin_level".into(), json!(self.admin_level));
+        properties.insert("country_code".into(), json!(self.country_code));
+        properties.insert("zip_codes".into(), json!(self.zip_codes));
+        properties.insert("wikidata".into(), json!(self.wikidata));
+        properties.insert("wikipedia".into(), json!(self.wikipedia));
+        properties.insert("website".into(), json!(self.website));
+        for (lang, label) in &self.international_labels {
+            properties.insert(format!("name:{}", lang), json!(label));
+        }
+        properties
+    }
+
+    /// Convert the zone to a GeoJSON feature
+    ///
+    /// The feature's geometry is the zone's boundary, and its properties are
+    /// the [`Zone::geojson_properties`].
+    pub fn to_geojson_feature(&self) -> geojson::Feature {
+        geojson::Feature {
+            bbox: self
+                .bbox
+                .map(|b| vec![b.min().x, b.min().y, b.max().x, b.max().y]),
+            geometry: self
+                .boundary
+                .as_ref()
+                .map(|b| geojson::Geometry::new(geojson::Value::from(b))),
+            id: Some(geojson::feature::Id::String(self.id.index.to_string())),
+            properties: Some(self.geojson_properties()),
+            foreign_members: None,
+        }
+    }
+
     /// iter_hierarchy gives an iterator over the whole hierachy including self
     pub fn iter_hierarchy<'a>(&'a self, all_zones: &'a MutableSlice<'_>) -> HierarchyIterator<'a> {
         HierarchyIterator {
             zone: Some(self),
             all_zones,
+            remaining: all_zones.len(),
         }
     }
 }
@@ -161,6 +401,9 @@ impl Zone {
 pub struct HierarchyIterator<'a> {
     zone: Option<&'a Zone>,
     all_zones: &'a MutableSlice<'a>,
+    // the hierarchy cannot be deeper than the number of zones,
+    // this guards against infinite loops if the hierarchy has a cycle
+    remaining: usize,
 }
 
 impl<'a> Iterator for HierarchyIterator<'a> {
@@ -169,7 +412,16 @@ impl<'a> Iterator for HierarchyIterator<'a> {
         let z = self.zone;
         match z {
             Some(z) => {
+                self.remaining = self.remaining.saturating_sub(1);
                 self.zone = match &z.parent {
+                    Some(ref p_idx) if p_idx.index == self.all_zones.idx => {
+                        warn!("cycle detected in the hierarchy of {}", z.osm_id);
+                        None
+                    }
+                    Some(_) if self.remaining == 0 => {
+                        warn!("hierarchy too deep for {}, there is a cycle", z.osm_id);
+                        None
+                    }
                     Some(ref p_idx) => Some(self.all_zones.get(p_idx)),
                     _ => None,
                 };
@@ -317,3 +569,176 @@ impl<'de> serde::de::Visitor<'de> for ZoneIndexVisitor {
         formatter.write_str("a zone index")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALL_ZONE_TYPES: [ZoneType; 10] = [
+        ZoneType::Neighbourhood,
+        ZoneType::Suburb,
+        ZoneType::CityDistrict,
+        ZoneType::City,
+        ZoneType::StateDistrict,
+        ZoneType::State,
+        ZoneType::CountryRegion,
+        ZoneType::Country,
+        ZoneType::NonAdministrative,
+        ZoneType::Maritime,
+    ];
+
+    #[test]
+    fn zone_type_round_trip() {
+        for zone_type in ALL_ZONE_TYPES {
+            assert_eq!(zone_type.as_str().parse::<ZoneType>().unwrap(), zone_type);
+            assert_eq!(zone_type.to_string(), zone_type.as_str());
+            if zone_type != ZoneType::Neighbourhood {
+                assert_eq!(ZoneType::parse(zone_type.as_str()), Some(zone_type));
+            }
+            // the string is the serialized value
+            assert_eq!(
+                serde_json::to_string(&zone_type).unwrap(),
+                format!("\"{}\"", zone_type)
+            );
+        }
+        assert!("town".parse::<ZoneType>().is_err());
+        assert!("City".parse::<ZoneType>().is_err());
+    }
+
+    #[test]
+    fn zone_types_order() {
+        // the zone types are sorted from the smallest to the largest
+        assert!(ALL_ZONE_TYPES.windows(2).all(|w| w[0] < w[1]));
+        assert!(ZoneType::Neighbourhood < ZoneType::Suburb);
+
+        // the ranks follow the hierarchy, in the same order as the zone types
+        assert!(ALL_ZONE_TYPES.windows(2).all(|w| w[0].rank() < w[1].rank()));
+        assert_eq!(ZoneType::Neighbourhood.rank(), 0);
+        assert!(ZoneType::Suburb.rank() < ZoneType::City.rank());
+        assert!(ZoneType::City.rank() < ZoneType::State.rank());
+        assert!(ZoneType::State.rank() < ZoneType::Country.rank());
+        assert!(ZoneType::Country.rank() < ZoneType::NonAdministrative.rank());
+        assert!(ZoneType::Country.rank() < ZoneType::Maritime.rank());
+
+        // the american spelling is also accepted
+        assert_eq!(
+            serde_json::from_str::<ZoneType>("\"neighborhood\"").unwrap(),
+            ZoneType::Neighbourhood
+        );
+        // the neighbourhood places are suburbs
+        assert_eq!(ZoneType::parse("neighbourhood"), Some(ZoneType::Suburb));
+        // the small places are only typed for the voronoi
+        assert_eq!(ZoneType::parse("hamlet"), None);
+    }
+
+    #[test]
+    fn test_contains_point() {
+        let square = geo_types::Polygon::new(
+            vec![(0., 0.), (0., 10.), (10., 10.), (10., 0.), (0., 0.)].into(),
+            vec![],
+        );
+        let zone = Zone {
+            boundary: Some(MultiPolygon(vec![square])),
+            ..Default::default()
+        };
+
+        assert!(zone.contains_point(5., 5.));
+        assert!(!zone.contains_point(15., 5.));
+        assert!(!zone.contains_point(-0.1, 5.));
+        // on the boundary
+        assert!(zone.contains_point(0., 5.));
+        assert!(zone.contains_point(10., 10.));
+
+        assert!(!Zone::default().contains_point(5., 5.));
+    }
+
+    #[test]
+    fn test_representative_point() {
+        // a U shaped zone, whose centroid is outside of it
+        let u_shape = geo_types::Polygon::new(
+            vec![
+                (0., 0.),
+                (0., 10.),
+                (2., 10.),
+                (2., 2.),
+                (8., 2.),
+                (8., 10.),
+                (10., 10.),
+                (10., 0.),
+                (0., 0.),
+            ]
+            .into(),
+            vec![],
+        );
+        let zone = |center: Option<(f64, f64)>| Zone {
+            boundary: Some(MultiPolygon(vec![u_shape.clone()])),
+            center: center.map(Point::from),
+            ..Default::default()
+        };
+
+        // the center is in the boundary
+        assert_eq!(
+            zone(Some((1., 5.))).representative_point(),
+            Some(Point::new(1., 5.))
+        );
+        // the center is outside of the boundary
+        for z in [zone(Some((5., 5.))), zone(None)] {
+            let point = z.representative_point().unwrap();
+            assert!(z.contains_point(point.x(), point.y()));
+        }
+        // without boundary, the center is used
+        let no_boundary = Zone {
+            center: Some(Point::new(5., 5.)),
+            ..Default::default()
+        };
+        assert_eq!(no_boundary.representative_point(), Some(Point::new(5., 5.)));
+        assert_eq!(Zone::default().representative_point(), None);
+    }
+
+    #[test]
+    fn test_to_geojson_feature() {
+        let zone = Zone {
+            id: ZoneIndex { index: 42 },
+            osm_id: "relation:42".into(),
+            admin_level: Some(8),
+            zone_type: Some(ZoneType::City),
+            name: "Bob".into(),
+            label: "Bob, Bob's land".into(),
+            international_labels: vec![("fr".into(), "Bobette, Pays de Bob".into())]
+                .into_iter()
+                .collect(),
+            zip_codes: vec!["75020".into()],
+            wikidata: Some("Q42".into()),
+            wikipedia: Some("en:Bob".into()),
+            ..Default::default()
+        };
+
+        let feature = zone.to_geojson_feature();
+        assert_eq!(feature.id, Some(geojson::feature::Id::String("42".into())));
+        assert!(feature.geometry.is_none());
+        let props = feature.properties.unwrap();
+        assert_eq!(props["id"], "42");
+        assert_eq!(props["osm_id"], "relation:42");
+        assert_eq!(props["zone_type"], "city");
+        assert_eq!(props["admin_level"], 8);
+        assert_eq!(props["zip_codes"], serde_json::json!(["75020"]));
+        assert_eq!(props["wikidata"], "Q42");
+        assert_eq!(props["wikipedia"], "en:Bob");
+        assert!(props["website"].is_null());
+        assert_eq!(props["name:fr"], "Bobette, Pays de Bob");
+        assert!(props["country_code"].is_null());
+    }
+
+    #[test]
+    fn test_iter_hierarchy_with_cycle() {
+        let make_zone = |idx, parent| Zone {
+            id: ZoneIndex { index: idx },
+            parent: Some(ZoneIndex { index: parent }),
+            ..Default::default()
+        };
+        let mut zones = vec![make_zone(0, 2), make_zone(1, 0), make_zone(2, 1)];
+
+        let (mslice, z) = MutableSlice::init(&mut zones, 1);
+        assert_eq!(z.iter_hierarchy(&mslice).count(), 3);
+    }
+}
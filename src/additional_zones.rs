@@ -1,26 +1,41 @@
+use crate::geometry::convert_to_geo;
 use crate::hierarchy_builder::ZonesTree;
 use crate::is_additional_place;
-use crate::zone_ext::ZoneExt;
-use anyhow::{Context, Result};
-use cosmogony::{Zone, ZoneIndex, ZoneType};
+use crate::progress::Progress;
+use crate::zone_ext::{boundary_perimeter, count_holes, ZoneExt};
+use anyhow::{anyhow, Result};
+use cosmogony::{VoronoiDebug, Zone, ZoneIndex, ZoneType};
 use geo::prelude::BoundingRect;
-use geo_types::{Coord, MultiPolygon, Point, Rect};
+use geo_types::{Coord, Point, Rect};
 use geos::{Geom, Geometry};
 use itertools::Itertools;
 use osmpbfreader::{OsmId, OsmObj};
-use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
-use std::collections::BTreeMap;
-
-fn difference<'a>(g: &geos::Geometry<'a>, other: &Zone) -> Option<geos::Geometry<'a>> {
-    let zone_as_geos: Option<Geometry> = other.boundary.as_ref().and_then(|b| {
-        b.try_into()
-            .map_err(|e| {
-                warn!(
-                    "Failed to convert boundary to geos Geometry for {}. Got {}",
-                    other.osm_id, e
-                );
-            })
-            .ok()
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// cache of the geos conversion of the zones' boundaries (None if the conversion failed)
+//
+// WARNING: geos geometries should not be shared between threads, so each thread needs its own cache
+type GeosCache = HashMap<ZoneIndex, Option<Geometry<'static>>>;
+
+fn difference<'a>(
+    g: &geos::Geometry<'a>,
+    other: &Zone,
+    cache: &mut GeosCache,
+) -> Option<geos::Geometry<'a>> {
+    let zone_as_geos = cache.entry(other.id).or_insert_with(|| {
+        other.boundary.as_ref().and_then(|b| {
+            b.try_into()
+                .map_err(|e| {
+                    warn!(
+                        "Failed to convert boundary to geos Geometry for {}. Got {}",
+                        other.osm_id, e
+                    );
+                })
+                .ok()
+        })
     });
     match zone_as_geos {
         Some(ref geom) => g
@@ -35,8 +50,11 @@ pub fn compute_additional_places(
     zones: &mut Vec<Zone>,
     parsed_pbf: &BTreeMap<OsmId, OsmObj>,
     zones_rtree: ZonesTree,
+    place_types: &[String],
+    city_parents_only: bool,
+    progress: bool,
 ) {
-    let place_zones = read_places(parsed_pbf);
+    let place_zones = read_places(parsed_pbf, place_types);
 
     info!(
         "there are {} places, we'll try to make boundaries for them",
@@ -46,7 +64,11 @@ pub fn compute_additional_places(
     let candidate_parent_zones = place_zones
         .par_iter()
         .filter(|place| {
-            (place.admin_level.is_none() && place.zone_type == Option::from(ZoneType::Suburb))
+            (place.admin_level.is_none()
+                && (matches!(
+                    place.zone_type,
+                    Some(ZoneType::Suburb | ZoneType::Neighbourhood)
+                ) || is_small_place(place)))
                 | place.tags.get("capital").map_or(false, |v| v == "yes")
         })
         .filter_map(|place| {
@@ -57,7 +79,7 @@ pub fn compute_additional_places(
             (parent.zone_type)
                 .map(|parent_zone| {
                     if parent_zone == ZoneType::Country {
-                        println!(
+                        info!(
                             "Ignoring place with id {} and country {} as parent",
                             place.osm_id, parent.osm_id
                         );
@@ -66,8 +88,13 @@ pub fn compute_additional_places(
                     // Ensuring zones are strictly increasing also ensures there will be no
                     // duplicates, for example by adding an admin label which is inside its
                     // boundary.
-                    parent_zone >= place.zone_type.unwrap_or(parent_zone)
-                        && (parent_zone < ZoneType::Country || parent.name == place.name)
+                    is_voronoi_parent_type(
+                        place.zone_type.unwrap_or(parent_zone),
+                        parent_zone,
+                        city_parents_only,
+                    ) && (!is_small_place(place) || parent_zone.rank() > ZoneType::City.rank())
+                        && (parent_zone.rank() < ZoneType::Country.rank()
+                            || parent.name == place.name)
                 })
                 .unwrap_or(false)
         })
@@ -82,16 +109,24 @@ pub fn compute_additional_places(
             map1
         });
 
-    println!(
+    info!(
         "We'll compute voronois partitions for {} parent zones",
         candidate_parent_zones.len()
     );
 
+    let progress = Progress::new("voronoi", candidate_parent_zones.len(), progress);
     let new_cities: Vec<Zone> = {
         candidate_parent_zones
             .into_par_iter()
             .filter(|(_, places)| !places.is_empty())
-            .map(|(parent, places)| compute_voronoi(parent, &places, zones, &zones_rtree))
+            .map(|(parent, mut places)| {
+                // the places are sorted so that the ties between places for a voronoi cell
+                // are always resolved the same way, whatever the number of threads
+                places.sort_by(|a, b| a.osm_id.cmp(&b.osm_id));
+                let voronoi = compute_voronoi(parent, &places, zones, &zones_rtree);
+                progress.inc();
+                voronoi
+            })
             .flatten()
             .collect()
     };
@@ -99,6 +134,22 @@ pub fn compute_additional_places(
     publish_new_places(zones, new_cities);
 }
 
+// check if a zone of type `parent_type` can be split in voronoi cells
+// for the places of type `place_type`
+fn is_voronoi_parent_type(
+    place_type: ZoneType,
+    parent_type: ZoneType,
+    city_parents_only: bool,
+) -> bool {
+    match place_type {
+        // a suburb or a neighbourhood is a part of a city, not of a larger zone
+        ZoneType::Suburb | ZoneType::Neighbourhood if city_parents_only => {
+            parent_type == ZoneType::City
+        }
+        _ => parent_type.rank() >= place_type.rank(),
+    }
+}
+
 fn get_parent<'a>(place: &Zone, zones: &'a [Zone], zones_rtree: &ZonesTree) -> Option<&'a Zone> {
     zones_rtree
         .fetch_zone_bbox(place)
@@ -112,19 +163,19 @@ fn get_parent<'a>(place: &Zone, zones: &'a [Zone], zones_rtree: &ZonesTree) -> O
             // to the boundary of a city distinct (for instance).
             // Points which are already part of a "City" will be ignored afterwards.
             z.admin_type()
-                .map(|zt| zt >= ZoneType::City)
+                .map(|zt| zt.rank() >= ZoneType::City.rank())
                 .unwrap_or(false)
         })
-        .sorted_by_key(|z| z.zone_type)
+        .sorted_by_key(|z| z.zone_type.map(|zt| zt.rank()))
         .find(|z| z.contains_center(place))
 }
 
-fn read_places(parsed_pbf: &BTreeMap<OsmId, OsmObj>) -> Vec<Zone> {
+fn read_places(parsed_pbf: &BTreeMap<OsmId, OsmObj>, place_types: &[String]) -> Vec<Zone> {
     parsed_pbf
         .values()
         .enumerate()
         .filter_map(|(index, obj)| {
-            if !is_additional_place(obj) {
+            if !is_additional_place(obj, place_types) {
                 return None;
             }
 
@@ -136,6 +187,14 @@ fn read_places(parsed_pbf: &BTreeMap<OsmId, OsmObj>) -> Vec<Zone> {
                 return None;
             }
 
+            if let Some(zone_type) = node
+                .tags
+                .get("place")
+                .map(|p| p.as_str())
+                .and_then(place_zone_type)
+            {
+                zone.zone_type = Some(zone_type);
+            }
             zone.center = Some(Point::<f64>::new(node.lon(), node.lat()));
             zone.bbox = zone.center.as_ref().map(|p| {
                 Rect::new(
@@ -156,46 +215,73 @@ fn read_places(parsed_pbf: &BTreeMap<OsmId, OsmObj>) -> Vec<Zone> {
         .collect()
 }
 
-fn convert_to_geo(geom: Geometry<'_>) -> Result<Option<MultiPolygon<f64>>> {
-    let is_empty_poly = |poly: &geo::Polygon| poly.exterior().lines().next().is_none();
+// the type of the places whose `place` value is not typed like `ZoneType::parse` does:
+// the neighbourhoods get their own type (below the suburbs), and the small places
+// are typed like the villages
+fn place_zone_type(place: &str) -> Option<ZoneType> {
+    match place {
+        "neighbourhood" => Some(ZoneType::Neighbourhood),
+        _ => small_place_type(place),
+    }
+}
 
-    let polys = match geom.try_into().context("failed to convert to geo")? {
-        geo::Geometry::Polygon(x) => vec![x],
-        geo::Geometry::GeometryCollection(geoms) => {
-            // Convert each geometry into a multi-polygon
-            let multi_polys: Vec<_> = geoms
-                .into_iter()
-                .map(MultiPolygon::try_from)
-                .try_collect()?;
+// the type of the small rural places, which can only be used for the voronoi
+// when they are explicitly asked for: they are settlements, typed like the villages
+fn small_place_type(place: &str) -> Option<ZoneType> {
+    match place {
+        "hamlet" | "isolated_dwelling" => Some(ZoneType::City),
+        _ => None,
+    }
+}
 
-            // Flatten all multi polygons into a single one
-            multi_polys
-                .into_iter()
-                .flat_map(|m| m.into_iter())
-                .collect()
+// check if a place is a small rural place (see `small_place_type`)
+// Unlike the suburbs, they are not a part of a city: their voronoi cells are only cut
+// in a zone larger than a city, and the ones inside a city are ignored.
+fn is_small_place(place: &Zone) -> bool {
+    place
+        .tags
+        .get("place")
+        .is_some_and(|p| small_place_type(p).is_some())
+}
+
+// repair the boundary of a place if the subtraction of the existing zones made it invalid
+// (slivers, degenerate holes, ...), with a 0 buffer
+fn repair_subtracted<'a>(g: Geometry<'a>, osm_id: &str) -> Result<Geometry<'a>> {
+    if g.is_valid() {
+        return Ok(g);
+    }
+    match g.buffer(0., 8) {
+        Ok(repaired) if repaired.is_valid() => {
+            warn!(
+                "subtract_existing_town: the subtraction produced an invalid geometry for {}, repaired",
+                osm_id
+            );
+            Ok(repaired)
         }
-        y => {
-            return Ok(Some(
-                y.try_into().context("failed to convert to multi-polygon")?,
-            ))
+        Ok(_) => {
+            warn!(
+                "subtract_existing_town: the subtraction produced an unrecoverable invalid geometry for {}",
+                osm_id
+            );
+            Err(anyhow!("unrecoverable invalid geometry for {}", osm_id))
         }
-    };
-
-    let polys: Vec<_> = polys
-        .into_iter()
-        .filter(|poly| !is_empty_poly(poly))
-        .collect();
-
-    Ok({
-        if polys.is_empty() {
-            None
-        } else {
-            Some(MultiPolygon(polys))
+        Err(err) => {
+            warn!(
+                "subtract_existing_town: the subtraction produced an unrecoverable invalid geometry for {}: {}",
+                osm_id, err
+            );
+            Err(err.into())
         }
-    })
+    }
 }
 
-fn subtract_existing_zones(zone: &mut Zone, to_subtract: &[&Zone]) -> Result<()> {
+fn subtract_existing_zones(
+    zone: &mut Zone,
+    to_subtract: &[&Zone],
+    cache: &mut GeosCache,
+) -> Result<()> {
+    // the debug info are only kept with the `--keep-voronoi-debug` option
+    zone.voronoi_debug = Some(VoronoiDebug::default());
     if to_subtract.is_empty() {
         return Ok(());
     }
@@ -212,13 +298,14 @@ fn subtract_existing_zones(zone: &mut Zone, to_subtract: &[&Zone]) -> Result<()>
         })?;
 
         for z in to_subtract {
-            if let Some(b) = difference(&g_boundary, z) {
+            if let Some(b) = difference(&g_boundary, z, cache) {
                 updates += 1;
                 g_boundary = b;
             }
         }
 
         if updates > 0 {
+            let g_boundary = repair_subtracted(g_boundary, &zone.osm_id)?;
             if let Some(g) = convert_to_geo(g_boundary).map_err(|err| {
                 warn!(
                     "subtract_existing_town: failed to convert back to geo for {}...",
@@ -228,6 +315,10 @@ fn subtract_existing_zones(zone: &mut Zone, to_subtract: &[&Zone]) -> Result<()>
             })? {
                 zone.bbox = g.bounding_rect();
                 zone.boundary = Some(g);
+                zone.voronoi_debug = Some(VoronoiDebug {
+                    modified: true,
+                    subtracted_zones: updates,
+                });
             }
         }
     }
@@ -244,6 +335,8 @@ fn get_places_to_subtract<'a>(
         .fetch_zone_bbox(zone)
         .into_par_iter()
         .map(|z_idx| &zones[z_idx.index])
+        // the place's boundary is a part of its parent, which is never subtracted
+        .filter(|z| z.id != *parent_id)
         .filter(|z| {
             z.admin_type()
                 .map(|zt| Some(zt) == zone.zone_type || z.parent == Some(*parent_id))
@@ -273,20 +366,20 @@ fn compute_voronoi(
         place.boundary = parent.boundary.clone();
         place.bbox = parent.bbox;
         place.parent = Some(parent.id);
-        let zones_to_subtract = get_places_to_subtract(parent, &parent.id, zones, zones_rtree);
+        let zones_to_subtract = get_places_to_subtract(&place, &parent.id, zones, zones_rtree);
         // If an error occurs, we can't just use the parent area so instead, we return nothing.
-        if subtract_existing_zones(&mut place, &zones_to_subtract).is_ok() {
+        if subtract_existing_zones(&mut place, &zones_to_subtract, &mut GeosCache::new()).is_ok() {
             return vec![place];
         }
         return Vec::new();
     }
     if parent.zone_type == Some(ZoneType::Country) {
-        println!(
+        info!(
             "Parent {} is a country, ignoring all zones inside it:",
             parent.osm_id
         );
         for point in &points {
-            println!(" => ignoring {}", places[point.0].osm_id);
+            info!(" => ignoring {}", places[point.0].osm_id);
         }
         return Vec::new();
     }
@@ -361,63 +454,533 @@ fn compute_voronoi(
         })
         .collect();
 
+    let cells_points = cells_points(&voronoi_polygons, &geos_points);
+
     voronoi_polygons
         .into_par_iter()
-        .filter_map(|voronoi| {
-            // WARNING: This clone should not be necessary, but segfaults occurred. Thread-safety issue in geos ?
-            let geos_points = geos_points.clone();
+        .zip(cells_points)
+        .map_init(
+            // Each rayon job has its own geos cache of the zones to subtract
+            GeosCache::new,
+            |cache, (voronoi, cell_point)| {
+                let mut place = match cell_point {
+                    Some(idx) => places[idx].clone(),
+                    None => {
+                        warn!("town not found for parent {}...", parent.osm_id);
+                        return None;
+                    }
+                };
+
+                match geos_parent.intersection(&voronoi) {
+                    Ok(s) => {
+                        place.parent = Some(parent.id);
+
+                        place.boundary = convert_to_geo(s)
+                            .map_err(|err| warn!("failed to convert to geos: {err:?}"))
+                            .ok()
+                            .flatten();
+
+                        if let Some(ref boundary) = place.boundary {
+                            place.bbox = boundary.bounding_rect();
+                        }
+                        let zones_to_subtract =
+                            get_places_to_subtract(&place, &parent.id, zones, zones_rtree);
+                        subtract_existing_zones(&mut place, &zones_to_subtract, cache).ok()?;
+                        Some(place)
+                    }
+                    Err(e) => {
+                        warn!(
+                            "intersection failure: {} ({})",
+                            e,
+                            voronoi
+                                .get_context_handle()
+                                .get_last_error()
+                                .unwrap_or_else(|| "Unknown GEOS error".to_owned())
+                        );
+                        None
+                    }
+                }
+            },
+        )
+        .flatten()
+        .collect()
+}
 
-            // Since GEOS doesn't return voronoi geometries in the same order as the given points...
-            let mut place = {
-                if let Some(idx) = geos_points
+/// The position of the place of each voronoi cell (None if no place can be found)
+///
+/// Since GEOS doesn't return voronoi geometries in the same order as the given points,
+/// the point of each cell is the one it contains. The cells without point get the nearest
+/// point that has no cell yet (see [`nearest_point`]).
+fn cells_points<'a, G: Geom<'a> + Sync>(
+    voronoi_polygons: &[G],
+    geos_points: &[(usize, Geometry<'_>)],
+) -> Vec<Option<usize>> {
+    // Note: the points are sorted by osm_id, so if several points match the cell
+    // (a point on the cell's boundary), the same one is always chosen
+    let mut cells_points: Vec<Option<usize>> = voronoi_polygons
+        .par_iter()
+        .map_init(
+            // WARNING: This clone should not be necessary, but segfaults occurred. Thread-safety issue in geos ?
+            // Each rayon job thus has its own points
+            || geos_points.to_vec(),
+            |geos_points, voronoi| {
+                // the cell is prepared since it is tested against all the points
+                let prepared_voronoi = voronoi
+                    .to_prepared_geom()
+                    .map_err(|e| warn!("impossible to prepare a voronoi cell: {}", e))
+                    .ok();
+                let contains = |x: &Geometry<'_>| match &prepared_voronoi {
+                    Some(prepared) => prepared.contains(x),
+                    None => voronoi.contains(x),
+                };
+                geos_points
                     .iter()
-                    .filter(|(_, x)| voronoi.contains(x).unwrap_or(false))
+                    .filter(|(_, x)| contains(x).unwrap_or(false))
                     .map(|(pos, _)| *pos)
                     .next()
-                {
-                    places[idx].clone()
-                } else {
-                    println!("town not found for parent {}...", parent.osm_id);
-                    return None;
-                }
-            };
-
-            match geos_parent.intersection(&voronoi) {
-                Ok(s) => {
-                    place.parent = Some(parent.id);
+            },
+        )
+        .collect();
 
-                    place.boundary = convert_to_geo(s)
-                        .map_err(|err| warn!("failed to convert to geos: {err:?}"))
-                        .ok()
-                        .flatten();
+    // the cells without point get the nearest point that has no cell yet,
+    // so a place is never given to several cells
+    let mut used_points: HashSet<usize> = cells_points.iter().flatten().copied().collect();
+    for (voronoi, cell_point) in voronoi_polygons.iter().zip(cells_points.iter_mut()) {
+        if cell_point.is_some() {
+            continue;
+        }
+        *cell_point = nearest_point(voronoi, geos_points, &used_points);
+        used_points.extend(*cell_point);
+    }
+    cells_points
+}
 
-                    if let Some(ref boundary) = place.boundary {
-                        place.bbox = boundary.bounding_rect();
-                    }
-                    let zones_to_subtract =
-                        get_places_to_subtract(&place, &parent.id, zones, zones_rtree);
-                    subtract_existing_zones(&mut place, &zones_to_subtract).ok()?;
-                    Some(place)
-                }
-                Err(e) => {
-                    warn!(
-                        "intersection failure: {} ({})",
-                        e,
-                        voronoi
-                            .get_context_handle()
-                            .get_last_error()
-                            .unwrap_or_else(|| "Unknown GEOS error".to_owned())
-                    );
-                    None
-                }
-            }
+/// Find the point nearest to the centroid of a voronoi cell, among the points not yet used
+///
+/// This is used as a fallback when GEOS returns a cell that does not
+/// contain any of the input points, so that the place is not lost.
+/// On a tie, the first point is returned, so the points' order has to be deterministic.
+fn nearest_point<'a>(
+    cell: &impl Geom<'a>,
+    points: &[(usize, Geometry<'_>)],
+    used_points: &HashSet<usize>,
+) -> Option<usize> {
+    let centroid = cell
+        .get_centroid()
+        .map_err(|e| {
+            warn!(
+                "impossible to compute the centroid of a voronoi cell: {}",
+                e
+            )
         })
-        .collect()
+        .ok()?;
+    points
+        .iter()
+        .filter(|(pos, _)| !used_points.contains(pos))
+        .filter_map(|(pos, p)| centroid.distance(p).ok().map(|d| (*pos, d)))
+        .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2))
+        .map(|(pos, _)| pos)
 }
 
 fn publish_new_places(zones: &mut Vec<Zone>, new_cities: Vec<Zone>) {
     for mut city in new_cities {
         city.id = ZoneIndex { index: zones.len() };
+        city.perimeter = city.boundary.as_ref().map(boundary_perimeter);
+        city.num_holes = city.boundary.as_ref().map_or(0, count_holes);
+        city.depth = city.parent.map_or(0, |p| zones[p.index].depth + 1);
         zones.push(city);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{compute_additional_places, compute_voronoi, read_places, subtract_existing_zones};
+    use crate::hierarchy_builder::ZonesTree;
+    use cosmogony::{Zone, ZoneIndex, ZoneType};
+    use geo::bounding_rect::BoundingRect;
+    use geo_types::{Coord, LineString, MultiPolygon, Point, Polygon, Rect};
+
+    fn make_parent() -> Zone {
+        let ls = LineString(
+            vec![(0., 0.), (0., 10.), (10., 10.), (10., 0.), (0., 0.)]
+                .into_iter()
+                .map(Coord::from)
+                .collect(),
+        );
+        let boundary = MultiPolygon(vec![Polygon::new(ls, vec![])]);
+        Zone {
+            id: ZoneIndex { index: 0 },
+            osm_id: "relation:0".into(),
+            zone_type: Some(ZoneType::StateDistrict),
+            bbox: boundary.bounding_rect(),
+            boundary: Some(boundary),
+            is_generated: false,
+            ..Default::default()
+        }
+    }
+
+    fn make_place(idx: usize, x: f64, y: f64) -> Zone {
+        Zone {
+            id: ZoneIndex { index: idx },
+            osm_id: format!("node:{}", idx),
+            zone_type: Some(ZoneType::City),
+            center: Some(Point::new(x, y)),
+            bbox: Some(Rect::new(Coord { x, y }, Coord { x, y })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn voronoi_does_not_lose_places() {
+        let zones = vec![make_parent()];
+        let rtree: ZonesTree = zones.iter().collect();
+
+        // some points on the parent's boundary and corners, a layout for which GEOS
+        // reorders the cells (not a regular grid: the cocircular points are rejected
+        // by the snapping of some GEOS versions)
+        let places: Vec<Zone> = [
+            (0., 0.),
+            (5., 0.),
+            (10., 10.),
+            (2., 3.),
+            (7., 2.),
+            (5., 5.5),
+            (3., 7.),
+            (8., 7.5),
+            (0., 5.),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, (x, y))| make_place(i + 1, *x, *y))
+        .collect();
+        let places_ref: Vec<&Zone> = places.iter().collect();
+
+        let new_places = compute_voronoi(&ZoneIndex { index: 0 }, &places_ref, &zones, &rtree);
+
+        for p in &places {
+            assert!(
+                new_places.iter().any(|n| n.osm_id == p.osm_id),
+                "place {} has been lost",
+                p.osm_id
+            );
+        }
+        // and a place is never given to several cells
+        let osm_ids: std::collections::HashSet<&str> =
+            new_places.iter().map(|p| p.osm_id.as_str()).collect();
+        assert_eq!(osm_ids.len(), new_places.len());
+        assert!(new_places.iter().all(|p| p.boundary.is_some()));
+        assert!(new_places
+            .iter()
+            .all(|p| p.parent == Some(ZoneIndex { index: 0 })));
+    }
+
+    #[test]
+    fn cells_without_point() {
+        use super::cells_points;
+        use geos::Geometry;
+
+        let wkt = |wkt: &str| Geometry::new_from_wkt(wkt).unwrap();
+        let cells = vec![
+            wkt("POLYGON((0 0, 0 1, 1 1, 1 0, 0 0))"),
+            // a cell without point
+            wkt("POLYGON((2 2, 2 3, 3 3, 3 2, 2 2))"),
+        ];
+        // the first point is the nearest to the empty cell, but it is already in a cell
+        let points = vec![
+            (0, wkt("POINT(0.9 0.9)")),
+            (1, wkt("POINT(4.5 4.5)")),
+            (2, wkt("POINT(10 10)")),
+        ];
+        assert_eq!(cells_points(&cells, &points), vec![Some(0), Some(1)]);
+
+        // without any point left, the cell has no place
+        assert_eq!(cells_points(&cells, &points[..1]), vec![Some(0), None]);
+    }
+
+    #[test]
+    fn neighbourhood_places() {
+        use osmpbfreader::{Node, NodeId, OsmId, OsmObj, Tags};
+        use std::collections::BTreeMap;
+
+        let square = |min: f64, max: f64| {
+            MultiPolygon(vec![Polygon::new(
+                LineString::from(vec![
+                    (min, min),
+                    (min, max),
+                    (max, max),
+                    (max, min),
+                    (min, min),
+                ]),
+                vec![],
+            )])
+        };
+        let zone =
+            |index: usize, zone_type, boundary: MultiPolygon<f64>, parent: Option<usize>| Zone {
+                id: ZoneIndex { index },
+                osm_id: format!("relation:{}", index),
+                zone_type: Some(zone_type),
+                parent: parent.map(|index| ZoneIndex { index }),
+                bbox: boundary.bounding_rect(),
+                boundary: Some(boundary),
+                is_generated: false,
+                ..Default::default()
+            };
+        // a city with an admin suburb
+        let zones = vec![
+            make_parent(),
+            zone(1, ZoneType::City, square(0., 4.), Some(0)),
+            zone(2, ZoneType::Suburb, square(0., 2.), Some(1)),
+        ];
+
+        // two neighbourhood nodes in the city, outside of the suburb
+        let neighbourhood = |id: i64, lon: f64, lat: f64| {
+            let mut tags = Tags::new();
+            tags.insert("place".into(), "neighbourhood".into());
+            tags.insert("name".into(), format!("neighbourhood {}", id).into());
+            let node = Node {
+                id: NodeId(id),
+                tags,
+                decimicro_lat: (lat * 1e7) as i32,
+                decimicro_lon: (lon * 1e7) as i32,
+            };
+            (OsmId::Node(NodeId(id)), OsmObj::Node(node))
+        };
+        let parsed_pbf: BTreeMap<OsmId, OsmObj> =
+            [neighbourhood(1, 3., 3.), neighbourhood(2, 3., 1.)]
+                .into_iter()
+                .collect();
+
+        // the neighbourhood places are not used if they are not in the place types
+        let generate = |place_types: &[String]| {
+            let mut zones = zones.clone();
+            let rtree: ZonesTree = zones.iter().collect();
+            compute_additional_places(&mut zones, &parsed_pbf, rtree, place_types, false, false);
+            zones.split_off(3)
+        };
+        assert!(generate(&["city".into(), "suburb".into()]).is_empty());
+
+        // they are in the default place types, and are neighbourhoods of the city
+        let new_places = generate(&[]);
+        assert_eq!(new_places.len(), 2);
+        assert!(new_places
+            .iter()
+            .all(|p| p.zone_type == Some(ZoneType::Neighbourhood)));
+        assert!(new_places
+            .iter()
+            .all(|p| p.parent == Some(ZoneIndex { index: 1 })));
+        // so the existing suburbs are subtracted from their boundary
+        assert!(new_places.iter().all(|p| !p.contains_point(1., 1.)));
+    }
+
+    #[test]
+    fn small_places() {
+        use osmpbfreader::{Node, NodeId, OsmId, OsmObj, Tags};
+        use std::collections::BTreeMap;
+
+        let place = |id: i64, place: &str, lon: f64, lat: f64| {
+            let mut tags = Tags::new();
+            tags.insert("place".into(), place.into());
+            tags.insert("name".into(), format!("place {}", id).into());
+            let node = Node {
+                id: NodeId(id),
+                tags,
+                decimicro_lat: (lat * 1e7) as i32,
+                decimicro_lon: (lon * 1e7) as i32,
+            };
+            (OsmId::Node(NodeId(id)), OsmObj::Node(node))
+        };
+        let place_types: Vec<String> = vec![
+            "hamlet".into(),
+            "isolated_dwelling".into(),
+            "village".into(),
+        ];
+
+        // the small places are typed like the villages
+        let parsed_pbf: BTreeMap<OsmId, OsmObj> = [
+            place(1, "hamlet", 0., 0.),
+            place(2, "isolated_dwelling", 0., 0.),
+            place(3, "village", 0., 0.),
+        ]
+        .into_iter()
+        .collect();
+        let places = read_places(&parsed_pbf, &place_types);
+        let types: Vec<_> = places
+            .iter()
+            .map(|p| (p.osm_id.as_str(), p.zone_type))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                ("node:1", Some(ZoneType::City)),
+                ("node:2", Some(ZoneType::City)),
+                ("node:3", Some(ZoneType::City)),
+            ]
+        );
+
+        // a hamlet outside of any city splits the state district,
+        // a hamlet in a city is a part of the city and is ignored
+        let city_boundary = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![(0., 0.), (0., 4.), (4., 4.), (4., 0.), (0., 0.)]),
+            vec![],
+        )]);
+        let city = Zone {
+            id: ZoneIndex { index: 1 },
+            osm_id: "relation:1".into(),
+            zone_type: Some(ZoneType::City),
+            parent: Some(ZoneIndex { index: 0 }),
+            bbox: city_boundary.bounding_rect(),
+            boundary: Some(city_boundary),
+            is_generated: false,
+            ..Default::default()
+        };
+        let mut zones = vec![make_parent(), city];
+        let parsed_pbf: BTreeMap<OsmId, OsmObj> =
+            [place(1, "hamlet", 7., 7.), place(2, "hamlet", 2., 2.)]
+                .into_iter()
+                .collect();
+        let rtree: ZonesTree = zones.iter().collect();
+        compute_additional_places(&mut zones, &parsed_pbf, rtree, &place_types, false, false);
+        let new_places = zones.split_off(2);
+        assert_eq!(new_places.len(), 1);
+        assert_eq!(new_places[0].osm_id, "node:1");
+        assert_eq!(new_places[0].zone_type, Some(ZoneType::City));
+        assert_eq!(new_places[0].parent, Some(ZoneIndex { index: 0 }));
+        // the city is subtracted from the hamlet's cell
+        assert!(!new_places[0].contains_point(2., 2.));
+        assert!(new_places[0].contains_point(7., 7.));
+    }
+
+    #[test]
+    fn voronoi_debug() {
+        let square = |min: f64, max: f64| {
+            MultiPolygon(vec![Polygon::new(
+                LineString::from(vec![
+                    (min, min),
+                    (min, max),
+                    (max, max),
+                    (max, min),
+                    (min, min),
+                ]),
+                vec![],
+            )])
+        };
+        let mut place = make_place(1, 5., 5.);
+        place.boundary = Some(square(0., 10.));
+        let city_boundary = square(0., 4.);
+        let city = Zone {
+            id: ZoneIndex { index: 2 },
+            bbox: city_boundary.bounding_rect(),
+            boundary: Some(city_boundary),
+            ..make_parent()
+        };
+
+        // nothing to subtract, the boundary is not modified
+        subtract_existing_zones(&mut place, &[], &mut Default::default()).unwrap();
+        let debug = place.voronoi_debug.unwrap();
+        assert!(!debug.modified);
+        assert_eq!(debug.subtracted_zones, 0);
+
+        subtract_existing_zones(&mut place, &[&city], &mut Default::default()).unwrap();
+        let debug = place.voronoi_debug.unwrap();
+        assert!(debug.modified);
+        assert_eq!(debug.subtracted_zones, 1);
+        assert!(!place.contains_point(2., 2.));
+    }
+
+    #[test]
+    fn repair_invalid_subtraction() {
+        use super::repair_subtracted;
+        use geos::{Geom, Geometry};
+
+        let square = Geometry::new_from_wkt("POLYGON((0 0, 0 1, 1 1, 1 0, 0 0))").unwrap();
+        let repaired = repair_subtracted(square, "node:1").unwrap();
+        assert_eq!(repaired.area().unwrap(), 1.);
+
+        // a self intersecting ring is repaired
+        let bowtie = Geometry::new_from_wkt("POLYGON((0 0, 2 2, 2 0, 0 2, 0 0))").unwrap();
+        assert!(!bowtie.is_valid());
+        let repaired = repair_subtracted(bowtie, "node:2").unwrap();
+        assert!(repaired.is_valid());
+    }
+
+    #[test]
+    fn suburb_places() {
+        use osmpbfreader::{Node, NodeId, OsmId, OsmObj, Tags};
+        use std::collections::BTreeMap;
+
+        let square = |size: f64| {
+            MultiPolygon(vec![Polygon::new(
+                LineString::from(vec![
+                    (0., 0.),
+                    (0., size),
+                    (size, size),
+                    (size, 0.),
+                    (0., 0.),
+                ]),
+                vec![],
+            )])
+        };
+        let city_boundary = square(4.);
+        let city = Zone {
+            id: ZoneIndex { index: 1 },
+            osm_id: "relation:1".into(),
+            name: "Big City".into(),
+            zone_type: Some(ZoneType::City),
+            parent: Some(ZoneIndex { index: 0 }),
+            bbox: city_boundary.bounding_rect(),
+            boundary: Some(city_boundary),
+            is_generated: false,
+            ..Default::default()
+        };
+        let zones = vec![make_parent(), city];
+
+        // a suburb in the city, and one in the state district but outside of the city
+        let suburb = |id: i64, name: &str, lon: f64, lat: f64| {
+            let mut tags = Tags::new();
+            tags.insert("place".into(), "suburb".into());
+            tags.insert("name".into(), name.into());
+            let node = Node {
+                id: NodeId(id),
+                tags,
+                decimicro_lat: (lat * 1e7) as i32,
+                decimicro_lon: (lon * 1e7) as i32,
+            };
+            (OsmId::Node(NodeId(id)), OsmObj::Node(node))
+        };
+        let parsed_pbf: BTreeMap<OsmId, OsmObj> = [
+            suburb(1, "Downtown", 2., 2.),
+            suburb(2, "Countryside", 7., 7.),
+        ]
+        .into_iter()
+        .collect();
+
+        let generate = |city_parents_only: bool| {
+            let mut zones = zones.clone();
+            let rtree: ZonesTree = zones.iter().collect();
+            compute_additional_places(
+                &mut zones,
+                &parsed_pbf,
+                rtree,
+                &[],
+                city_parents_only,
+                false,
+            );
+            zones.split_off(2)
+        };
+
+        // the suburb in the city is a part of the city, with the suburb type
+        let new_places = generate(true);
+        assert_eq!(new_places.len(), 1);
+        assert_eq!(new_places[0].osm_id, "node:1");
+        assert_eq!(new_places[0].zone_type, Some(ZoneType::Suburb));
+        assert_eq!(new_places[0].parent, Some(ZoneIndex { index: 1 }));
+
+        // by default, a suburb outside of a city is a part of the smallest zone containing it
+        let new_places = generate(false);
+        let mut osm_ids: Vec<_> = new_places.iter().map(|z| z.osm_id.as_str()).collect();
+        osm_ids.sort();
+        assert_eq!(osm_ids, vec!["node:1", "node:2"]);
+        let countryside = new_places.iter().find(|z| z.osm_id == "node:2").unwrap();
+        assert_eq!(countryside.parent, Some(ZoneIndex { index: 0 }));
+    }
+}
@@ -0,0 +1,190 @@
+//! Join of external attributes into the zones' tags
+//!
+//! The attributes are read from a CSV file with a header, one of its columns being the
+//! join key (the zones' osm_id or wikidata id). All the other columns are added to the
+//! tags of the matching zones, prefixed by `ext:` (eg: a `gdp` column gives an `ext:gdp` tag).
+
+use anyhow::{anyhow, Context, Error, Result};
+use cosmogony::Zone;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Prefix of the tags added from the external attributes
+pub const ATTRIBUTES_PREFIX: &str = "ext:";
+
+/// The zones' field used to join the attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinKey {
+    /// the zone's osm_id (eg: `relation:407489`)
+    OsmId,
+    /// the zone's wikidata id (eg: `Q1842`)
+    #[default]
+    Wikidata,
+}
+
+impl JoinKey {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JoinKey::OsmId => "osm_id",
+            JoinKey::Wikidata => "wikidata",
+        }
+    }
+
+    fn zone_key<'a>(&self, zone: &'a Zone) -> Option<&'a str> {
+        match self {
+            JoinKey::OsmId => Some(&zone.osm_id),
+            JoinKey::Wikidata => zone.wikidata.as_deref(),
+        }
+    }
+}
+
+impl FromStr for JoinKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "osm_id" => Ok(JoinKey::OsmId),
+            "wikidata" => Ok(JoinKey::Wikidata),
+            _ => Err(anyhow!(
+                "unknown join key '{}', the accepted values are 'osm_id' and 'wikidata'",
+                s
+            )),
+        }
+    }
+}
+
+/// Configuration of the join of the external attributes
+#[derive(Debug, Clone)]
+pub struct AttributesJoin {
+    /// The CSV file with the attributes
+    pub path: PathBuf,
+    /// The zones' field to join on
+    pub join_key: JoinKey,
+    /// The CSV column with the join key (by default, the name of the join key: `osm_id` or `wikidata`)
+    pub key_column: String,
+}
+
+impl AttributesJoin {
+    pub fn new(path: impl Into<PathBuf>, join_key: JoinKey) -> Self {
+        Self {
+            path: path.into(),
+            join_key,
+            key_column: join_key.as_str().to_string(),
+        }
+    }
+
+    pub fn key_column(mut self, key_column: impl Into<String>) -> Self {
+        self.key_column = key_column.into();
+        self
+    }
+}
+
+/// Add the attributes of the CSV file to the tags of the matching zones
+///
+/// The rows without a matching zone are logged, the number of joined rows is returned.
+pub fn join_attributes(zones: &mut [Zone], join: &AttributesJoin) -> Result<usize> {
+    info!("joining the attributes of {}", join.path.display());
+    let mut reader = csv::Reader::from_path(&join.path)
+        .with_context(|| format!("impossible to read {}", join.path.display()))?;
+    let headers = reader.headers()?.clone();
+    let key_idx = headers
+        .iter()
+        .position(|h| h == join.key_column)
+        .ok_or_else(|| anyhow!("no column '{}' in {}", join.key_column, join.path.display()))?;
+
+    // several zones can share a wikidata id
+    let mut zones_by_key: HashMap<String, Vec<usize>> = HashMap::new();
+    for (pos, z) in zones.iter().enumerate() {
+        if let Some(key) = join.join_key.zone_key(z) {
+            zones_by_key.entry(key.to_string()).or_default().push(pos);
+        }
+    }
+
+    let mut nb_joined = 0;
+    for record in reader.records() {
+        let record = record?;
+        let key = record.get(key_idx).unwrap_or_default();
+        let positions = match zones_by_key.get(key) {
+            Some(positions) => positions,
+            None => {
+                warn!("no zone found for the attributes of '{}'", key);
+                continue;
+            }
+        };
+        for pos in positions {
+            headers
+                .iter()
+                .zip(record.iter())
+                .enumerate()
+                .filter(|(idx, (_, value))| *idx != key_idx && !value.is_empty())
+                .for_each(|(_, (column, value))| {
+                    zones[*pos].tags.insert(
+                        format!("{}{}", ATTRIBUTES_PREFIX, column).into(),
+                        value.into(),
+                    );
+                });
+        }
+        nb_joined += 1;
+    }
+    info!("{} attributes rows joined", nb_joined);
+    Ok(nb_joined)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_zone(osm_id: &str, wikidata: Option<&str>) -> Zone {
+        Zone {
+            osm_id: osm_id.into(),
+            wikidata: wikidata.map(Into::into),
+            ..Default::default()
+        }
+    }
+
+    fn write_csv(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn join_on_wikidata() {
+        let path = write_csv(
+            "cosmogony_attributes_wikidata.csv",
+            "gdp,wikidata,population\n42,Q1,1000\n12,Q2,\n1,Q404,1\n",
+        );
+        let mut zones = vec![
+            make_zone("relation:1", Some("Q1")),
+            make_zone("relation:2", Some("Q2")),
+            make_zone("relation:3", None),
+        ];
+
+        let nb_joined =
+            join_attributes(&mut zones, &AttributesJoin::new(path, JoinKey::Wikidata)).unwrap();
+        assert_eq!(nb_joined, 2);
+        assert_eq!(zones[0].tags.get("ext:gdp"), Some(&"42".into()));
+        assert_eq!(zones[0].tags.get("ext:population"), Some(&"1000".into()));
+        assert!(zones[0].tags.get("ext:wikidata").is_none());
+        assert_eq!(zones[1].tags.get("ext:gdp"), Some(&"12".into()));
+        // the empty values are not added
+        assert!(zones[1].tags.get("ext:population").is_none());
+        assert!(zones[2].tags.is_empty());
+    }
+
+    #[test]
+    fn join_on_osm_id_with_custom_column() {
+        let path = write_csv("cosmogony_attributes_osm_id.csv", "id,gdp\nrelation:2,12\n");
+        let mut zones = vec![make_zone("relation:1", None), make_zone("relation:2", None)];
+
+        let join = AttributesJoin::new(&path, JoinKey::OsmId).key_column("id");
+        assert_eq!(join_attributes(&mut zones, &join).unwrap(), 1);
+        assert!(zones[0].tags.is_empty());
+        assert_eq!(zones[1].tags.get("ext:gdp"), Some(&"12".into()));
+
+        // the key column must exist
+        let join = AttributesJoin::new(&path, JoinKey::OsmId);
+        assert!(join_attributes(&mut zones, &join).is_err());
+    }
+}
@@ -1,13 +1,21 @@
 use anyhow::{anyhow, Result};
 use clap::error::ErrorKind;
 use clap::Parser;
-use cosmogony::{file_format::OutputFormat, Cosmogony};
-use cosmogony_builder::{build_cosmogony, merger};
+use cosmogony::{
+    file_format, file_format::OutputFormat, Cosmogony, CosmogonyMetadata, Zone, ZoneType,
+};
+use cosmogony_builder::attributes::{AttributesJoin, JoinKey};
+use cosmogony_builder::{
+    build_cosmogony_with_config, check, dry_run, extract, fgb, gpkg, locate, merger, tiles,
+    AreaModel, BuildConfig, LabelFormat, NamesMergePolicy, DEFAULT_MAX_RAW_ADMIN_LEVEL,
+};
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use regex::Regex;
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 /// You can:
 ///
@@ -15,6 +23,14 @@ use std::path::PathBuf;
 ///
 /// * merge several cosmogonies into one (merge)
 ///
+/// * check the admin boundaries of an osm file (check)
+///
+/// * extract the subtree of a zone from a cosmogony (extract)
+///
+/// * render the zones of a cosmogony as vector tiles (tiles)
+///
+/// * locate some points in the zones of a cosmogony (locate)
+///
 /// Note: for retrocompatibility, if no subcommand is provided, the default one is `generate`
 ///
 /// So `cosmogony -i <osm-file> -o output file` if the same as
@@ -34,6 +50,33 @@ enum Args {
     /// into several non overlapping small ones
     #[clap(name = "merge")]
     Merge(MergeArgs),
+    /// Check boundaries subcommand
+    ///
+    /// Report the admin boundaries that cannot be built or are invalid
+    /// (one 'osm_id<TAB>name<TAB>reason' line per boundary),
+    /// without the typing, hierarchy and voronoi steps of the generation
+    #[clap(name = "check")]
+    Check(CheckArgs),
+    /// Extract subtree subcommand
+    ///
+    /// Extract a zone and all its descendants from a cosmogony file,
+    /// with new ids (the extracted root zone has no parent)
+    #[clap(name = "extract")]
+    Extract(ExtractArgs),
+    /// Vector tiles subcommand
+    ///
+    /// Render the zones' boundaries of a cosmogony file as MVT vector tiles
+    /// in an MBTiles file, with one layer per zone type
+    #[clap(name = "tiles")]
+    Tiles(TilesArgs),
+    /// Locate points subcommand
+    ///
+    /// Find the zone, city, state and country of each point of a CSV file
+    /// (with 'lon' and 'lat' columns), in a CSV file with the columns
+    /// 'lon,lat,zone_osm_id,zone_name,city_osm_id,city_name,state_osm_id,state_name,
+    /// country_osm_id,country_name' (empty for a point in no zone)
+    #[clap(name = "locate")]
+    Locate(LocateArgs),
 }
 
 #[derive(Debug, clap::Parser)]
@@ -48,11 +91,26 @@ struct GenerateArgs {
         default_value = "cosmogony.json",
         help = concat!(
             "Output file name. Format will be deduced from the file extension. ",
-            "Accepted extensions are '.json', '.json.gz', '.jsonl', '.jsonl.gz'. ",
-            "'jsonl' is json stream where each line is a zone as json.",
+            "Accepted extensions are '.json', '.json.gz', '.json.zst', '.jsonl', '.jsonl.gz', ",
+            "'.jsonl.zst', '.fgb', '.geojsonl', '.geojsonseq', '.gpkg'. ",
+            "'jsonl' is json stream where each line is a zone as json, ",
+            "'gz' and 'zst' are compressed with gzip and zstd, ",
+            "'geojsonl' and 'geojsonseq' are json streams where each line is a GeoJSON Feature, ",
+            "'fgb' is FlatGeobuf (the zones without geometry are skipped). ",
+            "'gpkg' is GeoPackage (it cannot be written to the standard output). ",
+            "Use '-' to write to the standard output.",
         )
     )]
     output: String,
+    #[clap(
+        help = concat!(
+            "Output format, used when the output is '-' or has no recognizable extension. ",
+            "Accepted values are 'json', 'json.gz', 'json.zst', 'jsonl', 'jsonl.gz', 'jsonl.zst', ",
+            "'fgb', 'geojsonl', 'geojsonseq', 'gpkg'.",
+        ),
+        long
+    )]
+    format: Option<OutputFormat>,
     #[clap(help = "Do not display the stats", long)]
     no_stats: bool,
     #[clap(
@@ -73,6 +131,15 @@ struct GenerateArgs {
         long = "filter-langs"
     )]
     filter_langs_raw: Vec<String>,
+    #[clap(
+        help = concat!(
+            "File with the langs to generate labels for, one lang per line ",
+            "(blank lines and '#' comments are ignored). ",
+            "Merged with the '--filter-langs' values.",
+        ),
+        long = "filter-langs-file"
+    )]
+    filter_langs_file: Option<PathBuf>,
     #[clap(
         help = concat!(
             "Configure the max number of threads using during computations. ",
@@ -81,13 +148,490 @@ struct GenerateArgs {
         long
     )]
     num_threads: Option<usize>,
+    #[clap(
+        help = concat!(
+            "Dump the zones in this checkpoint file once their geometries are built. ",
+            "Accepted extensions are '.jsonl', '.jsonl.gz', '.jsonl.zst'.",
+        ),
+        long
+    )]
+    checkpoint: Option<PathBuf>,
+    #[clap(
+        help = concat!(
+            "Resume the build from a checkpoint file instead of building the zones from the osm file. ",
+            "The osm file is still read to get the places used by the voronoi.",
+        ),
+        long
+    )]
+    resume_from: Option<PathBuf>,
+    #[clap(
+        help = concat!(
+            "Keep, for each zone, all the zones containing it in the 'candidate_parents' field. ",
+            "This is only useful to debug the hierarchy.",
+        ),
+        long
+    )]
+    keep_inclusions: bool,
+    #[clap(
+        help = concat!(
+            "Do not output the zones' geometries, to get a lightweight hierarchy-only output. ",
+            "The bbox and center of the zones are kept.",
+        ),
+        long
+    )]
+    drop_geometry: bool,
+    #[clap(
+        help = concat!(
+            "Only keep the given tags in the zones' tags to shrink the output ",
+            "(the tags used to fill the zones' fields are always kept). ",
+            "A tag ending with '*' keeps all the tags with this prefix (eg: 'name:*'). ",
+            "Either repeat parameter or use comma-separated value.",
+        ),
+        long = "keep-tags"
+    )]
+    keep_tags_raw: Vec<String>,
+    #[clap(
+        help = "Gzip compression level (from 0: no compression, to 9: best compression), for gzipped outputs",
+        long,
+        value_parser = clap::value_parser!(u32).range(0..=9)
+    )]
+    compression_level: Option<u32>,
+    #[clap(
+        help = "Zstd compression level (from 1: fastest, to 22: best compression), for zstd outputs",
+        long,
+        default_value_t = zstd::DEFAULT_COMPRESSION_LEVEL,
+        value_parser = clap::value_parser!(i32).range(1..=22)
+    )]
+    zstd_level: i32,
+    #[clap(
+        help = concat!(
+            "Keep the zones that cannot be typed (but with a known country) ",
+            "as non administrative zones instead of dropping them. ",
+            "Note: as non administrative zones, they cannot be the parent of another zone.",
+        ),
+        long
+    )]
+    keep_untyped: bool,
+    #[clap(
+        help = concat!(
+            "Keep the zones' names by lang in the output, to be able to recompute the labels ",
+            "from the generated file. Note: this significantly increases the output size.",
+        ),
+        long
+    )]
+    keep_international_names: bool,
+    #[clap(
+        help = concat!(
+            "Sort the zones by zone type, country and osm id, to get a reproducible output. ",
+            "The zones are reindexed, so their ids are their position in the output.",
+        ),
+        long
+    )]
+    sort: bool,
+    #[clap(
+        help = concat!(
+            "Consider as countries the admin_level 2 zones without the 'ISO3166-1:alpha2' tag, ",
+            "if their country code can be found in other tags ",
+            "('ISO3166-1', 'is_in:country_code' or 'country_code_fr').",
+        ),
+        long
+    )]
+    country_fallback: bool,
+    #[clap(
+        help = "Separator between the zones of the hierarchy in the labels",
+        long,
+        default_value = ", "
+    )]
+    label_separator: String,
+    #[clap(help = "Do not display the zip codes in the labels", long)]
+    label_no_zip: bool,
+    #[clap(
+        help = concat!(
+            "Number of ancestors of the zones in the labels (0 for the zone's name only), ",
+            "all the ancestors are displayed by default",
+        ),
+        long
+    )]
+    label_depth: Option<usize>,
+    #[clap(
+        help = "Drop the zones with a degenerate boundary (with almost no area)",
+        long
+    )]
+    drop_degenerate: bool,
+    #[clap(
+        help = concat!(
+            "Include the disputed boundaries ('disputed=yes' or 'boundary=disputed'), ",
+            "skipped by default since they overlap other zones. ",
+            "A disputed zone cannot be the parent of a non disputed zone, but the hierarchy ",
+            "of the zones in a disputed area can still be wrong.",
+        ),
+        long
+    )]
+    include_disputed: bool,
+    #[clap(
+        help = concat!(
+            "Only output the zones of the given types (eg: 'city,suburb'). ",
+            "The labels still contain all the ancestors, and the parent of a zone is ",
+            "its nearest ancestor of one of the given types. ",
+            "Either repeat parameter or use comma-separated value.",
+        ),
+        long = "only-types"
+    )]
+    only_types_raw: Vec<String>,
+    #[clap(
+        help = concat!(
+            "The 'place' values of the nodes used to build the voronoi geometries ",
+            "(default: 'city,town,village,suburb,quarter,neighbourhood'). ",
+            "The 'hamlet' and 'isolated_dwelling' places are typed like the villages, ",
+            "they build a voronoi in the zones larger than a city (the ones in a city are ignored). ",
+            "Either repeat parameter or use comma-separated value.",
+        ),
+        long = "voronoi-place-types"
+    )]
+    voronoi_place_types_raw: Vec<String>,
+    #[clap(
+        help = concat!(
+            "Only build the voronoi geometries of the suburb and neighbourhood places in a city ",
+            "(by default, a place outside of any city splits a larger zone)",
+        ),
+        long
+    )]
+    voronoi_city_parents_only: bool,
+    #[clap(
+        help = concat!(
+            "Keep, for each zone, how its admin_level has been determined in the ",
+            "'admin_level_source' field (the relation's tag, its label node's tag, ",
+            "inferred from the other tags or defaulted). ",
+            "This is only useful to audit the typing.",
+        ),
+        long
+    )]
+    keep_provenance: bool,
+    #[clap(
+        help = concat!(
+            "Type the zones sequentially, to get ordered logs and a lower memory peak. ",
+            "Useful to debug the typing of a zone.",
+        ),
+        long
+    )]
+    single_threaded_typing: bool,
+    #[clap(
+        help = concat!(
+            "Only keep the zones of the given countries, by country code (eg: 'FR,DE'). ",
+            "The zones of the other countries are neither typed nor split by the voronoi, ",
+            "and the zones of an unknown country are dropped. ",
+            "Either repeat parameter or use comma-separated value.",
+        ),
+        long = "countries"
+    )]
+    countries_raw: Vec<String>,
+    #[clap(
+        help = concat!(
+            "CSV file with external attributes to add to the zones' tags (prefixed by 'ext:'). ",
+            "The rows are joined to the zones with a key column, see '--attributes-join-key'.",
+        ),
+        long
+    )]
+    attributes: Option<PathBuf>,
+    #[clap(
+        help = "Zone field to join the attributes on, either 'osm_id' or 'wikidata'",
+        long,
+        default_value = "wikidata"
+    )]
+    attributes_join_key: JoinKey,
+    #[clap(
+        help = concat!(
+            "Column of the attributes file with the join key ",
+            "(by default, the name of the join key: 'osm_id' or 'wikidata').",
+        ),
+        long
+    )]
+    attributes_key_column: Option<String>,
+    #[clap(
+        help = concat!(
+            "Maximum raw osm 'admin_level' (the osm tag, not the zone type) of the zones. ",
+            "The zones above are dropped before their typing (see '--clamp-raw-admin-level').",
+        ),
+        long,
+        default_value_t = DEFAULT_MAX_RAW_ADMIN_LEVEL
+    )]
+    max_raw_admin_level: u32,
+    #[clap(
+        help = "Clamp the raw osm 'admin_level' above '--max-raw-admin-level' to the max, instead of dropping the zones",
+        long
+    )]
+    clamp_raw_admin_level: bool,
+    #[clap(
+        help = concat!(
+            "Add the version and timestamp of the osm relations to the zones ",
+            "('osm_version' and 'osm_timestamp'). Note: the pbf is read once more.",
+        ),
+        long
+    )]
+    keep_osm_meta: bool,
+    #[clap(
+        help = concat!(
+            "Merge the adjacent zones with the same name, type and parent into one zone ",
+            "(their boundaries, zip codes and tags are merged)",
+        ),
+        long
+    )]
+    merge_same_name_siblings: bool,
+    #[clap(
+        help = "Periodically log the progress of the zones' typing and voronoi, with an ETA",
+        long
+    )]
+    progress: bool,
+    #[clap(
+        help = concat!(
+            "Keep the administrative boundaries without 'admin_level', with a level inferred ",
+            "from their 'border_type', 'place' or name (they are skipped if no level can be inferred)",
+        ),
+        long
+    )]
+    infer_missing_admin_level: bool,
+    #[clap(
+        help = concat!(
+            "Only keep the zones whose center is in the country with this country code ",
+            "(for a single country extract sliced from a multi-country pbf)",
+        ),
+        long
+    )]
+    only_country: Option<String>,
+    #[clap(
+        help = concat!(
+            "Skip the zones without geometry in a GeoPackage output ",
+            "(by default they are written with a NULL geometry)",
+        ),
+        long
+    )]
+    gpkg_skip_no_geometry: bool,
+    #[clap(
+        help = concat!(
+            "The osm ids of the objects to skip, like 'relation:123' ",
+            "(to exclude some broken relations, pending their fix in osm). ",
+            "Either repeat parameter or use comma-separated value.",
+        ),
+        long = "exclude-osm-ids"
+    )]
+    exclude_osm_ids_raw: Vec<String>,
+    #[clap(
+        help = concat!(
+            "For the countries and the states, use the 'admin_centre' node tagged as a capital ",
+            "('capital=yes' or 'capital=<admin_level>') as the zone's center",
+        ),
+        long
+    )]
+    center_from_capital: bool,
+    #[clap(
+        help = concat!(
+            "GeoJSON file with the polygon(s) to clip the zones with. The zones' boundaries are ",
+            "intersected with it, and the zones outside of it are dropped",
+        ),
+        long
+    )]
+    clip: Option<PathBuf>,
+    #[clap(
+        help = concat!(
+            "Only read the zones of the osm file and search their countries, to check the input ",
+            "and the configuration: the zones' counts and the countries found are printed, ",
+            "and no output is written",
+        ),
+        long
+    )]
+    dry_run: bool,
+    #[clap(
+        help = concat!(
+            "Which 'name:*' tag is kept when a relation and its label node have the same one, ",
+            "either 'relation-wins' or 'label-wins'",
+        ),
+        long,
+        default_value = "relation-wins"
+    )]
+    names_merge_policy: NamesMergePolicy,
+    #[clap(
+        help = concat!(
+            "Only output the zones whose name (or one of their 'name:*' names) matches this regex. ",
+            "The labels still contain all the ancestors, and the parent of a zone is ",
+            "its nearest kept ancestor.",
+        ),
+        long = "name-filter"
+    )]
+    name_filter_raw: Option<String>,
+    #[clap(
+        help = concat!(
+            "Directory of libpostal rules ('<country_code>.yaml' files) replacing the embedded ",
+            "rules of their countries. The other countries keep the embedded rules.",
+        ),
+        long
+    )]
+    rules_override_dir: Option<PathBuf>,
+    #[clap(
+        help = concat!(
+            "Move the center nodes (admin_centre or label) outside of their zone's boundary ",
+            "to a point in the boundary",
+        ),
+        long
+    )]
+    relocate_outside_centers: bool,
+    #[clap(
+        help = concat!(
+            "How the zones' area is computed, either 'spherical' (faster) or 'ellipsoidal' ",
+            "(more accurate, the difference is well under 1% for most zones)",
+        ),
+        long,
+        default_value = "ellipsoidal"
+    )]
+    area_model: AreaModel,
+    #[clap(
+        help = concat!(
+            "Write one file per country in the '--output-dir' directory (eg: 'FR.jsonl.gz'), ",
+            "instead of a single output. The zones without country are in 'unknown.<ext>'. ",
+            "The format is given by '--format' (default: 'jsonl.gz'). The zones whose parent ",
+            "is in another country have no parent in their country's file.",
+        ),
+        long,
+        requires = "output_dir"
+    )]
+    split_by_country: bool,
+    #[clap(
+        help = "The directory of the files of '--split-by-country'",
+        long,
+        requires = "split_by_country"
+    )]
+    output_dir: Option<PathBuf>,
+    #[clap(
+        help = concat!(
+            "Give the wikidata id of their center node (admin_centre or label) ",
+            "to the cities without one",
+        ),
+        long
+    )]
+    inherit_center_wikidata: bool,
+    #[clap(
+        help = concat!(
+            "Read back the output file(s) once written, and fail if the number of zones ",
+            "differs or if a zone's parent is not in the file. ",
+            "Only the json formats can be validated, not the standard output.",
+        ),
+        long
+    )]
+    validate_output: bool,
+    #[clap(
+        help = concat!(
+            "Include the maritime boundaries ('boundary=maritime', like the exclusive ",
+            "economic zones) as 'maritime' zones. They are not in the hierarchy of the land ",
+            "zones (they have no parent and are the parent of no zone), and they can overlap them.",
+        ),
+        long
+    )]
+    include_maritime: bool,
+    #[clap(
+        help = concat!(
+            "Keep, for each zone generated with a voronoi, whether its boundary has been ",
+            "modified by the subtraction of the existing zones, and how many zones have been ",
+            "subtracted, in the 'voronoi_debug' field. ",
+            "This is only useful to diagnose the voronoi artifacts (like slivers).",
+        ),
+        long
+    )]
+    keep_voronoi_debug: bool,
+    #[clap(
+        help = concat!(
+            "Use the 'admin_level' of the label node of the relations ",
+            "without a valid 'admin_level' tag.",
+        ),
+        long
+    )]
+    admin_level_from_label_node: bool,
+}
+
+fn split_comma_separated(values: &[String]) -> Vec<String> {
+    values
+        .iter()
+        .flat_map(|val| val.split(',').map(String::from))
+        .collect()
+}
+
+fn is_valid_lang(lang: &str) -> bool {
+    let mut subtags = lang.split(['-', '_']);
+    let primary_ok = subtags.next().is_some_and(|primary| {
+        (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic())
+    });
+    primary_ok
+        && subtags
+            .all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// read the langs of a file, one per line, ignoring the blank lines and the comments.
+// The invalid langs are skipped with a warning.
+fn read_langs_file(path: &Path) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("unable to read langs file {}: {}", path.display(), e))?;
+    Ok(content
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or_default().trim())
+        .filter(|l| !l.is_empty())
+        .filter(|l| {
+            let valid = is_valid_lang(l);
+            if !valid {
+                log::warn!("ignoring invalid lang '{}' in {}", l, path.display());
+            }
+            valid
+        })
+        .map(String::from)
+        .collect())
 }
 
 impl GenerateArgs {
-    fn filter_langs(&self) -> Vec<String> {
-        self.filter_langs_raw
+    fn filter_langs(&self) -> Result<Vec<String>> {
+        let mut langs = split_comma_separated(&self.filter_langs_raw);
+        if let Some(path) = &self.filter_langs_file {
+            for lang in read_langs_file(path)? {
+                if !langs.contains(&lang) {
+                    langs.push(lang);
+                }
+            }
+        }
+        Ok(langs)
+    }
+
+    fn keep_tags(&self) -> Vec<String> {
+        split_comma_separated(&self.keep_tags_raw)
+    }
+
+    fn only_types(&self) -> Result<Vec<ZoneType>> {
+        split_comma_separated(&self.only_types_raw)
             .iter()
-            .flat_map(|val| val.split(',').map(String::from))
+            .map(|t| t.parse::<ZoneType>())
+            .collect()
+    }
+
+    fn name_filter(&self) -> Result<Option<Regex>> {
+        self.name_filter_raw
+            .as_deref()
+            .map(|r| Regex::new(r).map_err(|e| anyhow!("invalid name filter '{}': {}", r, e)))
+            .transpose()
+    }
+
+    fn exclude_osm_ids(&self) -> Result<Vec<String>> {
+        split_comma_separated(&self.exclude_osm_ids_raw)
+            .into_iter()
+            .map(|osm_id| {
+                let valid = osm_id.split_once(':').is_some_and(|(osm_type, id)| {
+                    ["node", "way", "relation"].contains(&osm_type)
+                        && !id.is_empty()
+                        && id.chars().all(|c| c.is_ascii_digit())
+                });
+                if valid {
+                    Ok(osm_id)
+                } else {
+                    Err(anyhow!(
+                        "invalid osm id '{}', expected like 'relation:123'",
+                        osm_id
+                    ))
+                }
+            })
             .collect()
     }
 }
@@ -103,14 +647,92 @@ struct MergeArgs {
         long = "output",
         default_value = "cosmogony.jsonl",
         help = r#"Output file name. Format will be deduced from the file extension.
-    Accepted extensions are '.jsonl', '.jsonl.gz' (no json or json.gz)
+    Accepted extensions are '.jsonl', '.jsonl.gz', '.jsonl.zst' (no json, json.gz or json.zst)
     'jsonl' is json stream, each line is a zone as json
     "#
     )]
     output: PathBuf,
+    #[clap(
+        help = "Gzip compression level (from 0: no compression, to 9: best compression), for gzipped outputs",
+        long,
+        value_parser = clap::value_parser!(u32).range(0..=9)
+    )]
+    compression_level: Option<u32>,
+    #[clap(
+        help = "Zstd compression level (from 1: fastest, to 22: best compression), for zstd outputs",
+        long,
+        default_value_t = zstd::DEFAULT_COMPRESSION_LEVEL,
+        value_parser = clap::value_parser!(i32).range(1..=22)
+    )]
+    zstd_level: i32,
+    #[clap(
+        help = concat!(
+            "Append the zones to the output if it already exists, instead of overwriting it. ",
+            "The new zones' ids follow the ones of the existing zones. ",
+            "Note: a zone cannot have a parent in another file.",
+        ),
+        long
+    )]
+    append: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+struct CheckArgs {
+    /// OSM PBF file.
+    #[clap(short, long)]
+    input: PathBuf,
+    #[clap(help = "Exit with an error if an invalid boundary is found", long)]
+    strict: bool,
+}
+
+#[derive(Debug, clap::Parser)]
+struct ExtractArgs {
+    /// Cosmogony file to extract the zones from
+    #[clap(short, long)]
+    input: PathBuf,
+    /// osm_id of the root zone of the subtree (eg: 'relation:407489')
+    #[clap(short, long)]
+    root: String,
+    /// Output file name, accepted extensions are '.jsonl', '.jsonl.gz' and '.jsonl.zst'
+    #[clap(short, long, default_value = "cosmogony.jsonl")]
+    output: PathBuf,
+}
+
+#[derive(Debug, clap::Parser)]
+struct TilesArgs {
+    /// Cosmogony file to render
+    #[clap(short, long)]
+    input: PathBuf,
+    /// Minimum zoom level of the tiles
+    #[clap(long, default_value_t = 0)]
+    min_zoom: u8,
+    /// Maximum zoom level of the tiles (at most 18)
+    #[clap(long, default_value_t = 10)]
+    max_zoom: u8,
+    /// Output MBTiles file, overwritten if it exists
+    #[clap(short, long, default_value = "cosmogony.mbtiles")]
+    output: PathBuf,
+}
+
+#[derive(Debug, clap::Parser)]
+struct LocateArgs {
+    /// Cosmogony file with the zones' geometries
+    #[clap(short, long)]
+    input: PathBuf,
+    /// CSV file with the points to locate, with a 'lon' and a 'lat' column
+    #[clap(short, long)]
+    points: PathBuf,
+    /// Output CSV file, with one row per point
+    #[clap(short, long, default_value = "located.csv")]
+    output: PathBuf,
+}
+
+fn compression(level: Option<u32>) -> Compression {
+    level.map(Compression::new).unwrap_or_default()
 }
 
 fn to_json_stream(mut writer: impl std::io::Write, cosmogony: &Cosmogony) -> Result<()> {
+    file_format::write_schema_marker(&mut writer)?;
     for z in &cosmogony.zones {
         serde_json::to_writer(&mut writer, z)?;
         writer.write_all(b"\n")?;
@@ -121,36 +743,204 @@ fn to_json_stream(mut writer: impl std::io::Write, cosmogony: &Cosmogony) -> Res
     Ok(())
 }
 
+// write each zone as a GeoJSON Feature on its own line.
+// There is no schema marker, so that each line is a valid Feature for the GeoJSON tools
+fn to_geojson_stream(mut writer: impl std::io::Write, cosmogony: &Cosmogony) -> Result<()> {
+    for z in &cosmogony.zones {
+        serde_json::to_writer(&mut writer, &z.to_geojson_feature())?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
 fn serialize_cosmogony(
     cosmogony: &Cosmogony,
     output_file: String,
     format: OutputFormat,
+    compression: Compression,
+    zstd_level: i32,
+    gpkg_skip_no_geometry: bool,
 ) -> Result<()> {
-    log::info!("writing the output file {}", output_file);
-    let file = File::create(output_file)?;
-    let stream = BufWriter::new(file);
+    let stream = || -> Result<BufWriter<Box<dyn Write>>> {
+        let writer: Box<dyn Write> = if output_file == "-" {
+            log::info!("writing the output to stdout");
+            Box::new(std::io::stdout().lock())
+        } else {
+            log::info!("writing the output file {}", output_file);
+            Box::new(File::create(&output_file)?)
+        };
+        Ok(BufWriter::new(writer))
+    };
     match format {
         OutputFormat::JsonGz => {
-            let e = GzEncoder::new(stream, Compression::default());
+            let e = GzEncoder::new(stream()?, compression);
             serde_json::to_writer(e, cosmogony)?;
         }
         OutputFormat::Json => {
-            serde_json::to_writer(stream, cosmogony)?;
+            serde_json::to_writer(stream()?, cosmogony)?;
         }
         OutputFormat::JsonStream => {
-            to_json_stream(stream, cosmogony)?;
+            to_json_stream(stream()?, cosmogony)?;
         }
         OutputFormat::JsonStreamGz => {
-            let e = GzEncoder::new(stream, Compression::default());
+            let e = GzEncoder::new(stream()?, compression);
             to_json_stream(e, cosmogony)?;
         }
+        OutputFormat::JsonZst => {
+            let mut e = zstd::stream::write::Encoder::new(stream()?, zstd_level)?;
+            serde_json::to_writer(&mut e, cosmogony)?;
+            e.finish()?;
+        }
+        OutputFormat::JsonStreamZst => {
+            let mut e = zstd::stream::write::Encoder::new(stream()?, zstd_level)?;
+            to_json_stream(&mut e, cosmogony)?;
+            e.finish()?;
+        }
+        OutputFormat::GeoJsonSeq => {
+            to_geojson_stream(stream()?, cosmogony)?;
+        }
+        OutputFormat::FlatGeobuf => {
+            fgb::write_flatgeobuf(&cosmogony.zones, stream()?)?;
+        }
+        OutputFormat::GeoPackage => {
+            // a geopackage is a sqlite database, written directly in its file
+            if output_file == "-" {
+                return Err(anyhow!(
+                    "a geopackage cannot be written to the standard output"
+                ));
+            }
+            log::info!("writing the output file {}", output_file);
+            gpkg::write_geopackage(
+                &cosmogony.zones,
+                Path::new(&output_file),
+                gpkg_skip_no_geometry,
+            )?;
+        }
     };
     Ok(())
 }
 
+// write the zones of each country in its own file of the output directory
+#[allow(clippy::too_many_arguments)]
+fn serialize_by_country(
+    zones: Vec<Zone>,
+    meta: &CosmogonyMetadata,
+    output_dir: &Path,
+    format: OutputFormat,
+    compression: Compression,
+    zstd_level: i32,
+    gpkg_skip_no_geometry: bool,
+    validate: bool,
+) -> Result<()> {
+    std::fs::create_dir_all(output_dir)?;
+    for (country, zones) in extract::split_by_country(zones) {
+        let output_file = output_dir.join(format!("{}{}", country, format.extension()));
+        let nb_zones = zones.len();
+        // the stats of the build are kept, the ones of the zones are those of the country
+        let mut country_cosmogony = Cosmogony {
+            zones,
+            meta: meta.clone(),
+        };
+        country_cosmogony.recompute_stats();
+        serialize_cosmogony(
+            &country_cosmogony,
+            output_file.to_string_lossy().into_owned(),
+            format.clone(),
+            compression,
+            zstd_level,
+            gpkg_skip_no_geometry,
+        )?;
+        if validate {
+            validate_output(&output_file, nb_zones)?;
+        }
+    }
+    Ok(())
+}
+
+// the formats that can be read back to validate the output
+fn can_validate(format: &OutputFormat) -> bool {
+    matches!(
+        format,
+        OutputFormat::Json
+            | OutputFormat::JsonGz
+            | OutputFormat::JsonZst
+            | OutputFormat::JsonStream
+            | OutputFormat::JsonStreamGz
+            | OutputFormat::JsonStreamZst
+    )
+}
+
+// read back a written file, and check that it holds the expected number of zones
+// and that all their parents are in the file
+fn validate_output(output_file: &Path, expected_nb_zones: usize) -> Result<()> {
+    log::info!("validating the output file {}", output_file.display());
+    let mut ids = HashSet::new();
+    let mut parents = vec![];
+    for zone in cosmogony::read_zones_from_file(output_file)? {
+        let zone = zone.map_err(|e| {
+            anyhow!(
+                "invalid output file {}, a zone cannot be read back: {}",
+                output_file.display(),
+                e
+            )
+        })?;
+        ids.insert(zone.id);
+        if let Some(parent) = zone.parent {
+            parents.push((zone.osm_id, parent));
+        }
+    }
+    if ids.len() != expected_nb_zones {
+        return Err(anyhow!(
+            "invalid output file {}: {} zones were written but {} are read back",
+            output_file.display(),
+            expected_nb_zones,
+            ids.len()
+        ));
+    }
+    let dangling: Vec<_> = parents
+        .iter()
+        .filter(|(_, parent)| !ids.contains(parent))
+        .collect();
+    if let Some((osm_id, parent)) = dangling.first() {
+        return Err(anyhow!(
+            "invalid output file {}: {} zones have a parent that is not in the file \
+             (eg: the parent {} of {})",
+            output_file.display(),
+            dangling.len(),
+            parent.index,
+            osm_id
+        ));
+    }
+    Ok(())
+}
+
 fn cosmogony(args: GenerateArgs) -> Result<()> {
-    let format = OutputFormat::from_filename(&args.output)?;
-    let filter_langs = args.filter_langs();
+    let format =
+        OutputFormat::from_filename(&args.output).or_else(|err| args.format.clone().ok_or(err))?;
+    let filter_langs = args.filter_langs()?;
+    let keep_tags = args.keep_tags();
+    let only_types = args.only_types()?;
+    let exclude_osm_ids = args.exclude_osm_ids()?;
+    let name_filter = args.name_filter()?;
+    let split_format = args.format.clone().unwrap_or(OutputFormat::JsonStreamGz);
+
+    if args.validate_output {
+        // fail before the (long) generation if the output cannot be validated
+        let output_format = if args.split_by_country {
+            &split_format
+        } else {
+            &format
+        };
+        if !args.split_by_country && args.output == "-" {
+            return Err(anyhow!("the standard output cannot be validated"));
+        }
+        if !can_validate(output_format) {
+            return Err(anyhow!(
+                "the '{}' format cannot be validated, only the json ones can be",
+                output_format.extension()
+            ));
+        }
+    }
 
     if let Some(num_threads) = args.num_threads {
         rayon::ThreadPoolBuilder::new()
@@ -159,14 +949,98 @@ fn cosmogony(args: GenerateArgs) -> Result<()> {
             .map_err(|err| anyhow!("could not init rayon's global thread pool: {err}"))?;
     }
 
-    let cosmogony = build_cosmogony(
-        args.input,
-        args.country_code,
-        args.disable_voronoi,
-        &filter_langs,
-    )?;
+    let config = BuildConfig {
+        pbf_path: args.input.into(),
+        country_code: args.country_code,
+        disable_voronoi: args.disable_voronoi,
+        filter_langs,
+        checkpoint: args.checkpoint,
+        resume_from: args.resume_from,
+        keep_inclusions: args.keep_inclusions,
+        keep_tags,
+        keep_untyped: args.keep_untyped,
+        keep_international_names: args.keep_international_names,
+        sort: args.sort,
+        country_fallback: args.country_fallback,
+        label_format: LabelFormat {
+            separator: args.label_separator,
+            include_zip: !args.label_no_zip,
+            depth: args.label_depth,
+            ..Default::default()
+        },
+        drop_degenerate: args.drop_degenerate,
+        include_disputed: args.include_disputed,
+        only_types,
+        voronoi_place_types: split_comma_separated(&args.voronoi_place_types_raw),
+        voronoi_city_parents_only: args.voronoi_city_parents_only,
+        keep_provenance: args.keep_provenance,
+        single_threaded_typing: args.single_threaded_typing,
+        countries: split_comma_separated(&args.countries_raw),
+        attributes: args.attributes.map(|path| {
+            let join = AttributesJoin::new(path, args.attributes_join_key);
+            match args.attributes_key_column {
+                Some(key_column) => join.key_column(key_column),
+                None => join,
+            }
+        }),
+        max_raw_admin_level: Some(args.max_raw_admin_level),
+        clamp_raw_admin_level: args.clamp_raw_admin_level,
+        keep_osm_meta: args.keep_osm_meta,
+        merge_same_name_siblings: args.merge_same_name_siblings,
+        progress: args.progress,
+        infer_missing_admin_level: args.infer_missing_admin_level,
+        only_country: args.only_country,
+        exclude_osm_ids,
+        center_from_capital: args.center_from_capital,
+        clip: args.clip,
+        names_merge_policy: args.names_merge_policy,
+        name_filter,
+        rules_override_dir: args.rules_override_dir,
+        relocate_outside_centers: args.relocate_outside_centers,
+        area_model: args.area_model,
+        inherit_center_wikidata: args.inherit_center_wikidata,
+        include_maritime: args.include_maritime,
+        keep_voronoi_debug: args.keep_voronoi_debug,
+        admin_level_from_label_node: args.admin_level_from_label_node,
+    };
+    if args.dry_run {
+        let report = dry_run(&config)?;
+        print!("{}", report);
+        return Ok(());
+    }
+    let mut cosmogony = build_cosmogony_with_config(&config)?;
 
-    serialize_cosmogony(&cosmogony, args.output, format)?;
+    if args.drop_geometry {
+        // all the geometry based computation have been done, we can safely drop them
+        log::info!("dropping the zones' geometries");
+        cosmogony.zones.iter_mut().for_each(|z| z.boundary = None);
+    }
+
+    match args.output_dir {
+        Some(output_dir) if args.split_by_country => serialize_by_country(
+            std::mem::take(&mut cosmogony.zones),
+            &cosmogony.meta,
+            &output_dir,
+            split_format,
+            compression(args.compression_level),
+            args.zstd_level,
+            args.gpkg_skip_no_geometry,
+            args.validate_output,
+        )?,
+        _ => {
+            serialize_cosmogony(
+                &cosmogony,
+                args.output.clone(),
+                format,
+                compression(args.compression_level),
+                args.zstd_level,
+                args.gpkg_skip_no_geometry,
+            )?;
+            if args.validate_output {
+                validate_output(Path::new(&args.output), cosmogony.zones.len())?;
+            }
+        }
+    }
 
     if !args.no_stats {
         log::info!(
@@ -178,10 +1052,45 @@ fn cosmogony(args: GenerateArgs) -> Result<()> {
     Ok(())
 }
 
+fn check(args: CheckArgs) -> Result<()> {
+    let issues = check::check_pbf(&args.input)?;
+    let mut stdout = std::io::stdout().lock();
+    for issue in &issues {
+        writeln!(stdout, "{}", issue)?;
+    }
+    log::info!("{} invalid boundaries found", issues.len());
+
+    if args.strict && !issues.is_empty() {
+        return Err(anyhow!("{} invalid boundaries found", issues.len()));
+    }
+    Ok(())
+}
+
 fn run(args: Args) -> Result<()> {
     match args {
-        Args::Merge(merge_args) => merger::merge_cosmogony(&merge_args.files, &merge_args.output),
+        Args::Merge(merge_args) => merger::merge_cosmogony(
+            &merge_args.files,
+            &merge_args.output,
+            compression(merge_args.compression_level),
+            merge_args.zstd_level,
+            merge_args.append,
+        ),
         Args::Generate(gen_args) => cosmogony(gen_args),
+        Args::Check(check_args) => check(check_args),
+        Args::Extract(extract_args) => extract::extract_cosmogony(
+            &extract_args.input,
+            &extract_args.root,
+            &extract_args.output,
+        ),
+        Args::Tiles(tiles_args) => tiles::render_tiles(
+            &tiles_args.input,
+            tiles_args.min_zoom,
+            tiles_args.max_zoom,
+            &tiles_args.output,
+        ),
+        Args::Locate(locate_args) => {
+            locate::locate_csv(&locate_args.input, &locate_args.points, &locate_args.output)
+        }
     }
 }
 
@@ -0,0 +1,403 @@
+use crate::attributes::AttributesJoin;
+use crate::{AreaModel, LabelFormat, NamesMergePolicy};
+use cosmogony::ZoneType;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// The default maximum raw osm `admin_level` of the zones, see [`BuildConfig::max_raw_admin_level`]
+pub const DEFAULT_MAX_RAW_ADMIN_LEVEL: u32 = 11;
+
+/// Configuration of a cosmogony build
+///
+/// Only the osm pbf file is mandatory, all the other options have a default value
+/// (the one of the command line):
+///
+/// ```no_run
+/// use cosmogony_builder::{build_cosmogony_with_config, BuildConfig};
+///
+/// let config = BuildConfig::new("luxembourg.osm.pbf")
+///     .country_code("lu")
+///     .disable_voronoi(true)
+///     .filter_langs(vec!["fr".into(), "de".into()]);
+/// let cosmogony = build_cosmogony_with_config(&config).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct BuildConfig {
+    /// The osm pbf file to build the cosmogony from
+    pub pbf_path: PathBuf,
+    /// Country code to use if the pbf file does not contain any country
+    pub country_code: Option<String>,
+    /// Prevent the voronoi geometries computation and generation
+    pub disable_voronoi: bool,
+    /// Only generate the labels for those langs (all langs if empty)
+    pub filter_langs: Vec<String>,
+    /// Dump the zones in this checkpoint file once their geometries are built
+    pub checkpoint: Option<PathBuf>,
+    /// Read the zones from this checkpoint instead of building them from the pbf file.
+    /// The pbf is then only read to get the places needed by the voronoi
+    /// (and is not read at all if the voronoi are disabled).
+    pub resume_from: Option<PathBuf>,
+    /// Keep all the zones containing a zone in its `candidate_parents` (for debugging purpose)
+    pub keep_inclusions: bool,
+    /// Only keep those tags in the zones' tags (all tags if empty),
+    /// in addition to the ones used to fill the zones' fields
+    pub keep_tags: Vec<String>,
+    /// Keep the zones that cannot be typed (but with a known country)
+    /// as `NonAdministrative` zones instead of dropping them
+    pub keep_untyped: bool,
+    /// Keep the zones' `international_names` in the output, so that the labels can be
+    /// recomputed from a loaded cosmogony (at the cost of a bigger output)
+    pub keep_international_names: bool,
+    /// Sort the zones by zone type, country and osm id to get a reproducible output.
+    /// The zones are reindexed, so their ids are their position in the output
+    pub sort: bool,
+    /// Consider as countries the admin_level 2 zones without the ISO3166-1:alpha2 tag,
+    /// if their country code can be found in other tags (like 'is_in:country_code')
+    pub country_fallback: bool,
+    /// Format of the zones' labels
+    pub label_format: LabelFormat,
+    /// Drop the zones with a degenerate boundary (almost no area)
+    pub drop_degenerate: bool,
+    /// Include the disputed boundaries (`disputed=yes` or `boundary=disputed`).
+    /// A disputed zone cannot be the parent of a non disputed zone, but since several
+    /// countries can claim the same area, the hierarchy of the zones inside it can be wrong
+    pub include_disputed: bool,
+    /// Only output the zones of those types (all the zones if empty).
+    /// The zones are filtered once the hierarchy and the labels are built, and the parent
+    /// of an output zone is its nearest ancestor with one of those types
+    pub only_types: Vec<ZoneType>,
+    /// The `place` values of the nodes used to build the voronoi
+    /// (the [`DEFAULT_PLACE_TYPES`](crate::DEFAULT_PLACE_TYPES) if empty).
+    /// The `hamlet` and `isolated_dwelling` places are typed like the villages, but unlike them
+    /// they build a voronoi in the zones larger than a city (the ones in a city are ignored)
+    pub voronoi_place_types: Vec<String>,
+    /// Only split the cities in voronoi cells for the suburb and neighbourhood places.
+    /// By default, those places are a part of the smallest zone containing them, so a place
+    /// outside of any city splits a larger zone (like a state district)
+    pub voronoi_city_parents_only: bool,
+    /// Keep how the zones' admin_level have been determined in their `admin_level_source`
+    /// (for debugging purpose)
+    pub keep_provenance: bool,
+    /// Type the zones sequentially instead of in parallel,
+    /// to get ordered logs and a lower memory peak (but a slower typing)
+    pub single_threaded_typing: bool,
+    /// Only keep the zones of those countries, by country code (all the countries if empty).
+    /// The zones of the other countries, or of an unknown country, are not typed, and are dropped
+    pub countries: Vec<String>,
+    /// Add the attributes of a CSV file to the tags of the matching zones,
+    /// once the cosmogony is built (see [`AttributesJoin`])
+    pub attributes: Option<AttributesJoin>,
+    /// Maximum raw osm `admin_level` (the `admin_level` tag, not the `ZoneType`) of the zones
+    /// (no maximum if None, [`DEFAULT_MAX_RAW_ADMIN_LEVEL`] by default).
+    /// The zones above are dropped before their typing
+    pub max_raw_admin_level: Option<u32>,
+    /// Clamp the raw osm `admin_level` above `max_raw_admin_level` to the max,
+    /// instead of dropping the zones
+    pub clamp_raw_admin_level: bool,
+    /// Add the version and timestamp of the osm relations to the zones built from them
+    /// (`osm_version` and `osm_timestamp`). The metadata are read in an additional pass over the pbf
+    pub keep_osm_meta: bool,
+    /// Merge the adjacent zones with the same name, type and parent into one zone
+    /// (for the places split in several osm relations)
+    pub merge_same_name_siblings: bool,
+    /// Periodically log the progress of the typing and of the voronoi, with an ETA
+    pub progress: bool,
+    /// Keep the administrative boundaries without `admin_level`, with a level inferred
+    /// from their `border_type`, `place` or name (they are skipped if no level can be inferred)
+    pub infer_missing_admin_level: bool,
+    /// Only keep the zones whose center is in the country with this country code
+    /// (for a single country extract sliced from a multi-country pbf).
+    /// The build fails if the pbf does not contain this country
+    pub only_country: Option<String>,
+    /// The osm ids (like `relation:123`) of the objects to skip, before their geometry is built
+    /// (to exclude some broken relations, pending their fix in osm)
+    pub exclude_osm_ids: Vec<String>,
+    /// For the countries and the states (once the zones are typed), use the
+    /// `admin_centre` node tagged as a capital as the zone's center
+    /// (the zones without such a node keep their usual center)
+    pub center_from_capital: bool,
+    /// A GeoJSON file with the polygon(s) to clip the zones with: the zones' boundaries are
+    /// intersected with it, and the zones outside of it are dropped
+    pub clip: Option<PathBuf>,
+    /// Which `name:*` tag is kept when a relation and its label node have the same one
+    pub names_merge_policy: NamesMergePolicy,
+    /// Only keep the zones whose name (or one of their `name:*` names) matches this regex
+    /// (done after the build, so the labels and the depths still reflect the whole hierarchy)
+    pub name_filter: Option<Regex>,
+    /// A directory of libpostal rules (`<country_code>.yaml` files) replacing the embedded
+    /// rules of their countries (the other countries keep the embedded rules)
+    pub rules_override_dir: Option<PathBuf>,
+    /// Move the center nodes outside of their zone's boundary to a point in the boundary
+    /// (they are reported in the stats either way)
+    pub relocate_outside_centers: bool,
+    /// How the zones' area is computed, on a sphere or on the WGS84 ellipsoid
+    pub area_model: AreaModel,
+    /// Give the wikidata id of their center node to the cities without one
+    pub inherit_center_wikidata: bool,
+    /// Include the maritime boundaries (`boundary=maritime`, like the exclusive economic zones)
+    /// as `Maritime` zones. They are not in the hierarchy of the land zones, and they can
+    /// overlap them
+    pub include_maritime: bool,
+    /// Keep, for the zones generated with a voronoi, how their boundary has been cut
+    /// by the existing zones in their `voronoi_debug` (for debugging purpose)
+    pub keep_voronoi_debug: bool,
+    /// Use the `admin_level` of the label node of the relations without a valid `admin_level`
+    pub admin_level_from_label_node: bool,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            pbf_path: Default::default(),
+            country_code: Default::default(),
+            disable_voronoi: Default::default(),
+            filter_langs: Default::default(),
+            checkpoint: Default::default(),
+            resume_from: Default::default(),
+            keep_inclusions: Default::default(),
+            keep_tags: Default::default(),
+            keep_untyped: Default::default(),
+            keep_international_names: Default::default(),
+            sort: Default::default(),
+            country_fallback: Default::default(),
+            label_format: Default::default(),
+            drop_degenerate: Default::default(),
+            include_disputed: Default::default(),
+            only_types: Default::default(),
+            voronoi_place_types: Default::default(),
+            voronoi_city_parents_only: Default::default(),
+            keep_provenance: Default::default(),
+            single_threaded_typing: Default::default(),
+            countries: Default::default(),
+            attributes: Default::default(),
+            max_raw_admin_level: Some(DEFAULT_MAX_RAW_ADMIN_LEVEL),
+            clamp_raw_admin_level: Default::default(),
+            keep_osm_meta: Default::default(),
+            merge_same_name_siblings: Default::default(),
+            progress: Default::default(),
+            infer_missing_admin_level: Default::default(),
+            only_country: Default::default(),
+            exclude_osm_ids: Default::default(),
+            center_from_capital: Default::default(),
+            clip: Default::default(),
+            names_merge_policy: Default::default(),
+            name_filter: Default::default(),
+            rules_override_dir: Default::default(),
+            relocate_outside_centers: Default::default(),
+            area_model: Default::default(),
+            inherit_center_wikidata: Default::default(),
+            include_maritime: Default::default(),
+            keep_voronoi_debug: Default::default(),
+            admin_level_from_label_node: Default::default(),
+        }
+    }
+}
+
+impl BuildConfig {
+    pub fn new(pbf_path: impl Into<PathBuf>) -> Self {
+        Self {
+            pbf_path: pbf_path.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn country_code(mut self, country_code: impl Into<String>) -> Self {
+        self.country_code = Some(country_code.into());
+        self
+    }
+
+    pub fn disable_voronoi(mut self, disable_voronoi: bool) -> Self {
+        self.disable_voronoi = disable_voronoi;
+        self
+    }
+
+    pub fn filter_langs(mut self, filter_langs: Vec<String>) -> Self {
+        self.filter_langs = filter_langs;
+        self
+    }
+
+    pub fn checkpoint(mut self, checkpoint: impl AsRef<Path>) -> Self {
+        self.checkpoint = Some(checkpoint.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn resume_from(mut self, resume_from: impl AsRef<Path>) -> Self {
+        self.resume_from = Some(resume_from.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn keep_inclusions(mut self, keep_inclusions: bool) -> Self {
+        self.keep_inclusions = keep_inclusions;
+        self
+    }
+
+    pub fn keep_tags(mut self, keep_tags: Vec<String>) -> Self {
+        self.keep_tags = keep_tags;
+        self
+    }
+
+    pub fn keep_untyped(mut self, keep_untyped: bool) -> Self {
+        self.keep_untyped = keep_untyped;
+        self
+    }
+
+    pub fn keep_international_names(mut self, keep_international_names: bool) -> Self {
+        self.keep_international_names = keep_international_names;
+        self
+    }
+
+    pub fn sort(mut self, sort: bool) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    pub fn country_fallback(mut self, country_fallback: bool) -> Self {
+        self.country_fallback = country_fallback;
+        self
+    }
+
+    pub fn label_format(mut self, label_format: LabelFormat) -> Self {
+        self.label_format = label_format;
+        self
+    }
+
+    pub fn drop_degenerate(mut self, drop_degenerate: bool) -> Self {
+        self.drop_degenerate = drop_degenerate;
+        self
+    }
+
+    pub fn include_disputed(mut self, include_disputed: bool) -> Self {
+        self.include_disputed = include_disputed;
+        self
+    }
+
+    pub fn only_types(mut self, only_types: Vec<ZoneType>) -> Self {
+        self.only_types = only_types;
+        self
+    }
+
+    pub fn voronoi_place_types(mut self, voronoi_place_types: Vec<String>) -> Self {
+        self.voronoi_place_types = voronoi_place_types;
+        self
+    }
+
+    pub fn voronoi_city_parents_only(mut self, voronoi_city_parents_only: bool) -> Self {
+        self.voronoi_city_parents_only = voronoi_city_parents_only;
+        self
+    }
+
+    pub fn keep_provenance(mut self, keep_provenance: bool) -> Self {
+        self.keep_provenance = keep_provenance;
+        self
+    }
+
+    pub fn single_threaded_typing(mut self, single_threaded_typing: bool) -> Self {
+        self.single_threaded_typing = single_threaded_typing;
+        self
+    }
+
+    pub fn countries(mut self, countries: Vec<String>) -> Self {
+        self.countries = countries;
+        self
+    }
+
+    pub fn attributes(mut self, attributes: AttributesJoin) -> Self {
+        self.attributes = Some(attributes);
+        self
+    }
+
+    pub fn max_raw_admin_level(mut self, max_raw_admin_level: u32) -> Self {
+        self.max_raw_admin_level = Some(max_raw_admin_level);
+        self
+    }
+
+    pub fn clamp_raw_admin_level(mut self, clamp_raw_admin_level: bool) -> Self {
+        self.clamp_raw_admin_level = clamp_raw_admin_level;
+        self
+    }
+
+    pub fn keep_osm_meta(mut self, keep_osm_meta: bool) -> Self {
+        self.keep_osm_meta = keep_osm_meta;
+        self
+    }
+
+    pub fn merge_same_name_siblings(mut self, merge_same_name_siblings: bool) -> Self {
+        self.merge_same_name_siblings = merge_same_name_siblings;
+        self
+    }
+
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn infer_missing_admin_level(mut self, infer_missing_admin_level: bool) -> Self {
+        self.infer_missing_admin_level = infer_missing_admin_level;
+        self
+    }
+
+    pub fn only_country(mut self, country_code: impl Into<String>) -> Self {
+        self.only_country = Some(country_code.into());
+        self
+    }
+
+    pub fn exclude_osm_ids(mut self, exclude_osm_ids: Vec<String>) -> Self {
+        self.exclude_osm_ids = exclude_osm_ids;
+        self
+    }
+
+    pub fn center_from_capital(mut self, center_from_capital: bool) -> Self {
+        self.center_from_capital = center_from_capital;
+        self
+    }
+
+    pub fn clip(mut self, clip: impl AsRef<Path>) -> Self {
+        self.clip = Some(clip.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn names_merge_policy(mut self, names_merge_policy: NamesMergePolicy) -> Self {
+        self.names_merge_policy = names_merge_policy;
+        self
+    }
+
+    pub fn name_filter(mut self, name_filter: Regex) -> Self {
+        self.name_filter = Some(name_filter);
+        self
+    }
+
+    pub fn rules_override_dir(mut self, rules_override_dir: impl AsRef<Path>) -> Self {
+        self.rules_override_dir = Some(rules_override_dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn relocate_outside_centers(mut self, relocate_outside_centers: bool) -> Self {
+        self.relocate_outside_centers = relocate_outside_centers;
+        self
+    }
+
+    pub fn area_model(mut self, area_model: AreaModel) -> Self {
+        self.area_model = area_model;
+        self
+    }
+
+    pub fn inherit_center_wikidata(mut self, inherit_center_wikidata: bool) -> Self {
+        self.inherit_center_wikidata = inherit_center_wikidata;
+        self
+    }
+
+    pub fn include_maritime(mut self, include_maritime: bool) -> Self {
+        self.include_maritime = include_maritime;
+        self
+    }
+
+    pub fn keep_voronoi_debug(mut self, keep_voronoi_debug: bool) -> Self {
+        self.keep_voronoi_debug = keep_voronoi_debug;
+        self
+    }
+
+    pub fn admin_level_from_label_node(mut self, admin_level_from_label_node: bool) -> Self {
+        self.admin_level_from_label_node = admin_level_from_label_node;
+        self
+    }
+}
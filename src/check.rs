@@ -0,0 +1,163 @@
+//! Check of the admin boundaries' geometries, without building the whole cosmogony
+//!
+//! The boundaries are checked as they are in OSM, before the normalization done
+//! during a build, so the boundaries that a build has to fix are also reported.
+
+use crate::geometry::is_degenerate;
+use crate::{is_admin, read_pbf};
+use anyhow::Error;
+use geo_types::MultiPolygon;
+use geos::{Geom, Geometry};
+use osm_boundaries_utils::build_boundary;
+use osmpbfreader::{OsmId, OsmObj};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// An admin zone whose boundary cannot be used as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundaryIssue {
+    pub osm_id: String,
+    pub name: String,
+    pub reason: String,
+}
+
+impl fmt::Display for BoundaryIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}\t{}", self.osm_id, self.name, self.reason)
+    }
+}
+
+// the reason why a boundary cannot be used as is, if any
+fn check_boundary(boundary: Option<&MultiPolygon<f64>>) -> Option<String> {
+    let boundary = match boundary {
+        Some(boundary) => boundary,
+        None => return Some("the boundary cannot be built (missing or unclosed ways)".into()),
+    };
+    let geom = match Geometry::try_from(boundary) {
+        Ok(geom) => geom,
+        Err(err) => {
+            return Some(format!(
+                "impossible to convert the boundary to geos: {}",
+                err
+            ))
+        }
+    };
+    if !geom.is_valid() {
+        return Some(
+            geom.is_valid_reason()
+                .unwrap_or_else(|_| "invalid geometry".into()),
+        );
+    }
+    if is_degenerate(boundary) {
+        return Some("degenerate boundary (almost no area)".into());
+    }
+    None
+}
+
+/// Check the boundaries of all the admin relations of parsed osm objects
+pub fn check_boundaries(pbf: &BTreeMap<OsmId, OsmObj>) -> Vec<BoundaryIssue> {
+    pbf.values()
+        .filter(|obj| is_admin(obj))
+        .filter_map(|obj| obj.relation())
+        .filter_map(|relation| {
+            // the raw boundary is checked, without the unclosed rings fix done during a build
+            let boundary = build_boundary(relation, pbf);
+            check_boundary(boundary.as_ref()).map(|reason| BoundaryIssue {
+                osm_id: format!("relation:{}", relation.id.0),
+                name: relation
+                    .tags
+                    .get("name")
+                    .map_or_else(String::new, |name| name.to_string()),
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// Check the boundaries of all the admin relations of an osm pbf file
+pub fn check_pbf(pbf_path: &Path) -> Result<Vec<BoundaryIssue>, Error> {
+    let parsed_pbf = read_pbf(pbf_path, is_admin)?;
+    Ok(check_boundaries(&parsed_pbf))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_boundaries, check_boundary};
+    use geo_types::{LineString, MultiPolygon, Polygon};
+
+    fn polygon(coords: Vec<(f64, f64)>) -> MultiPolygon<f64> {
+        MultiPolygon(vec![Polygon::new(LineString::from(coords), vec![])])
+    }
+
+    #[test]
+    fn valid_boundary() {
+        let square = polygon(vec![(0., 0.), (0., 1.), (1., 1.), (1., 0.), (0., 0.)]);
+        assert_eq!(check_boundary(Some(&square)), None);
+    }
+
+    #[test]
+    fn invalid_boundaries() {
+        assert!(check_boundary(None).unwrap().contains("cannot be built"));
+
+        // a self intersecting ring
+        let bowtie = polygon(vec![(0., 0.), (1., 1.), (1., 0.), (0., 1.), (0., 0.)]);
+        assert!(check_boundary(Some(&bowtie))
+            .unwrap()
+            .contains("Self-intersection"));
+
+        let flat = polygon(vec![(0., 0.), (1., 0.), (2., 0.), (0., 0.)]);
+        assert!(check_boundary(Some(&flat)).is_some());
+    }
+
+    #[test]
+    fn unclosed_rings_are_reported() {
+        use osmpbfreader::objects::{Node, NodeId, Ref, Relation, RelationId, Tags, Way, WayId};
+        use osmpbfreader::{OsmId, OsmObj};
+        use std::collections::BTreeMap;
+
+        let node = |id: i64, lon: f64, lat: f64| {
+            OsmObj::Node(Node {
+                id: NodeId(id),
+                tags: Tags::new(),
+                decimicro_lat: (lat * 1e7) as i32,
+                decimicro_lon: (lon * 1e7) as i32,
+            })
+        };
+        let mut tags = Tags::new();
+        tags.insert("boundary".into(), "administrative".into());
+        tags.insert("admin_level".into(), "8".into());
+        tags.insert("name".into(), "Gap Town".into());
+        // a ring whose ends are a few meters apart, closed during a build
+        let objects: BTreeMap<OsmId, OsmObj> = vec![
+            node(1, 0., 0.),
+            node(2, 0., 0.01),
+            node(3, 0.01, 0.01),
+            node(4, 0.01, 0.),
+            node(5, 0.00001, 0.),
+            OsmObj::Way(Way {
+                id: WayId(1),
+                tags: Tags::new(),
+                nodes: (1..=5).map(NodeId).collect(),
+            }),
+            OsmObj::Relation(Relation {
+                id: RelationId(1),
+                tags,
+                refs: vec![Ref {
+                    member: OsmId::Way(WayId(1)),
+                    role: "outer".into(),
+                }],
+            }),
+        ]
+        .into_iter()
+        .map(|o| (o.id(), o))
+        .collect();
+
+        // the raw boundary is checked, so the ring is reported as unclosed
+        let issues = check_boundaries(&objects);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].osm_id, "relation:1");
+        assert_eq!(issues[0].name, "Gap Town");
+        assert!(issues[0].reason.contains("cannot be built"));
+    }
+}
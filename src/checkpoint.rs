@@ -0,0 +1,75 @@
+// A checkpoint is a dump of the zones once their geometries have been built,
+// before the ontology creation (typing, hierarchy, voronoi, labels).
+// Since building the boundaries is the most expensive part of the process,
+// resuming from a checkpoint makes iterating on the ontology logic far faster.
+
+use anyhow::{anyhow, Context, Error};
+use cosmogony::{file_format, file_format::OutputFormat, read_zones_from_file, Zone};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::path::Path;
+
+/// Dump the zones to a checkpoint file
+///
+/// The checkpoint reuses the json stream format (each line is a zone),
+/// so only '.jsonl', '.jsonl.gz' and '.jsonl.zst' files are accepted
+pub fn write_checkpoint(zones: &[Zone], path: &Path) -> Result<(), Error> {
+    info!(
+        "writing a checkpoint of {} zones in {}",
+        zones.len(),
+        path.display()
+    );
+    let format = OutputFormat::from_filename(path)?;
+    let file = std::fs::File::create(path).context("impossible to create the checkpoint")?;
+    let stream = std::io::BufWriter::new(file);
+    match format {
+        OutputFormat::JsonStream => write_zones(stream, zones),
+        OutputFormat::JsonStreamGz => {
+            write_zones(GzEncoder::new(stream, Compression::default()), zones)
+        }
+        OutputFormat::JsonStreamZst => {
+            let mut e = zstd::stream::write::Encoder::new(stream, 0)?;
+            write_zones(&mut e, zones)?;
+            e.finish()?;
+            Ok(())
+        }
+        OutputFormat::Json
+        | OutputFormat::JsonGz
+        | OutputFormat::JsonZst
+        | OutputFormat::FlatGeobuf
+        | OutputFormat::GeoJsonSeq
+        | OutputFormat::GeoPackage => Err(anyhow!(
+            "a checkpoint can only be a '.jsonl', '.jsonl.gz' or '.jsonl.zst' file"
+        )),
+    }
+}
+
+fn write_zones(mut writer: impl Write, zones: &[Zone]) -> Result<(), Error> {
+    file_format::write_schema_marker(&mut writer)?;
+    for z in zones {
+        serde_json::to_writer(&mut writer, z)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read the zones from a checkpoint file
+///
+/// The zones' indexes must be contiguous (the index of a zone is its position in the checkpoint),
+/// since the ontology creation uses them to lookup the zones
+pub fn read_checkpoint(path: &Path) -> Result<Vec<Zone>, Error> {
+    info!("reading the checkpoint {}", path.display());
+    let zones: Vec<Zone> = read_zones_from_file(path)?.collect::<Result<_, _>>()?;
+    if let Some((pos, z)) = zones.iter().enumerate().find(|(pos, z)| z.id.index != *pos) {
+        return Err(anyhow!(
+            "invalid checkpoint, the zone {} has the index {} instead of {}",
+            z.osm_id,
+            z.id.index,
+            pos
+        ));
+    }
+    info!("{} zones read from the checkpoint", zones.len());
+    Ok(zones)
+}
@@ -0,0 +1,126 @@
+use crate::country_finder::CountryFinder;
+use crate::hierarchy_builder::{build_hierarchy, compute_depths, find_inclusions};
+use crate::zone_typer::ZoneTyper;
+use crate::{compute_labels, LabelFormat};
+use anyhow::Error;
+use cosmogony::{reindex_zones, Cosmogony, Zone, ZoneIndex};
+
+/// Extension methods of a [`Cosmogony`] that need the builder (like the libpostal rules)
+pub trait CosmogonyExt {
+    /// Type again the zones of a loaded cosmogony with new rules
+    ///
+    /// The zones' inclusions are computed again from their boundaries, then the zones are
+    /// retyped, their hierarchy is rebuilt and their labels are computed again
+    /// (with the given langs and label format, like in a build).
+    /// This way, new libpostal rules can be tried without parsing the osm file again.
+    ///
+    /// Only the zones built from an osm boundary with an admin_level are retyped: the
+    /// generated zones (like the voronoi places) and the other zones (like the non
+    /// administrative and the maritime ones) keep their type.
+    /// The generated zones also keep their parent (unless it cannot be typed anymore),
+    /// and they cannot be the parent of another zone, like in a build.
+    ///
+    /// The retyped zones that cannot be typed by the new rules are kept, without a type
+    /// (so they cannot be the parent of another zone).
+    /// The zones keep their country, it is only searched for the zones without one.
+    ///
+    /// Note: this is a no-op for a cosmogony without geometries (generated with `--drop-geometry`),
+    /// since the inclusions cannot be computed.
+    /// The international labels are computed from the international names, so they are lost
+    /// if the cosmogony has been generated without `--keep-international-names`.
+    /// The stats are not updated.
+    fn retype(
+        &mut self,
+        typer: &ZoneTyper,
+        filter_langs: &[String],
+        label_format: &LabelFormat,
+    ) -> Result<(), Error>;
+}
+
+// the zones typed by the rules in a build
+fn is_typed_by_rules(z: &Zone) -> bool {
+    z.admin_level.is_some() && !z.is_generated && !z.is_maritime()
+}
+
+impl CosmogonyExt for Cosmogony {
+    fn retype(
+        &mut self,
+        typer: &ZoneTyper,
+        filter_langs: &[String],
+        label_format: &LabelFormat,
+    ) -> Result<(), Error> {
+        let zones = &mut self.zones;
+        if zones.iter().all(|z| z.boundary.is_none()) {
+            warn!("the zones have no geometry, they cannot be retyped");
+            return Ok(());
+        }
+        // the ids of the zones must be their positions to compute the inclusions
+        reindex_zones(zones);
+
+        info!("retyping {} zones", zones.len());
+        let (inclusions, _) = find_inclusions(zones);
+        let country_finder = CountryFinder::init(zones, typer, false);
+        // None for the zones that are not retyped
+        let types: Vec<_> = zones
+            .iter()
+            .map(|z| {
+                if !is_typed_by_rules(z) {
+                    return None;
+                }
+                let inclusions = &inclusions[z.id.index];
+                let country_code = z
+                    .country_code
+                    .clone()
+                    .or_else(|| country_finder.find_zone_country(z, inclusions));
+                Some(country_code.map(|country_code| {
+                    match typer.get_zone_type(z, &country_code, inclusions, zones) {
+                        Ok(zone_type) => (country_code, Some(zone_type)),
+                        Err(_) => {
+                            debug!("{} cannot be typed with the new rules", z.osm_id);
+                            (country_code, None)
+                        }
+                    }
+                }))
+            })
+            .collect();
+        zones
+            .iter_mut()
+            .zip(types)
+            .for_each(|(z, typing)| match typing {
+                None => {}
+                Some(Some((country_code, zone_type))) => {
+                    z.country_code = Some(country_code);
+                    z.zone_type = zone_type;
+                }
+                Some(None) => z.zone_type = None,
+            });
+
+        // the generated zones are added after the hierarchy in a build,
+        // so they are not candidate parents, and they keep the parent they were generated in
+        let generated_parents: Vec<Option<Option<ZoneIndex>>> = zones
+            .iter()
+            .map(|z| z.is_generated.then_some(z.parent))
+            .collect();
+        let inclusions = inclusions
+            .into_iter()
+            .map(|candidates| {
+                candidates
+                    .into_iter()
+                    .filter(|c| !zones[c.index].is_generated)
+                    .collect()
+            })
+            .collect();
+        build_hierarchy(zones, inclusions);
+        for (i, parent) in generated_parents.into_iter().enumerate() {
+            if let Some(Some(parent)) = parent {
+                if zones[parent.index].zone_type.is_some() {
+                    zones[i].set_parent(Some(parent));
+                }
+            }
+        }
+        compute_depths(zones);
+
+        compute_labels(zones, filter_langs, label_format);
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::hierarchy_builder::ZonesTree;
+use cosmogony::{Cosmogony, Zone, ZoneIndex};
+use geo_types::Point;
+use geos::Geometry;
+use prepared::PreparedBoundary;
+use std::collections::HashMap;
+
+mod prepared {
+    use geos::{GResult, Geom, Geometry, PreparedGeometry};
+
+    /// The boundary of a zone, prepared for the point queries
+    ///
+    /// The GEOS prepared geometry borrows the geometry it has been built from, so the two
+    /// cannot be stored together without erasing this borrow (see [`PreparedBoundary::new`]).
+    /// They are thus kept in this module, with private fields: the geometry is never exposed
+    /// nor moved out, and the prepared geometry is declared first so that it is dropped first.
+    pub(super) struct PreparedBoundary {
+        prepared: PreparedGeometry<'static>,
+        _boundary: Geometry<'static>,
+    }
+
+    impl PreparedBoundary {
+        pub(super) fn new(boundary: Geometry<'static>) -> GResult<Self> {
+            let prepared = boundary.to_prepared_geom()?;
+            // SAFETY: the prepared geometry only keeps the pointer to the GEOS geometry, which
+            // is allocated by GEOS and does not move with `boundary`. `boundary` is kept with
+            // it, is never mutated, and is dropped after it (fields drop in declaration order).
+            let prepared = unsafe {
+                std::mem::transmute::<PreparedGeometry<'_>, PreparedGeometry<'static>>(prepared)
+            };
+            Ok(PreparedBoundary {
+                prepared,
+                _boundary: boundary,
+            })
+        }
+
+        /// Is the point in the boundary (or on it)
+        pub(super) fn intersects(&self, point: &Geometry<'_>) -> bool {
+            self.prepared.intersects(point).unwrap_or(false)
+        }
+    }
+}
+
+/// A spatial index of the zones of a [`Cosmogony`], to find the zones containing a point
+///
+/// The rtree of the zones' bboxes and the prepared GEOS geometries of their boundaries are
+/// built once, so it is made for a lot of queries (like a batch reverse geocoding).
+/// The zones without boundary are not indexed.
+pub struct CosmogonyIndex<'a> {
+    cosmogony: &'a Cosmogony,
+    tree: ZonesTree,
+    boundaries: HashMap<ZoneIndex, PreparedBoundary>,
+}
+
+impl<'a> CosmogonyIndex<'a> {
+    /// Build the index of the zones of a cosmogony
+    pub fn build(cosmogony: &'a Cosmogony) -> Self {
+        info!("building the index of {} zones", cosmogony.zones.len());
+        let boundaries = cosmogony
+            .zones
+            .iter()
+            .filter_map(|z| {
+                let boundary: Geometry<'static> = z
+                    .boundary
+                    .as_ref()?
+                    .try_into()
+                    .map_err(|e| warn!("impossible to convert {} to geos: {}", z.osm_id, e))
+                    .ok()?;
+                let prepared = PreparedBoundary::new(boundary)
+                    .map_err(|e| warn!("impossible to prepare {}: {}", z.osm_id, e))
+                    .ok()?;
+                Some((z.id, prepared))
+            })
+            .collect();
+        let tree = cosmogony
+            .zones
+            .iter()
+            .filter(|z| z.boundary.is_some())
+            .collect();
+        CosmogonyIndex {
+            cosmogony,
+            tree,
+            boundaries,
+        }
+    }
+
+    /// All the zones containing a point (or with the point on their boundary),
+    /// in no particular order
+    pub fn query_all(&self, lon: f64, lat: f64) -> Vec<&'a Zone> {
+        let point: Geometry<'_> = match (&Point::new(lon, lat)).try_into() {
+            Ok(point) => point,
+            Err(e) => {
+                warn!("impossible to convert ({}, {}) to geos: {}", lon, lat, e);
+                return vec![];
+            }
+        };
+        self.tree
+            .fetch_point(lon, lat)
+            .into_iter()
+            .filter(|id| {
+                self.boundaries
+                    .get(id)
+                    .is_some_and(|b| b.intersects(&point))
+            })
+            .filter_map(|id| self.cosmogony.zone_by_id(id))
+            .collect()
+    }
+
+    /// The most precise zone containing a point, ie the deepest one in the hierarchy
+    pub fn query(&self, lon: f64, lat: f64) -> Option<&'a Zone> {
+        self.query_all(lon, lat).into_iter().max_by_key(|z| z.depth)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmogony::CosmogonyMetadata;
+    use geo::bounding_rect::BoundingRect;
+    use geo_types::{LineString, MultiPolygon, Polygon};
+
+    fn make_zone(index: usize, min: f64, max: f64, parent: Option<usize>) -> Zone {
+        let boundary = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![
+                (min, min),
+                (min, max),
+                (max, max),
+                (max, min),
+                (min, min),
+            ]),
+            vec![],
+        )]);
+        Zone {
+            id: ZoneIndex { index },
+            osm_id: format!("relation:{}", index),
+            parent: parent.map(|index| ZoneIndex { index }),
+            depth: parent.map_or(0, |_| 1),
+            bbox: boundary.bounding_rect(),
+            boundary: Some(boundary),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn query_points() {
+        let cosmogony = Cosmogony {
+            zones: vec![
+                make_zone(0, 0., 10., None),
+                make_zone(1, 2., 4., Some(0)),
+                Zone {
+                    id: ZoneIndex { index: 2 },
+                    osm_id: "node:2".into(),
+                    ..Default::default()
+                },
+            ],
+            meta: CosmogonyMetadata::default(),
+        };
+        let index = CosmogonyIndex::build(&cosmogony);
+        let query = |lon, lat| index.query(lon, lat).map(|z| z.osm_id.as_str());
+
+        assert_eq!(query(3., 3.), Some("relation:1"));
+        assert_eq!(query(5., 5.), Some("relation:0"));
+        // outside of all the zones
+        assert_eq!(query(11., 11.), None);
+        // the boundary is in the zone
+        assert_eq!(query(10., 5.), Some("relation:0"));
+
+        let mut all: Vec<_> = index
+            .query_all(3., 3.)
+            .into_iter()
+            .map(|z| z.osm_id.as_str())
+            .collect();
+        all.sort();
+        assert_eq!(all, vec!["relation:0", "relation:1"]);
+    }
+}
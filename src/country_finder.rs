@@ -10,34 +10,75 @@ pub struct Country {
     admin_level: Option<u32>,
 }
 
+// tags that can hold the country code of a country without the ISO3166-1:alpha2 tag
+const FALLBACK_COUNTRY_CODE_TAGS: [&str; 3] =
+    ["ISO3166-1", "is_in:country_code", "country_code_fr"];
+
 pub struct CountryFinder {
     countries: BTreeMap<ZoneIndex, Country>,
+    nb_rescued: usize,
+}
+
+// the country code of an admin_level 2 zone without the ISO3166-1:alpha2 tag
+fn fallback_country_code(z: &Zone) -> Option<String> {
+    if z.admin_level != Some(2) || z.tags.get(COUNTRY_CODE_TAG).is_some() {
+        return None;
+    }
+    FALLBACK_COUNTRY_CODE_TAGS
+        .iter()
+        .find_map(|tag| z.tags.get(*tag))
+        .map(|country_code| country_code.to_uppercase())
 }
 
 impl CountryFinder {
-    pub fn init(zones: &[Zone], typer: &ZoneTyper) -> Self {
+    /// If `country_fallback` is true, the admin_level 2 zones without the ISO3166-1:alpha2 tag
+    /// are also considered as countries if their country code can be found in other tags
+    pub fn init(zones: &[Zone], typer: &ZoneTyper, country_fallback: bool) -> Self {
+        let mut nb_rescued = 0;
+        let countries = zones
+            .iter()
+            // a maritime zone can have the country code of its country, but it is not a country
+            .filter(|z| !z.is_maritime())
+            .filter_map(|z| {
+                let country_code = z
+                    .tags
+                    .get(COUNTRY_CODE_TAG) // iso3166 code, should use capital letters
+                    .map(|country_code| country_code.to_uppercase())
+                    .filter(|country_code| typer.contains_rule(country_code));
+                let country_code = match country_code {
+                    Some(country_code) => country_code,
+                    None if country_fallback => {
+                        let country_code = fallback_country_code(z)
+                            .filter(|country_code| typer.contains_rule(country_code))?;
+                        info!(
+                            "{} has no {} tag, but is considered as the country {}",
+                            z.osm_id, COUNTRY_CODE_TAG, country_code
+                        );
+                        nb_rescued += 1;
+                        country_code
+                    }
+                    None => return None,
+                };
+                Some((
+                    z.id,
+                    Country {
+                        iso: country_code,
+                        admin_level: z.admin_level,
+                    },
+                ))
+            })
+            .collect();
         CountryFinder {
-            countries: zones
-                .iter()
-                .filter_map(|z| {
-                    z.tags
-                        .get(COUNTRY_CODE_TAG) // iso3166 code, should use capital letters
-                        .map(|country_code| country_code.to_uppercase())
-                        .filter(|country_code| typer.contains_rule(country_code))
-                        .map(|country_code| {
-                            (
-                                z.id,
-                                Country {
-                                    iso: country_code,
-                                    admin_level: z.admin_level,
-                                },
-                            )
-                        })
-                })
-                .collect(),
+            countries,
+            nb_rescued,
         }
     }
 
+    /// number of countries found thanks to the fallback
+    pub fn nb_rescued(&self) -> usize {
+        self.nb_rescued
+    }
+
     pub fn find_zone_country(&self, z: &Zone, inclusion: &[ZoneIndex]) -> Option<String> {
         inclusion
             .iter()
@@ -50,4 +91,51 @@ impl CountryFinder {
     pub fn is_empty(&self) -> bool {
         self.countries.is_empty()
     }
+
+    /// the zones found as countries, with their country code
+    pub fn countries(&self) -> impl Iterator<Item = (ZoneIndex, &str)> {
+        self.countries.iter().map(|(idx, c)| (*idx, c.iso.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CountryFinder;
+    use crate::zone_typer::ZoneTyper;
+    use cosmogony::{Zone, ZoneIndex};
+
+    fn make_zone(idx: usize, admin_level: u32, tags: Vec<(&str, &str)>) -> Zone {
+        Zone {
+            id: ZoneIndex { index: idx },
+            osm_id: format!("relation:{}", idx),
+            admin_level: Some(admin_level),
+            tags: tags
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn country_fallback_test() {
+        let typer = ZoneTyper::new().unwrap();
+        let zones = vec![
+            make_zone(0, 2, vec![("ISO3166-1:alpha2", "fr")]),
+            make_zone(1, 2, vec![("is_in:country_code", "de")]),
+            // only the admin_level 2 zones can be rescued
+            make_zone(2, 4, vec![("is_in:country_code", "es")]),
+        ];
+
+        let finder = CountryFinder::init(&zones, &typer, false);
+        assert_eq!(finder.find_zone_country(&zones[0], &[]), Some("FR".into()));
+        assert_eq!(finder.find_zone_country(&zones[1], &[]), None);
+        assert_eq!(finder.nb_rescued(), 0);
+
+        let finder = CountryFinder::init(&zones, &typer, true);
+        assert_eq!(finder.find_zone_country(&zones[0], &[]), Some("FR".into()));
+        assert_eq!(finder.find_zone_country(&zones[1], &[]), Some("DE".into()));
+        assert_eq!(finder.find_zone_country(&zones[2], &[]), None);
+        assert_eq!(finder.nb_rescued(), 1);
+    }
 }
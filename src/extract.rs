@@ -0,0 +1,309 @@
+//! Extraction of the subtree of a zone from an existing cosmogony
+//!
+//! The extracted zones are the root zone and all its descendants, with new ids
+//! (the root's parent being removed).
+//!
+//! The zones can also be split by country, see [`split_by_country`].
+
+use crate::hierarchy_builder::compute_depths;
+use crate::merger::to_json_stream;
+use anyhow::{anyhow, Result};
+use cosmogony::{file_format, file_format::OutputFormat, read_zones_from_file, Zone, ZoneIndex};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+/// The positions of the descendants of the zone at position `root`
+/// (the root included), in the zones' order
+pub fn descendants(zones: &[Zone], root: usize) -> BTreeSet<usize> {
+    let positions: HashMap<ZoneIndex, usize> = zones
+        .iter()
+        .enumerate()
+        .map(|(pos, z)| (z.id, pos))
+        .collect();
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (pos, z) in zones.iter().enumerate() {
+        if let Some(parent_pos) = z.parent.and_then(|p| positions.get(&p)) {
+            children.entry(*parent_pos).or_default().push(pos);
+        }
+    }
+
+    let mut subtree = BTreeSet::new();
+    let mut to_visit = vec![root];
+    while let Some(pos) = to_visit.pop() {
+        // the insertion check protects against cycles in the parents
+        if subtree.insert(pos) {
+            to_visit.extend(children.get(&pos).into_iter().flatten());
+        }
+    }
+    subtree
+}
+
+/// Extract the zone with the given osm_id and all its descendants
+///
+/// The extracted zones are reindexed, the root being the first one, without parent.
+/// The candidate parents outside of the subtree are removed, and the depths are computed
+/// again from the root.
+pub fn extract_subtree(zones: Vec<Zone>, root_osm_id: &str) -> Result<Vec<Zone>> {
+    let root = zones
+        .iter()
+        .position(|z| z.osm_id == root_osm_id)
+        .ok_or_else(|| anyhow!("no zone with the osm_id '{}'", root_osm_id))?;
+    let subtree = descendants(&zones, root);
+
+    let new_ids: HashMap<ZoneIndex, ZoneIndex> = std::iter::once(root)
+        .chain(subtree.iter().copied().filter(|pos| *pos != root))
+        .enumerate()
+        .map(|(index, pos)| (zones[pos].id, ZoneIndex { index }))
+        .collect();
+    let root_id = zones[root].id;
+    let mut extracted: Vec<Zone> = zones
+        .into_iter()
+        .enumerate()
+        .filter(|(pos, _)| subtree.contains(pos))
+        .map(|(_, mut z)| {
+            z.parent = if z.id == root_id {
+                None
+            } else {
+                z.parent.and_then(|p| new_ids.get(&p).copied())
+            };
+            z.candidate_parents = z
+                .candidate_parents
+                .iter()
+                .filter_map(|p| new_ids.get(p).copied())
+                .collect();
+            z.id = new_ids[&z.id];
+            z
+        })
+        .collect();
+    extracted.sort_by_key(|z| z.id);
+    compute_depths(&mut extracted);
+    Ok(extracted)
+}
+
+/// The group of the zones without country code in [`split_by_country`]
+pub const UNKNOWN_COUNTRY: &str = "unknown";
+
+/// Split the zones by country code (uppercased), the zones without country code
+/// being in the [`UNKNOWN_COUNTRY`] group
+///
+/// The zones of each country are reindexed, in their order. The parent of a zone is kept
+/// if it is in the same country, else it is removed (the zone is then a root of its country).
+/// The same goes for the candidate parents. The depths are computed again, in each country.
+pub fn split_by_country(zones: Vec<Zone>) -> BTreeMap<String, Vec<Zone>> {
+    let country = |z: &Zone| {
+        z.country_code
+            .as_ref()
+            .map_or_else(|| UNKNOWN_COUNTRY.to_string(), |c| c.to_uppercase())
+    };
+    let mut nb_zones: HashMap<String, usize> = HashMap::new();
+    let new_ids: HashMap<ZoneIndex, (String, ZoneIndex)> = zones
+        .iter()
+        .map(|z| {
+            let country = country(z);
+            let index = nb_zones.entry(country.clone()).or_default();
+            let new_id = ZoneIndex { index: *index };
+            *index += 1;
+            (z.id, (country, new_id))
+        })
+        .collect();
+    let id_in_country = |id: &ZoneIndex, country: &str| {
+        new_ids
+            .get(id)
+            .filter(|(c, _)| c == country)
+            .map(|(_, new_id)| *new_id)
+    };
+
+    let mut removed_parents = 0;
+    let mut by_country: BTreeMap<String, Vec<Zone>> = BTreeMap::new();
+    for mut z in zones {
+        let (country, new_id) = new_ids[&z.id].clone();
+        let parent = z.parent.and_then(|p| id_in_country(&p, &country));
+        if z.parent.is_some() && parent.is_none() {
+            debug!(
+                "the parent of {} is not in {}, it is removed",
+                z.osm_id, country
+            );
+            removed_parents += 1;
+        }
+        z.parent = parent;
+        z.candidate_parents = z
+            .candidate_parents
+            .iter()
+            .filter_map(|p| id_in_country(p, &country))
+            .collect();
+        z.id = new_id;
+        by_country.entry(country).or_default().push(z);
+    }
+    if removed_parents > 0 {
+        info!(
+            "{} zones have a parent in another country, their parent has been removed",
+            removed_parents
+        );
+    }
+    by_country
+        .values_mut()
+        .for_each(|zones| compute_depths(zones));
+    by_country
+}
+
+/// Extract the subtree of a zone of a cosmogony file into a streamed cosmogony file
+pub fn extract_cosmogony(input: &Path, root_osm_id: &str, output: &Path) -> Result<()> {
+    let format = OutputFormat::from_filename(output)?;
+    let zones = read_zones_from_file(input)?.collect::<Result<Vec<_>, _>>()?;
+    let nb_zones = zones.len();
+    let extracted = extract_subtree(zones, root_osm_id)?;
+    info!(
+        "{} zones extracted from the {} zones of {}",
+        extracted.len(),
+        nb_zones,
+        input.display()
+    );
+
+    // the output is only created once its format is known to be supported
+    let create_output = || -> Result<std::io::BufWriter<std::fs::File>> {
+        Ok(std::io::BufWriter::new(std::fs::File::create(output)?))
+    };
+    match format {
+        OutputFormat::JsonGz
+        | OutputFormat::Json
+        | OutputFormat::JsonZst
+        | OutputFormat::FlatGeobuf
+        | OutputFormat::GeoJsonSeq
+        | OutputFormat::GeoPackage => {
+            return Err(anyhow!(
+                "the extracted zones can only be written as jsonl/jsonl.gz/jsonl.zst"
+            ))
+        }
+        OutputFormat::JsonStream => {
+            let mut stream = create_output()?;
+            file_format::write_schema_marker(&mut stream)?;
+            to_json_stream(&mut stream, extracted.into_iter())?;
+        }
+        OutputFormat::JsonStreamGz => {
+            let mut e = GzEncoder::new(create_output()?, Compression::default());
+            file_format::write_schema_marker(&mut e)?;
+            to_json_stream(&mut e, extracted.into_iter())?;
+        }
+        OutputFormat::JsonStreamZst => {
+            let mut e = zstd::stream::write::Encoder::new(create_output()?, 0)?;
+            file_format::write_schema_marker(&mut e)?;
+            to_json_stream(&mut e, extracted.into_iter())?;
+            e.finish()?;
+        }
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_zone(index: usize, osm_id: &str, parent: Option<usize>) -> Zone {
+        Zone {
+            id: ZoneIndex { index },
+            osm_id: osm_id.into(),
+            parent: parent.map(|index| ZoneIndex { index }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn extract_a_subtree() {
+        let zone = |index, osm_id, parent, depth, candidate_parents: Vec<usize>| Zone {
+            depth,
+            candidate_parents: candidate_parents
+                .into_iter()
+                .map(|index| ZoneIndex { index })
+                .collect(),
+            ..make_zone(index, osm_id, parent)
+        };
+        // the ids are not the positions of the zones
+        let zones = vec![
+            zone(12, "city", Some(11), 2, vec![11, 13]),
+            zone(10, "country", None, 0, vec![]),
+            zone(11, "state", Some(10), 1, vec![10]),
+            zone(13, "other_state", Some(10), 1, vec![10]),
+            zone(14, "suburb", Some(12), 3, vec![12, 11]),
+            zone(15, "other_city", Some(13), 2, vec![13]),
+        ];
+
+        let extracted = extract_subtree(zones, "state").unwrap();
+        let summary: Vec<_> = extracted
+            .iter()
+            .map(|z| {
+                (
+                    z.id.index,
+                    z.osm_id.as_str(),
+                    z.parent.map(|p| p.index),
+                    z.depth,
+                    z.candidate_parents.iter().map(|p| p.index).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, "state", None, 0, vec![]),
+                (1, "city", Some(0), 1, vec![0]),
+                (2, "suburb", Some(1), 2, vec![1, 0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_an_unknown_root() {
+        let zones = vec![make_zone(0, "country", None)];
+        assert!(extract_subtree(zones, "unknown").is_err());
+    }
+
+    #[test]
+    fn split_zones_by_country() {
+        let zone = |index, osm_id, parent, country_code: Option<&str>, depth| Zone {
+            country_code: country_code.map(String::from),
+            depth,
+            ..make_zone(index, osm_id, parent)
+        };
+        let zones = vec![
+            zone(10, "fr", None, Some("fr"), 0),
+            zone(11, "de", None, Some("DE"), 0),
+            zone(12, "paris", Some(10), Some("fr"), 1),
+            // a zone whose parent is in another country
+            zone(13, "enclave", Some(11), Some("FR"), 1),
+            zone(14, "enclave district", Some(13), Some("FR"), 2),
+            zone(15, "sea", None, None, 0),
+        ];
+
+        let by_country = split_by_country(zones);
+        let summary = |country: &str| -> Vec<_> {
+            by_country[country]
+                .iter()
+                .map(|z| {
+                    (
+                        z.id.index,
+                        z.osm_id.as_str(),
+                        z.parent.map(|p| p.index),
+                        z.depth,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            by_country.keys().collect::<Vec<_>>(),
+            vec!["DE", "FR", UNKNOWN_COUNTRY]
+        );
+        // the depths follow the removal of the parents
+        assert_eq!(
+            summary("FR"),
+            vec![
+                (0, "fr", None, 0),
+                (1, "paris", Some(0), 1),
+                (2, "enclave", None, 0),
+                (3, "enclave district", Some(2), 1),
+            ]
+        );
+        assert_eq!(summary("DE"), vec![(0, "de", None, 0)]);
+        assert_eq!(summary(UNKNOWN_COUNTRY), vec![(0, "sea", None, 0)]);
+    }
+}
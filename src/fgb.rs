@@ -0,0 +1,98 @@
+//! FlatGeobuf export of the zones
+//!
+//! FlatGeobuf is an indexed binary format, well suited to stream large zone sets
+//! into web maps or GDAL. Since it needs a single geometry type, all the boundaries
+//! are written as MultiPolygon, and the zones without boundary are skipped.
+//!
+//! Note: a FlatGeobuf file cannot be streamed, so it is not supported by the merge.
+
+use anyhow::Result;
+use cosmogony::Zone;
+use flatgeobuf::{ColumnType, FgbWriter, GeometryType};
+use geozero::{ColumnValue, PropertyProcessor};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+// the columns of the properties of `Zone::geojson_properties`, except the `name:<lang>` ones
+const COLUMNS: [(&str, ColumnType); 11] = [
+    ("id", ColumnType::String),
+    ("osm_id", ColumnType::String),
+    ("name", ColumnType::String),
+    ("label", ColumnType::String),
+    ("zone_type", ColumnType::String),
+    ("admin_level", ColumnType::UInt),
+    ("country_code", ColumnType::String),
+    ("zip_codes", ColumnType::Json),
+    ("wikidata", ColumnType::String),
+    ("wikipedia", ColumnType::String),
+    ("website", ColumnType::String),
+];
+
+fn write_properties(
+    feature: &mut impl PropertyProcessor,
+    columns: &[(String, ColumnType)],
+    properties: &serde_json::Map<String, Value>,
+) -> Result<()> {
+    for (idx, (name, _)) in columns.iter().enumerate() {
+        match properties.get(name) {
+            None | Some(Value::Null) => continue,
+            Some(Value::String(s)) => feature.property(idx, name, &ColumnValue::String(s))?,
+            Some(Value::Number(n)) => {
+                let n = n.as_u64().unwrap_or_default() as u32;
+                feature.property(idx, name, &ColumnValue::UInt(n))?
+            }
+            Some(v) => feature.property(idx, name, &ColumnValue::Json(&v.to_string()))?,
+        };
+    }
+    Ok(())
+}
+
+/// Write the zones as FlatGeobuf features, with the same properties as the GeoJSON export
+pub fn write_flatgeobuf(zones: &[Zone], mut writer: impl Write) -> Result<()> {
+    // FlatGeobuf needs all the columns upfront, so we need all the langs of the labels
+    let langs: BTreeSet<&str> = zones
+        .iter()
+        .flat_map(|z| z.international_labels.keys().map(String::as_str))
+        .collect();
+    let columns: Vec<(String, ColumnType)> = COLUMNS
+        .iter()
+        .map(|(name, column_type)| (name.to_string(), *column_type))
+        .chain(
+            langs
+                .iter()
+                .map(|lang| (format!("name:{}", lang), ColumnType::String)),
+        )
+        .collect();
+
+    let mut fgb = FgbWriter::create("cosmogony", GeometryType::MultiPolygon)?;
+    for (name, column_type) in &columns {
+        fgb.add_column(name, *column_type, |_, col| col.nullable = true);
+    }
+
+    let mut nb_skipped = 0;
+    for zone in zones {
+        let boundary = match &zone.boundary {
+            Some(boundary) => boundary.clone(),
+            None => {
+                nb_skipped += 1;
+                continue;
+            }
+        };
+        let properties = zone.geojson_properties();
+        let mut res = Ok(());
+        fgb.add_feature_geom(geo_types::Geometry::MultiPolygon(boundary), |feature| {
+            res = write_properties(feature, &columns, &properties);
+        })?;
+        res?;
+    }
+    if nb_skipped > 0 {
+        info!(
+            "{} zones without boundary skipped in the flatgeobuf",
+            nb_skipped
+        );
+    }
+
+    fgb.write(&mut writer)?;
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use anyhow::{anyhow, Context, Result};
+use geo::{Area, CoordsIter};
+use geo_types::{MultiPolygon, Polygon};
+use geos::{Geom, Geometry};
+use std::path::Path;
+
+/// Convert a GEOS geometry to a MultiPolygon
+///
+/// The polygons of a `Polygon`, a `MultiPolygon` or a `GeometryCollection` (recursively)
+/// are flattened in a single MultiPolygon, without the empty polygons.
+/// The other geometries (points, lines) are skipped.
+/// Returns None if there is no polygon left.
+pub(crate) fn convert_to_geo(geom: Geometry<'_>) -> Result<Option<MultiPolygon<f64>>> {
+    let is_empty_poly = |poly: &geo::Polygon| poly.exterior().lines().next().is_none();
+
+    let geom: geo::Geometry<f64> = geom.try_into().context("failed to convert to geo")?;
+    let polys: Vec<_> = polygons(geom)
+        .into_iter()
+        .filter(|poly| !is_empty_poly(poly))
+        .collect();
+
+    Ok({
+        if polys.is_empty() {
+            None
+        } else {
+            Some(MultiPolygon(polys))
+        }
+    })
+}
+
+/// Compute the union of several boundaries with GEOS
+///
+/// Returns None if there is no boundary (or if the union is empty).
+pub(crate) fn union_boundaries(
+    boundaries: &[&MultiPolygon<f64>],
+) -> Result<Option<MultiPolygon<f64>>> {
+    let mut geoms = boundaries
+        .iter()
+        .map(|b| Geometry::try_from(*b).context("failed to convert to geos"));
+    let first = match geoms.next() {
+        Some(first) => first?,
+        None => return Ok(None),
+    };
+    let union = geoms.try_fold(first, |union, geom| {
+        union
+            .union(&geom?)
+            .context("failed to union the boundaries")
+    })?;
+    convert_to_geo(union)
+}
+
+/// Make sure a zone's boundary is a valid MultiPolygon
+///
+/// The invalid boundaries (self intersecting rings, disjoint outers with interleaved inners, ...)
+/// are fixed by GEOS (with a 0 buffer) and normalized back to a MultiPolygon.
+/// Returns true if the boundary has been modified.
+pub(crate) fn normalize_boundary(boundary: &mut Option<MultiPolygon<f64>>, osm_id: &str) -> bool {
+    let geom = match boundary.as_ref().map(Geometry::try_from) {
+        Some(Ok(geom)) => geom,
+        Some(Err(err)) => {
+            warn!(
+                "impossible to convert boundary of {} to geos: {}",
+                osm_id, err
+            );
+            return false;
+        }
+        None => return false,
+    };
+    if geom.is_valid() {
+        return false;
+    }
+
+    let normalized = geom
+        .buffer(0., 8)
+        .context("failed to buffer boundary")
+        .and_then(convert_to_geo);
+    match normalized {
+        Ok(normalized) => {
+            debug!("the boundary of {} has been normalized", osm_id);
+            *boundary = normalized;
+            true
+        }
+        Err(err) => {
+            warn!("impossible to normalize boundary of {}: {:?}", osm_id, err);
+            false
+        }
+    }
+}
+
+// below this area (in square degrees, so about 1m² at the equator), a boundary is useless
+const DEGENERATE_AREA_EPSILON: f64 = 1e-10;
+
+/// Check if a boundary is degenerate, ie if it has almost no area,
+/// or fewer than 4 coordinates (the minimum for a closed ring)
+pub(crate) fn is_degenerate(boundary: &MultiPolygon<f64>) -> bool {
+    boundary.exterior_coords_iter().count() < 4
+        || boundary.unsigned_area() < DEGENERATE_AREA_EPSILON
+}
+
+// all the polygons of a geometry (the other geometries are skipped)
+fn polygons(geom: geo::Geometry<f64>) -> Vec<Polygon<f64>> {
+    match geom {
+        geo::Geometry::Polygon(poly) => vec![poly],
+        geo::Geometry::MultiPolygon(multi_poly) => multi_poly.0,
+        geo::Geometry::GeometryCollection(geoms) => geoms.into_iter().flat_map(polygons).collect(),
+        _ => vec![],
+    }
+}
+
+/// Read the polygons of a GeoJSON file (a Geometry, a Feature or a FeatureCollection)
+/// as a single MultiPolygon, to be used to clip the zones
+pub(crate) fn read_clip_polygon(path: &Path) -> Result<MultiPolygon<f64>> {
+    let geojson: geojson::GeoJson = std::fs::read_to_string(path)
+        .with_context(|| format!("impossible to read {}", path.display()))?
+        .parse()
+        .with_context(|| format!("invalid geojson in {}", path.display()))?;
+    let geoms: geo_types::GeometryCollection<f64> = geojson::quick_collection(&geojson)?;
+    let clip: Vec<_> = geoms.into_iter().flat_map(polygons).collect();
+    if clip.is_empty() {
+        return Err(anyhow!("no polygon in {}", path.display()));
+    }
+    Ok(MultiPolygon(clip))
+}
+
+/// Intersect a boundary with a clip polygon with GEOS
+///
+/// Returns None if the intersection has no area (the boundary is outside of the polygon,
+/// or only touches it).
+pub(crate) fn clip_boundary(
+    boundary: &MultiPolygon<f64>,
+    clip: &Geometry<'_>,
+) -> Result<Option<MultiPolygon<f64>>> {
+    let geom = Geometry::try_from(boundary).context("failed to convert to geos")?;
+    let clipped = geom
+        .intersection(clip)
+        .context("failed to clip the boundary")?;
+    let clipped: geo::Geometry<f64> = clipped.try_into().context("failed to convert to geo")?;
+    let clipped = MultiPolygon(polygons(clipped));
+    Ok(if is_degenerate(&clipped) {
+        None
+    } else {
+        Some(clipped)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{clip_boundary, convert_to_geo, is_degenerate, normalize_boundary};
+    use geo_types::{LineString, MultiPolygon, Polygon};
+
+    fn square(min: f64, max: f64) -> Polygon<f64> {
+        Polygon::new(
+            LineString::from(vec![
+                (min, min),
+                (min, max),
+                (max, max),
+                (max, min),
+                (min, min),
+            ]),
+            vec![],
+        )
+    }
+
+    #[test]
+    fn valid_boundary_is_not_normalized() {
+        let mut boundary = Some(MultiPolygon(vec![square(0., 1.), square(2., 3.)]));
+        let expected = boundary.clone();
+        assert!(!normalize_boundary(&mut boundary, "relation:1"));
+        assert_eq!(boundary, expected);
+    }
+
+    #[test]
+    fn overlapping_polygons_are_normalized() {
+        // 2 overlapping polygons do not make a valid MultiPolygon
+        let mut boundary = Some(MultiPolygon(vec![square(0., 2.), square(1., 3.)]));
+        assert!(normalize_boundary(&mut boundary, "relation:1"));
+        let boundary = boundary.unwrap();
+        assert_eq!(boundary.0.len(), 1);
+        assert!(boundary.0[0].interiors().is_empty());
+    }
+
+    #[test]
+    fn collections_are_flattened() {
+        let geom = geos::Geometry::new_from_wkt(
+            "GEOMETRYCOLLECTION(POLYGON((0 0, 0 1, 1 1, 1 0, 0 0)), LINESTRING(2 2, 3 3), \
+             MULTIPOLYGON(((4 4, 4 5, 5 5, 5 4, 4 4))))",
+        )
+        .unwrap();
+        let boundary = convert_to_geo(geom).unwrap().unwrap();
+        assert_eq!(boundary, MultiPolygon(vec![square(0., 1.), square(4., 5.)]));
+
+        // no polygon
+        let geom =
+            geos::Geometry::new_from_wkt("GEOMETRYCOLLECTION(LINESTRING(2 2, 3 3))").unwrap();
+        assert_eq!(convert_to_geo(geom).unwrap(), None);
+    }
+
+    #[test]
+    fn degenerate_boundaries() {
+        assert!(!is_degenerate(&MultiPolygon(vec![square(0., 1.)])));
+        assert!(is_degenerate(&MultiPolygon(vec![])));
+        assert!(is_degenerate(&MultiPolygon(vec![square(0., 1e-6)])));
+        // a flat polygon
+        let flat = Polygon::new(
+            LineString::from(vec![(0., 0.), (1., 1.), (2., 2.), (0., 0.)]),
+            vec![],
+        );
+        assert!(is_degenerate(&MultiPolygon(vec![flat])));
+    }
+
+    #[test]
+    fn clipped_boundaries() {
+        let clip = geos::Geometry::try_from(&MultiPolygon(vec![square(0., 2.)])).unwrap();
+
+        let clipped_area = |boundary: Polygon<f64>| {
+            clip_boundary(&MultiPolygon(vec![boundary]), &clip)
+                .unwrap()
+                .map(|b| geo::Area::unsigned_area(&b))
+        };
+        // inside
+        assert_eq!(clipped_area(square(0.5, 1.)), Some(0.25));
+        // partially inside
+        assert_eq!(clipped_area(square(1., 3.)), Some(1.));
+        // outside
+        assert_eq!(clipped_area(square(3., 4.)), None);
+        // only touching the clip polygon
+        assert_eq!(clipped_area(square(2., 3.)), None);
+    }
+}
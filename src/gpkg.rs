@@ -0,0 +1,289 @@
+//! GeoPackage export of the zones
+//!
+//! GeoPackage is an SQLite based format, natively read by GDAL, QGIS or ArcGIS.
+//! All the zones are written in a single `cosmogony` features table, with the same properties
+//! as the GeoJSON export, the boundaries as MultiPolygon (in WGS84) and an R-tree spatial index.
+//!
+//! The zones without boundary are written with a NULL geometry, unless they are skipped.
+//!
+//! Note: the file is written once, so the R-tree index is filled directly, without the
+//! triggers keeping it up to date (a file edited afterwards needs its index to be rebuilt).
+//!
+//! Note: a GeoPackage file cannot be streamed, so it is not supported by the merge.
+
+use anyhow::{Context, Result};
+use cosmogony::Zone;
+use geo_types::{LineString, MultiPolygon, Rect};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+const TABLE_NAME: &str = "cosmogony";
+const GEOMETRY_COLUMN: &str = "geom";
+// WGS84
+const SRS_ID: i32 = 4326;
+
+// the columns of the properties of `Zone::geojson_properties`, except the `name:<lang>` ones
+const COLUMNS: [(&str, &str); 11] = [
+    ("id", "TEXT"),
+    ("osm_id", "TEXT"),
+    ("name", "TEXT"),
+    ("label", "TEXT"),
+    ("zone_type", "TEXT"),
+    ("admin_level", "INTEGER"),
+    ("country_code", "TEXT"),
+    ("zip_codes", "TEXT"),
+    ("wikidata", "TEXT"),
+    ("wikipedia", "TEXT"),
+    ("website", "TEXT"),
+];
+
+// the mandatory tables of a GeoPackage, with the mandatory spatial reference systems
+const GPKG_SCHEMA: &str = "
+    PRAGMA application_id = 1196444487;
+    PRAGMA user_version = 10300;
+    CREATE TABLE gpkg_spatial_ref_sys (
+        srs_name TEXT NOT NULL,
+        srs_id INTEGER NOT NULL PRIMARY KEY,
+        organization TEXT NOT NULL,
+        organization_coordsys_id INTEGER NOT NULL,
+        definition TEXT NOT NULL,
+        description TEXT
+    );
+    INSERT INTO gpkg_spatial_ref_sys VALUES
+        ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+        ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system'),
+        ('WGS 84 geodetic', 4326, 'EPSG', 4326,
+         'GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,AUTHORITY[\"EPSG\",\"7030\"]],AUTHORITY[\"EPSG\",\"6326\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],AUTHORITY[\"EPSG\",\"4326\"]]',
+         'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid');
+    CREATE TABLE gpkg_contents (
+        table_name TEXT NOT NULL PRIMARY KEY,
+        data_type TEXT NOT NULL,
+        identifier TEXT UNIQUE,
+        description TEXT DEFAULT '',
+        last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+        min_x DOUBLE,
+        min_y DOUBLE,
+        max_x DOUBLE,
+        max_y DOUBLE,
+        srs_id INTEGER,
+        CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+    );
+    CREATE TABLE gpkg_geometry_columns (
+        table_name TEXT NOT NULL,
+        column_name TEXT NOT NULL,
+        geometry_type_name TEXT NOT NULL,
+        srs_id INTEGER NOT NULL,
+        z TINYINT NOT NULL,
+        m TINYINT NOT NULL,
+        CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+        CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+        CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+    );
+    CREATE TABLE gpkg_extensions (
+        table_name TEXT,
+        column_name TEXT,
+        extension_name TEXT NOT NULL,
+        definition TEXT NOT NULL,
+        scope TEXT NOT NULL,
+        CONSTRAINT ge_tce UNIQUE (table_name, column_name, extension_name)
+    );
+";
+
+fn write_ring(wkb: &mut Vec<u8>, ring: &LineString<f64>) {
+    wkb.extend((ring.0.len() as u32).to_le_bytes());
+    for c in ring.coords() {
+        wkb.extend(c.x.to_le_bytes());
+        wkb.extend(c.y.to_le_bytes());
+    }
+}
+
+/// Encode a boundary as a GeoPackage geometry blob: a header with the
+/// envelope of the boundary, followed by the (little endian) WKB of the boundary
+fn gpkg_geometry(boundary: &MultiPolygon<f64>, bbox: &Rect<f64>) -> Vec<u8> {
+    // magic, version 0, and flags: little endian with a [minx, maxx, miny, maxy] envelope
+    let mut blob = vec![b'G', b'P', 0, 0b0000_0011];
+    blob.extend(SRS_ID.to_le_bytes());
+    for v in [bbox.min().x, bbox.max().x, bbox.min().y, bbox.max().y] {
+        blob.extend(v.to_le_bytes());
+    }
+    // WKB MultiPolygon (type 6) of Polygons (type 3)
+    blob.push(1);
+    blob.extend(6u32.to_le_bytes());
+    blob.extend((boundary.0.len() as u32).to_le_bytes());
+    for polygon in boundary {
+        blob.push(1);
+        blob.extend(3u32.to_le_bytes());
+        blob.extend((polygon.interiors().len() as u32 + 1).to_le_bytes());
+        write_ring(&mut blob, polygon.exterior());
+        polygon
+            .interiors()
+            .iter()
+            .for_each(|ring| write_ring(&mut blob, ring));
+    }
+    blob
+}
+
+fn sql_value(value: Option<&Value>) -> rusqlite::types::Value {
+    use rusqlite::types::Value as SqlValue;
+    match value {
+        None | Some(Value::Null) => SqlValue::Null,
+        Some(Value::String(s)) => SqlValue::Text(s.clone()),
+        Some(Value::Number(n)) => SqlValue::Integer(n.as_i64().unwrap_or_default()),
+        Some(v) => SqlValue::Text(v.to_string()),
+    }
+}
+
+/// Write the zones in a GeoPackage file, with the same properties as the GeoJSON export
+///
+/// The file is overwritten if it exists. If `skip_without_geometry` is true, the zones
+/// without boundary are not written, else they are written with a NULL geometry.
+pub fn write_geopackage(zones: &[Zone], path: &Path, skip_without_geometry: bool) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("impossible to overwrite {}", path.display()))?;
+    }
+    let mut conn = rusqlite::Connection::open(path)?;
+    conn.execute_batch(GPKG_SCHEMA)?;
+
+    // like the FlatGeobuf, all the columns are needed upfront, so we need all the langs of the labels
+    let langs: BTreeSet<&str> = zones
+        .iter()
+        .flat_map(|z| z.international_labels.keys().map(String::as_str))
+        .collect();
+    let columns: Vec<(String, &str)> = COLUMNS
+        .iter()
+        .map(|(name, column_type)| (name.to_string(), *column_type))
+        .chain(langs.iter().map(|lang| (format!("name:{}", lang), "TEXT")))
+        .collect();
+    let columns_def: String = columns
+        .iter()
+        .map(|(name, column_type)| format!(", \"{}\" {}", name, column_type))
+        .collect();
+    conn.execute_batch(&format!(
+        "CREATE TABLE {table} (fid INTEGER PRIMARY KEY AUTOINCREMENT, {geom} MULTIPOLYGON{columns});
+         CREATE VIRTUAL TABLE rtree_{table}_{geom} USING rtree(id, minx, maxx, miny, maxy);",
+        table = TABLE_NAME,
+        geom = GEOMETRY_COLUMN,
+        columns = columns_def,
+    ))?;
+
+    let transaction = conn.transaction()?;
+    let mut nb_skipped = 0;
+    let mut extent: Option<Rect<f64>> = None;
+    {
+        let columns_names: String = columns
+            .iter()
+            .map(|(name, _)| format!(", \"{}\"", name))
+            .collect();
+        let placeholders: String = (0..columns.len() + 1).map(|_| ", ?").collect();
+        let mut insert_zone = transaction.prepare(&format!(
+            "INSERT INTO {} (fid, {}{}) VALUES (?{})",
+            TABLE_NAME, GEOMETRY_COLUMN, columns_names, placeholders
+        ))?;
+        let mut insert_rtree = transaction.prepare(&format!(
+            "INSERT INTO rtree_{}_{} VALUES (?1, ?2, ?3, ?4, ?5)",
+            TABLE_NAME, GEOMETRY_COLUMN
+        ))?;
+
+        for (fid, zone) in zones.iter().enumerate() {
+            // the fid starts at 1, like the sqlite's rowids
+            let fid = fid as i64 + 1;
+            let geometry = match (&zone.boundary, &zone.bbox) {
+                (Some(boundary), Some(bbox)) => Some((boundary, bbox)),
+                _ if skip_without_geometry => {
+                    nb_skipped += 1;
+                    continue;
+                }
+                _ => None,
+            };
+            let properties = zone.geojson_properties();
+            let mut values = vec![
+                rusqlite::types::Value::Integer(fid),
+                geometry
+                    .map(|(boundary, bbox)| gpkg_geometry(boundary, bbox))
+                    .into(),
+            ];
+            values.extend(
+                columns
+                    .iter()
+                    .map(|(name, _)| sql_value(properties.get(name))),
+            );
+            insert_zone.execute(rusqlite::params_from_iter(values))?;
+
+            if let Some((_, bbox)) = geometry {
+                insert_rtree.execute((
+                    fid,
+                    bbox.min().x,
+                    bbox.max().x,
+                    bbox.min().y,
+                    bbox.max().y,
+                ))?;
+                extent = Some(match extent {
+                    Some(e) => Rect::new(
+                        (e.min().x.min(bbox.min().x), e.min().y.min(bbox.min().y)),
+                        (e.max().x.max(bbox.max().x), e.max().y.max(bbox.max().y)),
+                    ),
+                    None => *bbox,
+                });
+            }
+        }
+    }
+
+    transaction.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id)
+         VALUES (?1, 'features', ?1, ?2, ?3, ?4, ?5, ?6)",
+        (
+            TABLE_NAME,
+            extent.map(|e| e.min().x),
+            extent.map(|e| e.min().y),
+            extent.map(|e| e.max().x),
+            extent.map(|e| e.max().y),
+            SRS_ID,
+        ),
+    )?;
+    transaction.execute(
+        "INSERT INTO gpkg_geometry_columns VALUES (?1, ?2, 'MULTIPOLYGON', ?3, 0, 0)",
+        (TABLE_NAME, GEOMETRY_COLUMN, SRS_ID),
+    )?;
+    transaction.execute(
+        "INSERT INTO gpkg_extensions VALUES (?1, ?2, 'gpkg_rtree_index',
+         'http://www.geopackage.org/spec120/#extension_rtree', 'write-only')",
+        (TABLE_NAME, GEOMETRY_COLUMN),
+    )?;
+    transaction.commit()?;
+
+    if nb_skipped > 0 {
+        info!(
+            "{} zones without boundary skipped in the geopackage",
+            nb_skipped
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use geo_types::{Coord, Polygon};
+
+    #[test]
+    fn geometry_blob() {
+        let square = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![(0., 0.), (0., 1.), (1., 1.), (1., 0.), (0., 0.)]),
+            vec![],
+        )]);
+        let bbox = Rect::new(Coord { x: 0., y: 0. }, Coord { x: 1., y: 1. });
+        let blob = gpkg_geometry(&square, &bbox);
+
+        assert_eq!(&blob[..4], &[b'G', b'P', 0, 3]);
+        assert_eq!(&blob[4..8], &4326i32.to_le_bytes());
+        // header (8 bytes) + envelope (4 doubles)
+        let wkb = &blob[8 + 32..];
+        // little endian multipolygon of 1 polygon
+        assert_eq!(&wkb[..9], &[1, 6, 0, 0, 0, 1, 0, 0, 0]);
+        // little endian polygon of 1 ring, of 5 points
+        assert_eq!(&wkb[9..22], &[1, 3, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0]);
+        assert_eq!(wkb.len(), 22 + 5 * 16);
+    }
+}
@@ -2,12 +2,19 @@ extern crate geo;
 
 use cosmogony::mutable_slice::MutableSlice;
 use cosmogony::{Zone, ZoneIndex};
+use geo::bounding_rect::BoundingRect;
 use geo_types::{Point, Rect};
+use itertools::Itertools;
 use log::{info, warn};
 use rstar::{RTree, RTreeObject, AABB};
+use std::collections::HashMap;
 use std::iter::FromIterator;
 
-use crate::zone_ext::ZoneExt;
+use crate::geometry::union_boundaries;
+use crate::zone_ext::{
+    antimeridian_bboxes, boundary_centroid, boundary_perimeter, count_holes, reaches_antimeridian,
+    ZoneExt,
+};
 
 #[derive(Debug)]
 struct ZoneIndexAndBbox {
@@ -39,19 +46,43 @@ fn envelope(bbox: &Rect<f64>) -> AABB<Point<f64>> {
     AABB::from_corners(bbox.min().into(), bbox.max().into())
 }
 
+// the bboxes of a zone in the rtree. The zones crossing the antimeridian have a bbox
+// on each side, else they would be candidates for all the zones at their latitudes
+// (the boundary is only split for the zones whose bbox reaches the antimeridian)
+fn zone_bboxes(z: &Zone) -> Vec<Rect<f64>> {
+    match z.bbox {
+        Some(bbox) if reaches_antimeridian(&bbox) => {
+            match z.boundary.as_ref().and_then(antimeridian_bboxes) {
+                Some((east, west)) => vec![east, west],
+                None => vec![bbox],
+            }
+        }
+        Some(bbox) => vec![bbox],
+        None => vec![],
+    }
+}
+
 impl ZonesTree {
     pub fn fetch_zone_bbox(&self, z: &Zone) -> Vec<ZoneIndex> {
-        match z.bbox {
-            None => {
-                warn!("No bbox: Cannot fetch zone with osm_id {}", z.osm_id);
-                vec![]
-            }
-            Some(ref bbox) => self
-                .tree
-                .locate_in_envelope_intersecting(&envelope(bbox))
-                .map(|z_and_bbox| z_and_bbox.index)
-                .collect(),
+        let bboxes = zone_bboxes(z);
+        if bboxes.is_empty() {
+            warn!("No bbox: Cannot fetch zone with osm_id {}", z.osm_id);
         }
+        bboxes
+            .iter()
+            .flat_map(|bbox| self.tree.locate_in_envelope_intersecting(&envelope(bbox)))
+            .map(|z_and_bbox| z_and_bbox.index)
+            .unique()
+            .collect()
+    }
+
+    /// the zones whose bbox contains a point
+    pub fn fetch_point(&self, lon: f64, lat: f64) -> Vec<ZoneIndex> {
+        self.tree
+            .locate_in_envelope_intersecting(&AABB::from_point(Point::new(lon, lat)))
+            .map(|z_and_bbox| z_and_bbox.index)
+            .unique()
+            .collect()
     }
 }
 
@@ -59,12 +90,15 @@ impl<'a> FromIterator<&'a Zone> for ZonesTree {
     fn from_iter<I: IntoIterator<Item = &'a Zone>>(zones: I) -> Self {
         let z = zones
             .into_iter()
-            .filter_map(|z| match z.bbox {
-                Some(ref b) => Some(ZoneIndexAndBbox::new(z.id, b)),
-                None => {
+            .flat_map(|z| {
+                let bboxes = zone_bboxes(z);
+                if bboxes.is_empty() {
                     warn!("No bbox: Cannot insert zone with osm_id {}", z.osm_id);
-                    None
                 }
+                bboxes
+                    .into_iter()
+                    .map(|b| ZoneIndexAndBbox::new(z.id, &b))
+                    .collect::<Vec<_>>()
             })
             .collect();
         ZonesTree {
@@ -94,6 +128,46 @@ pub fn find_inclusions(zones: &[Zone]) -> (Vec<Vec<ZoneIndex>>, ZonesTree) {
     (result, ztree)
 }
 
+/// Find the zones contained by several admin zones of the same type
+///
+/// This is a topological conflict (usually overlapping boundaries), for which the parent
+/// of the zone is chosen arbitrarily.
+/// The conflicts are returned as the zone's osm_id with the osm_ids of the containing zones.
+///
+/// Note: this needs the typed zones, and only uses the already computed inclusions
+pub fn find_containment_conflicts(
+    zones: &[Zone],
+    zones_inclusions: &[Vec<ZoneIndex>],
+) -> Vec<(String, Vec<String>)> {
+    zones
+        .iter()
+        .zip(zones_inclusions.iter())
+        .filter_map(|(z, inclusions)| {
+            let conflicting: Vec<String> = inclusions
+                .iter()
+                .map(|idx| &zones[idx.index])
+                .filter_map(|c| c.admin_type().map(|t| (t, c)))
+                .into_group_map()
+                .into_values()
+                .filter(|containers| containers.len() > 1)
+                .flatten()
+                .map(|c| c.osm_id.clone())
+                .sorted()
+                .collect();
+            if conflicting.is_empty() {
+                None
+            } else {
+                warn!(
+                    "{} is contained by several zones of the same type: {}",
+                    z.osm_id,
+                    conflicting.join(", ")
+                );
+                Some((z.osm_id.clone(), conflicting))
+            }
+        })
+        .collect()
+}
+
 /// Build the cosmogony hierarchy for all the zones
 ///
 /// The hierarchy is a tree.
@@ -105,37 +179,158 @@ pub fn find_inclusions(zones: &[Zone]) -> (Vec<Vec<ZoneIndex>>, ZonesTree) {
 ///     a City cannot be attached to a CityDistrict or a Suburb, it should be attached to a
 ///     StateDistrict, a State, a CountryRegion or a Country
 pub fn build_hierarchy(zones: &mut [Zone], zones_inclusions: Vec<Vec<ZoneIndex>>) {
+    use rayon::prelude::*;
     info!("building the zones's hierarchy");
     assert_eq!(zones.len(), zones_inclusions.len());
 
-    zones_inclusions
+    // the parents only depend on the other zones' types and tags (not on their parents),
+    // so they are all computed in parallel before being set
+    let parents: Vec<Option<ZoneIndex>> = zones
+        .par_iter()
+        .zip(zones_inclusions.par_iter())
+        .map(|(z, inclusions)| find_parent(z, inclusions, zones))
+        .collect();
+
+    zones
+        .iter_mut()
+        .zip(parents)
+        .for_each(|(z, parent)| z.set_parent(parent));
+
+    compute_depths(zones);
+}
+
+// the 'smallest' zone containing a zone that can be its parent
+// (the first one of the inclusions if several zones have the same type)
+fn find_parent(zone: &Zone, inclusions: &[ZoneIndex], zones: &[Zone]) -> Option<ZoneIndex> {
+    inclusions
         .iter()
-        .enumerate()
-        .for_each(|(i, inclusions)| {
-            let (mslice, z) = MutableSlice::init(zones, i);
+        .map(|c_idx| &zones[c_idx.index])
+        .filter(|c| zone.can_be_child_of(c))
+        .min_by_key(|c| c.zone_type.map(|t| t.rank()))
+        .map(|c| c.id)
+}
 
-            let parent = inclusions
+// group the zones (by position) whose boundaries are connected (touching or overlapping)
+fn adjacent_groups(zones: &[Zone], positions: &[usize]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for pos in positions {
+        // the existing groups touching this zone are merged with it
+        let (touching, mut others): (Vec<_>, Vec<_>) = groups.into_iter().partition(|group| {
+            group
                 .iter()
-                .filter_map(|c_idx| {
-                    let c = mslice.get(c_idx);
-                    if z.can_be_child_of(c) {
-                        Some(c)
-                    } else {
-                        None
-                    }
-                })
-                .min_by_key(|z| z.zone_type);
-
-            z.set_parent(parent.map(|z| z.id));
-        })
+                .any(|other| zones[*pos].intersects(&zones[*other]))
+        });
+        let mut group: Vec<usize> = touching.into_iter().flatten().collect();
+        group.push(*pos);
+        group.sort_unstable();
+        others.push(group);
+        groups = others;
+    }
+    groups
+}
+
+// merge the zones at the `merged` positions into the zone at the `kept` position
+fn merge_zones(zones: &mut [Zone], kept: usize, merged: &[usize]) -> anyhow::Result<()> {
+    let boundaries: Vec<_> = std::iter::once(kept)
+        .chain(merged.iter().copied())
+        .filter_map(|pos| zones[pos].boundary.as_ref())
+        .collect();
+    let boundary = union_boundaries(&boundaries)?;
+    let zip_codes: Vec<String> = merged
+        .iter()
+        .flat_map(|pos| zones[*pos].zip_codes.clone())
+        .collect();
+    let tags: Vec<_> = merged
+        .iter()
+        .flat_map(|pos| zones[*pos].tags.iter())
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+
+    let zone = &mut zones[kept];
+    zone.bbox = boundary.as_ref().and_then(|b| b.bounding_rect());
+    zone.perimeter = boundary.as_ref().map(boundary_perimeter);
+    zone.num_holes = boundary.as_ref().map_or(0, count_holes);
+    // a center from a node is kept, the other ones are the centroid of the merged boundary
+    if zone.center_tags.is_empty() {
+        zone.center = boundary
+            .as_ref()
+            .and_then(|b| boundary_centroid(b, &zone.osm_id));
+    }
+    zone.boundary = boundary;
+    zone.zip_codes.extend(zip_codes);
+    zone.zip_codes.sort();
+    zone.zip_codes.dedup();
+    // the tags of the kept zone have the priority
+    for (k, v) in tags {
+        zone.tags.entry(k).or_insert(v);
+    }
+    Ok(())
+}
+
+/// Merge the adjacent zones with the same name, type and parent
+///
+/// A place is sometimes split in several osm relations sharing a border. Those zones are
+/// merged into the first of them: it gets the union of their boundaries, their zip codes,
+/// their tags (its own tags having the priority) and their children.
+/// The ids of the zones merged into another one are returned, they have to be removed.
+///
+/// Note: this needs the hierarchy to be built, and the ids to be the zones' positions
+pub fn merge_same_name_siblings(zones: &mut [Zone]) -> Vec<ZoneIndex> {
+    info!("merging the adjacent zones with the same name");
+    let siblings = zones
+        .iter()
+        .filter(|z| z.zone_type.is_some() && !z.name.is_empty())
+        .map(|z| ((z.parent, z.zone_type, z.name.clone()), z.id.index))
+        .into_group_map();
+
+    let mut merged_into: HashMap<ZoneIndex, ZoneIndex> = HashMap::new();
+    for positions in siblings.into_values().filter(|p| p.len() > 1) {
+        for group in adjacent_groups(zones, &positions) {
+            let (kept, merged) = match group.split_first() {
+                Some((kept, merged)) if !merged.is_empty() => (*kept, merged),
+                _ => continue,
+            };
+            if let Err(err) = merge_zones(zones, kept, merged) {
+                warn!(
+                    "impossible to merge the zones named {}: {:?}",
+                    zones[kept].name, err
+                );
+                continue;
+            }
+            merged_into.extend(merged.iter().map(|pos| (zones[*pos].id, zones[kept].id)));
+        }
+    }
+
+    // the children of the merged zones are moved to the zone they are merged into
+    for z in zones.iter_mut() {
+        if let Some(parent) = z.parent.and_then(|p| merged_into.get(&p)) {
+            z.parent = Some(*parent);
+        }
+    }
+    info!("{} zones merged into a sibling", merged_into.len());
+    merged_into.into_keys().sorted().collect()
+}
+
+// compute the depth of each zone by walking up its hierarchy.
+// If the hierarchy had a cycle, the walk is stopped after all the zones have been visited,
+// so the depth is capped by the number of zones.
+pub(crate) fn compute_depths(zones: &mut [Zone]) {
+    for i in 0..zones.len() {
+        let (mslice, z) = MutableSlice::init(zones, i);
+        let depth = z.iter_hierarchy(&mslice).count().saturating_sub(1);
+        z.depth = depth as u32;
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::hierarchy_builder::{build_hierarchy, find_inclusions};
-    use cosmogony::{Zone, ZoneType};
+    use crate::hierarchy_builder::{
+        build_hierarchy, find_containment_conflicts, find_inclusions, merge_same_name_siblings,
+    };
+    use cosmogony::{Zone, ZoneIndex, ZoneType};
     use geo::bounding_rect::BoundingRect;
-    use geo_types::{Coord, LineString, MultiPolygon, Polygon};
+    use geo::{Area, Contains};
+    use geo_types::{Coord, LineString, MultiPolygon, Point, Polygon};
 
     fn zone_factory(idx: usize, ls: LineString<f64>, zone_type: Option<ZoneType>) -> Zone {
         let p = Polygon::new(ls, vec![]);
@@ -214,6 +409,94 @@ mod test {
         assert_parent(&zones, 1, Some(0)); // z1 parent is z0
         assert_parent(&zones, 2, Some(1)); // z2 parent is z1
         assert_parent(&zones, 3, Some(0)); // z3 parent is z0
+
+        let depths: Vec<_> = zones.iter().map(|z| z.depth).collect();
+        assert_eq!(depths, vec![0, 1, 2, 1]);
+    }
+
+    #[test]
+    fn antimeridian_candidates_test() {
+        let square = |min_x: f64, max_x: f64| {
+            Polygon::new(
+                LineString(coords(vec![
+                    (min_x, -18.),
+                    (max_x, -18.),
+                    (max_x, -16.),
+                    (min_x, -16.),
+                    (min_x, -18.),
+                ])),
+                vec![],
+            )
+        };
+        let mut fiji = zone_factory(0, square(177., 180.).into_inner().0, None);
+        fiji.boundary = Some(MultiPolygon(vec![square(177., 180.), square(-180., -178.)]));
+        fiji.bbox = fiji.boundary.as_ref().and_then(|b| b.bounding_rect());
+        let zones = vec![
+            fiji,
+            zone_factory(1, square(178., 179.).into_inner().0, None),
+            zone_factory(2, square(-179., -178.5).into_inner().0, None),
+            // at the same latitudes, far from the antimeridian
+            zone_factory(3, square(0., 1.).into_inner().0, None),
+        ];
+
+        let (inclusions, ztree) = find_inclusions(&zones);
+        let candidates = |idx: usize| -> Vec<usize> {
+            let mut candidates: Vec<_> = ztree
+                .fetch_zone_bbox(&zones[idx])
+                .into_iter()
+                .map(|z| z.index)
+                .collect();
+            candidates.sort();
+            candidates
+        };
+        assert_eq!(candidates(0), vec![0, 1, 2]);
+        assert_eq!(candidates(3), vec![3]);
+        assert_eq!(inclusions[1], vec![ZoneIndex { index: 0 }]);
+        assert_eq!(inclusions[2], vec![ZoneIndex { index: 0 }]);
+        assert!(inclusions[3].is_empty());
+    }
+
+    // the former sequential parent selection, to check the parallel one
+    fn build_hierarchy_sequentially(zones: &mut [Zone], zones_inclusions: Vec<Vec<ZoneIndex>>) {
+        use crate::zone_ext::ZoneExt;
+        use cosmogony::mutable_slice::MutableSlice;
+
+        for (i, inclusions) in zones_inclusions.iter().enumerate() {
+            let (mslice, z) = MutableSlice::init(zones, i);
+            let parent = inclusions
+                .iter()
+                .map(|c_idx| mslice.get(c_idx))
+                .filter(|c| z.can_be_child_of(c))
+                .min_by_key(|c| c.zone_type.map(|t| t.rank()));
+            z.set_parent(parent.map(|c| c.id));
+        }
+    }
+
+    #[test]
+    fn parallel_hierarchy_test() {
+        let variants: Vec<fn(&mut Vec<Zone>)> = vec![
+            |_| {},
+            |zones| zones[1].zone_type = Some(ZoneType::NonAdministrative),
+            |zones| zones[1].zone_type = None,
+            |zones| zones[2].zone_type = Some(ZoneType::State),
+            |zones| zones[2].zone_type = Some(ZoneType::CountryRegion),
+            |zones| {
+                zones[1].tags.insert("disputed".into(), "yes".into());
+            },
+        ];
+        for variant in variants {
+            let mut zones = create_zones();
+            variant(&mut zones);
+            let (inclusions, _) = find_inclusions(&zones);
+
+            let mut expected = zones.clone();
+            build_hierarchy_sequentially(&mut expected, inclusions.clone());
+            build_hierarchy(&mut zones, inclusions);
+
+            let parents: Vec<_> = zones.iter().map(|z| z.parent).collect();
+            let expected_parents: Vec<_> = expected.iter().map(|z| z.parent).collect();
+            assert_eq!(parents, expected_parents);
+        }
     }
 
     #[test]
@@ -268,6 +551,59 @@ mod test {
         assert_parent(&zones, 3, Some(0)); // z3 parent is z0
     }
 
+    #[test]
+    fn containment_conflicts_test() {
+        let mut zones = create_zones();
+        for (idx, z) in zones.iter_mut().enumerate() {
+            z.osm_id = format!("relation:{}", idx);
+        }
+
+        let (inclusions, _) = find_inclusions(&zones);
+        assert!(find_containment_conflicts(&zones, &inclusions).is_empty());
+
+        // z1 and z3 are both states, so z2, contained by both of them, is in conflict
+        // (z1 is contained by z0 only, and z3 is not contained by z1)
+        zones[2].boundary = zone_factory(
+            2,
+            LineString(coords(vec![
+                (2., 2.),
+                (2., 4.),
+                (4., 4.),
+                (4., 2.),
+                (2., 2.),
+            ])),
+            None,
+        )
+        .boundary;
+        zones[2].bbox = zones[2].boundary.as_ref().and_then(|b| b.bounding_rect());
+        let (inclusions, _) = find_inclusions(&zones);
+        assert_eq!(
+            find_containment_conflicts(&zones, &inclusions),
+            vec![(
+                "relation:2".to_string(),
+                vec!["relation:1".to_string(), "relation:3".to_string()]
+            )]
+        );
+    }
+
+    /// a disputed zone should not be the parent of a non disputed zone
+    #[test]
+    fn hierarchy_test_disputed_parent() {
+        let mut zones = create_zones();
+
+        zones[1].tags = vec![("disputed", "yes")]
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+
+        let inclusions = find_inclusions(&zones);
+        build_hierarchy(&mut zones, inclusions.0);
+
+        assert_parent(&zones, 1, Some(0)); // z1 parent is z0
+        assert_parent(&zones, 2, Some(0)); // z2 parent is z0 even if it is contained by z1
+        assert_parent(&zones, 3, Some(0)); // z3 parent is z0
+    }
+
     /// a zone without a zone_type should not be a parent
     ///(but should be attached to an admin
     #[test]
@@ -285,4 +621,61 @@ mod test {
         assert_parent(&zones, 2, Some(0)); // z2 parent is z0 even if it is contained by z1
         assert_parent(&zones, 3, Some(0)); // z3 parent is z0
     }
+
+    #[test]
+    fn merge_same_name_siblings_test() {
+        let square = |x: f64, y: f64| {
+            LineString(coords(vec![
+                (x, y),
+                (x, y + 1.),
+                (x + 1., y + 1.),
+                (x + 1., y),
+                (x, y),
+            ]))
+        };
+        let sibling = |idx: usize, ls, name: &str, parent: usize| {
+            let mut z = zone_factory(idx, ls, Some(ZoneType::Suburb));
+            z.name = name.into();
+            z.parent = Some(ZoneIndex { index: parent });
+            z
+        };
+        let mut zones = create_zones();
+        // z4 and z5 share a border, z6 has the same name but is not adjacent,
+        // z7 is adjacent but has another name
+        let mut z4 = sibling(4, square(3., 3.), "Centre", 2);
+        z4.zip_codes = vec!["2".into()];
+        let mut z5 = sibling(5, square(4., 3.), "Centre", 2);
+        z5.zip_codes = vec!["1".into(), "2".into()];
+        z5.tags.insert("wikidata".into(), "Q5".into());
+        zones.extend([
+            z4,
+            z5,
+            sibling(6, square(6., 6.), "Centre", 2),
+            sibling(7, square(3., 4.), "North", 2),
+        ]);
+        // a child of the merged zone
+        let mut z8 = zone_factory(8, square(4.2, 3.2), Some(ZoneType::NonAdministrative));
+        z8.parent = Some(ZoneIndex { index: 5 });
+        zones.push(z8);
+
+        let merged = merge_same_name_siblings(&mut zones);
+        assert_eq!(merged, vec![ZoneIndex { index: 5 }]);
+
+        let bbox = zones[4].bbox.unwrap();
+        assert_eq!((bbox.min().x, bbox.max().x), (3., 5.));
+        assert_eq!((bbox.min().y, bbox.max().y), (3., 4.));
+        // the merged boundary is the 2x1 rectangle, its centroid is the new center
+        let boundary = zones[4].boundary.as_ref().unwrap();
+        assert_eq!(boundary.0.len(), 1);
+        assert!((boundary.unsigned_area() - 2.).abs() < 1e-9);
+        assert!(boundary.contains(&Point::new(3.5, 3.5)));
+        assert!(boundary.contains(&Point::new(4.5, 3.5)));
+        let center = zones[4].center.unwrap();
+        assert!((center.x() - 4.).abs() < 1e-9);
+        assert!((center.y() - 3.5).abs() < 1e-9);
+        assert_eq!(zones[4].zip_codes, vec!["1".to_string(), "2".to_string()]);
+        assert_eq!(zones[4].tags.get("wikidata"), Some(&"Q5".into()));
+        assert_parent(&zones, 8, Some(4));
+        assert!(zones[6].zip_codes.is_empty());
+    }
 }
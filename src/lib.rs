@@ -2,28 +2,56 @@
 extern crate log;
 
 mod additional_zones;
+pub mod attributes;
+mod build_config;
+pub mod check;
+mod checkpoint;
+mod cosmogony_ext;
+mod cosmogony_index;
 mod country_finder;
+pub mod extract;
+pub mod fgb;
+mod geometry;
+pub mod gpkg;
 mod hierarchy_builder;
+pub mod locate;
 pub mod merger;
+pub mod osm_meta;
+mod progress;
+pub mod tiles;
 mod zone_ext;
 pub mod zone_typer;
 
-use crate::country_finder::CountryFinder;
-use crate::hierarchy_builder::{build_hierarchy, find_inclusions};
+use crate::country_finder::{CountryFinder, COUNTRY_CODE_TAG};
+use crate::hierarchy_builder::{
+    build_hierarchy, find_containment_conflicts, find_inclusions, merge_same_name_siblings,
+};
 use additional_zones::compute_additional_places;
 use anyhow::{anyhow, Context, Error};
+pub use build_config::{BuildConfig, DEFAULT_MAX_RAW_ADMIN_LEVEL};
 use cosmogony::mutable_slice::MutableSlice;
-use cosmogony::{Cosmogony, CosmogonyMetadata, CosmogonyStats, ZoneType};
+use cosmogony::{
+    reindex_zones, retain_zones, AdminLevelSource, Coord, Cosmogony, CosmogonyMetadata,
+    CosmogonyStats, ZoneType,
+};
+pub use cosmogony_ext::CosmogonyExt;
+pub use cosmogony_index::CosmogonyIndex;
 use log::{debug, info};
-use osmpbfreader::{OsmId, OsmObj, OsmPbfReader};
-use std::collections::BTreeMap;
+use osmpbfreader::{OsmId, OsmObj, OsmPbfReader, RelationId};
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
+pub use zone_ext::{AreaModel, LabelFormat, NamesMergePolicy, ZipFormat};
 
 use cosmogony::{Zone, ZoneIndex};
 
-use crate::zone_ext::ZoneExt;
+use crate::zone_ext::{
+    boundary_area, boundary_perimeter, capital_centre, count_holes, infer_admin_level,
+    label_node_admin_level, ZoneExt,
+};
+use geo::bounding_rect::BoundingRect;
 
 const FILE_BUF_SIZE: usize = 1024 * 1024; // 1MB
 
@@ -41,44 +69,202 @@ pub fn is_admin(obj: &OsmObj) -> bool {
     }
 }
 
+/// an administrative boundary without `admin_level` tag
+/// (see the `--infer-missing-admin-level` option)
+pub fn is_admin_without_level(obj: &OsmObj) -> bool {
+    match *obj {
+        OsmObj::Relation(ref rel) => {
+            rel.tags
+                .get("boundary")
+                .is_some_and(|v| v == "administrative")
+                && rel.tags.get("admin_level").is_none()
+        }
+        _ => false,
+    }
+}
+
+/// a disputed boundary with an admin level (`boundary=disputed` are not admins)
+pub fn is_disputed(obj: &OsmObj) -> bool {
+    match *obj {
+        OsmObj::Relation(ref rel) => {
+            rel.tags.get("admin_level").is_some() && zone_ext::has_disputed_tags(&rel.tags)
+        }
+        _ => false,
+    }
+}
+
+/// a maritime boundary, like an exclusive economic zone (see the `--include-maritime` option)
+pub fn is_maritime(obj: &OsmObj) -> bool {
+    match *obj {
+        OsmObj::Relation(ref rel) => zone_ext::has_maritime_tags(&rel.tags),
+        _ => false,
+    }
+}
+
+/// The `place` values of the nodes used as places, if no other values are configured
+///
+/// The suburb, quarter and neighbourhood places are included: they build most of the voronoi
+/// geometries (the city, town and village places only build one when they are a capital).
+pub const DEFAULT_PLACE_TYPES: [&str; 6] = [
+    "city",
+    "town",
+    "village",
+    "suburb",
+    "quarter",
+    "neighbourhood",
+];
+
 pub fn is_place(obj: &OsmObj) -> bool {
+    is_place_of_types(obj, &[])
+}
+
+/// check if the object is a node with one of the given `place` values
+/// (or one of the [`DEFAULT_PLACE_TYPES`] if none is given)
+pub fn is_place_of_types(obj: &OsmObj, place_types: &[String]) -> bool {
     match *obj {
-        OsmObj::Node(ref node) => matches!(
-            node.tags.get("place").and_then(|s| ZoneType::parse(s)),
-            Some(ZoneType::City | ZoneType::Suburb)
-        ),
+        OsmObj::Node(ref node) => node.tags.get("place").is_some_and(|place| {
+            if place_types.is_empty() {
+                DEFAULT_PLACE_TYPES.iter().any(|t| place == *t)
+            } else {
+                place_types.iter().any(|t| place == t.as_str())
+            }
+        }),
         _ => false,
     }
 }
 
-pub fn is_additional_place(obj: &OsmObj) -> bool {
+pub fn is_additional_place(obj: &OsmObj, place_types: &[String]) -> bool {
     match *obj {
         OsmObj::Node(ref node) => {
-            matches!(
-                node.tags.get("place").and_then(|s| ZoneType::parse(s)),
-                Some(ZoneType::City | ZoneType::Suburb)
-            ) | node.tags.get("capital").map_or(false, |v| v == "yes")
+            is_place_of_types(obj, place_types)
+                | node.tags.get("capital").is_some_and(|v| v == "yes")
         }
         _ => false,
     }
 }
 
+/// Build the zones from the osm objects
+///
+/// The zones with a degenerate boundary (almost no area) are counted in the stats,
+/// and dropped if `config.drop_degenerate` is true.
+///
+/// The disputed boundaries are skipped (and counted in the stats),
+/// unless `config.include_disputed` is true.
+///
+/// The maritime boundaries are only built if `config.include_maritime` is true,
+/// they are typed as `Maritime` right away (they are not typed by the countries' rules).
+///
+/// The zones with a raw osm `admin_level` above `config.max_raw_admin_level` are dropped,
+/// or their admin_level is clamped to the max if `config.clamp_raw_admin_level` is true.
 pub fn get_zones_and_stats(
     pbf: &BTreeMap<OsmId, OsmObj>,
+    config: &BuildConfig,
 ) -> Result<(Vec<Zone>, CosmogonyStats), Error> {
-    let stats = CosmogonyStats::default();
+    let mut stats = CosmogonyStats::default();
     let mut zones = Vec::with_capacity(1000);
+    let mut zones_without_boundary = vec![];
+    let excluded_osm_ids: HashSet<&str> =
+        config.exclude_osm_ids.iter().map(String::as_str).collect();
 
     for obj in pbf.values() {
-        if !is_admin(obj) && !obj.tags().get("place").map_or(false, |v| v == "suburb") {
+        let to_infer = config.infer_missing_admin_level && is_admin_without_level(obj);
+        let maritime = config.include_maritime && is_maritime(obj);
+        if !is_admin(obj)
+            && !is_disputed(obj)
+            && !to_infer
+            && !maritime
+            && obj.tags().get("place").is_none_or(|v| v != "suburb")
+        {
+            continue;
+        }
+        if is_disputed(obj) && !config.include_disputed {
+            debug!("{:?} is a disputed boundary, skipped", obj.id());
+            stats.disputed_skipped += 1;
             continue;
         }
+        if !excluded_osm_ids.is_empty() {
+            let osm_id = match obj.id() {
+                OsmId::Node(n) => format!("node:{}", n.0),
+                OsmId::Way(w) => format!("way:{}", w.0),
+                OsmId::Relation(r) => format!("relation:{}", r.0),
+            };
+            if excluded_osm_ids.contains(osm_id.as_str()) {
+                info!("{} excluded", osm_id);
+                stats.excluded_zones += 1;
+                continue;
+            }
+        }
         if let OsmObj::Relation(ref relation) = *obj {
             let next_index = ZoneIndex { index: zones.len() };
-            if let Some(zone) = Zone::from_osm_relation(relation, pbf, next_index) {
+            if let Some(mut zone) =
+                Zone::from_osm_relation(relation, pbf, next_index, config.names_merge_policy)
+            {
+                if maritime {
+                    zone.zone_type = Some(ZoneType::Maritime);
+                }
+                if config.admin_level_from_label_node && zone.admin_level.is_none() {
+                    if let Some(level) = label_node_admin_level(relation, pbf) {
+                        debug!(
+                            "admin_level {} of the label node used for {}",
+                            level, zone.osm_id
+                        );
+                        zone.admin_level = Some(level);
+                        zone.admin_level_source = Some(AdminLevelSource::LabelNode);
+                    }
+                }
+                if to_infer && zone.admin_level.is_none() {
+                    match infer_admin_level(&relation.tags) {
+                        Some(level) => {
+                            info!("admin_level {} inferred for {}", level, zone.osm_id);
+                            zone.admin_level = Some(level);
+                            zone.admin_level_source = Some(AdminLevelSource::Inferred);
+                            stats.inferred_admin_levels += 1;
+                        }
+                        None => {
+                            debug!(
+                                "no admin_level can be inferred for {}, skipped",
+                                zone.osm_id
+                            );
+                            continue;
+                        }
+                    }
+                }
+                if let (Some(max), Some(level)) = (config.max_raw_admin_level, zone.admin_level) {
+                    if level > max && config.clamp_raw_admin_level {
+                        debug!(
+                            "admin_level {} of {} clamped to {}",
+                            level, zone.osm_id, max
+                        );
+                        zone.admin_level = Some(max);
+                        zone.admin_level_source = Some(AdminLevelSource::Defaulted);
+                        stats.clamped_admin_levels += 1;
+                    } else if level > max {
+                        debug!(
+                            "{} skipped, its admin_level {} is above {}",
+                            zone.osm_id, level, max
+                        );
+                        stats.dropped_admin_levels += 1;
+                        continue;
+                    }
+                }
+                if geometry::normalize_boundary(&mut zone.boundary, &zone.osm_id) {
+                    stats.normalized_boundaries += 1;
+                    zone.bbox = zone.boundary.as_ref().and_then(|b| b.bounding_rect());
+                    zone.perimeter = zone.boundary.as_ref().map(boundary_perimeter);
+                    zone.num_holes = zone.boundary.as_ref().map_or(0, count_holes);
+                }
+                if zone.boundary.as_ref().is_some_and(geometry::is_degenerate) {
+                    warn!("{} has a degenerate boundary", zone.osm_id);
+                    stats.degenerate_boundaries += 1;
+                    if config.drop_degenerate {
+                        continue;
+                    }
+                }
                 // Ignore zone without boundary polygon for the moment
                 if zone.boundary.is_some() {
                     zones.push(zone);
+                } else {
+                    zones_without_boundary.push(zone);
                 }
             };
         }
@@ -91,9 +277,45 @@ pub fn get_zones_and_stats(
         }
     }
 
+    stats.boundary_build_failures = count_by_country(&zones_without_boundary, &zones);
     Ok((zones, stats))
 }
 
+// number of zones by country code ("??" if the country is unknown)
+//
+// the country of a zone is the one of its tags, or else the country containing its center
+// (the zones are not typed yet, so the countries are the zones with a country code tag)
+fn count_by_country(zones: &[Zone], all_zones: &[Zone]) -> BTreeMap<String, u64> {
+    use geo::Contains;
+    let countries: Vec<(&Zone, String)> = all_zones
+        .iter()
+        .filter(|z| z.admin_level == Some(2))
+        .filter_map(|z| Some((z, z.tags.get(COUNTRY_CODE_TAG)?.to_uppercase())))
+        .collect();
+    let mut counts = BTreeMap::new();
+    for zone in zones {
+        let country_code = zone
+            .tags
+            .get(COUNTRY_CODE_TAG)
+            .map(|c| c.to_uppercase())
+            .or_else(|| {
+                let center = zone.center?;
+                countries
+                    .iter()
+                    .find(|(country, _)| {
+                        country
+                            .boundary
+                            .as_ref()
+                            .is_some_and(|b| b.contains(&center))
+                    })
+                    .map(|(_, country_code)| country_code.clone())
+            })
+            .unwrap_or_else(|| "??".into());
+        *counts.entry(country_code).or_insert(0) += 1;
+    }
+    counts
+}
+
 fn get_country_code<'a>(
     country_finder: &'a CountryFinder,
     zone: &Zone,
@@ -110,88 +332,226 @@ fn get_country_code<'a>(
 fn type_zones(
     zones: &mut [Zone],
     stats: &mut CosmogonyStats,
-    country_code: Option<String>,
+    config: &BuildConfig,
     inclusions: &[Vec<ZoneIndex>],
 ) -> Result<(), Error> {
     use rayon::prelude::*;
-    info!("reading libpostal's rules");
-    let zone_typer = zone_typer::ZoneTyper::new()?;
+    let zone_typer = read_zone_typer(config)?;
 
     info!("creating a countries rtree");
-    let country_finder: CountryFinder = CountryFinder::init(zones, &zone_typer);
-    if country_code.is_none() && country_finder.is_empty() {
-        return Err(anyhow!(
-            "no country_code has been provided and no country have been found, \
-             we won't be able to make a cosmogony",
-        ));
-    }
-
-    info!("typing zones");
-    // We type all the zones in parallele
-    // To not mutate the zones while doing it
-    // (the borrow checker would not be happy since we also need to access to the zone's vector
-    // to be able to transform the ZoneIndex to a zone)
-    // we collect all the types in a Vector, and assign the zone's zone_type as a post process
-    let zones_type: Vec<_> = zones
-        .par_iter()
-        .map(|z| {
-            get_country_code(&country_finder, z, &country_code, &inclusions[z.id.index]).map(|c| {
+    let country_finder: CountryFinder =
+        CountryFinder::init(zones, &zone_typer, config.country_fallback);
+    stats.rescued_countries = country_finder.nb_rescued();
+    check_countries(config, &country_finder)?;
+    let country_code = &config.country_code;
+
+    // the zones of a country outside of the allowlist are not typed (so they will be dropped)
+    let is_allowed_country = |c: &str| {
+        config.countries.is_empty() || config.countries.iter().any(|a| a.eq_ignore_ascii_case(c))
+    };
+    let type_zone = |z: &Zone, zones: &[Zone]| {
+        get_country_code(&country_finder, z, country_code, &inclusions[z.id.index]).map(|c| {
+            if !is_allowed_country(&c) {
+                return None;
+            }
+            Some(
                 zone_typer
                     .get_zone_type(z, &c, &inclusions[z.id.index], zones)
-                    .map(|zone_type| (c, zone_type))
-            })
+                    .map(|zone_type| (c, zone_type)),
+            )
         })
-        .collect();
+    };
 
-    zones
-        .iter_mut()
-        .zip(zones_type.into_iter())
-        .for_each(
-            |(z, country_code_and_zone_type)| match country_code_and_zone_type {
-                None => {
-                    info!(
-                        "impossible to find a country for {} ({}), skipping",
-                        z.osm_id, z.name
-                    );
-                    stats.zone_without_country += 1;
-                }
-                Some(Ok((country_code, t))) => {
-                    z.country_code = Some(country_code);
-                    z.zone_type = Some(t)
-                }
-                Some(Err(zone_typer::ZoneTyperError::InvalidCountry(c))) => {
-                    z.country_code = Some(c.clone());
-                    info!("impossible to find rules for country {}", c);
-                    *stats.zone_with_unkwown_country_rules.entry(c).or_insert(0) += 1;
-                }
-                Some(Err(zone_typer::ZoneTyperError::UnkownLevel(lvl, country))) => {
-                    z.country_code = Some(country.clone());
-                    debug!(
-                        "impossible to find a rule for level {:?} for country {}",
-                        lvl, country
-                    );
-                    *stats
-                        .unhandled_admin_level
-                        .entry(country)
-                        .or_insert_with(BTreeMap::new)
-                        .entry(lvl.unwrap_or(0))
-                        .or_insert(0) += 1;
+    let progress = progress::Progress::new("typing", zones.len(), config.progress);
+    if config.single_threaded_typing {
+        info!("typing zones sequentially");
+        let nb_zones = zones.len();
+        for i in 0..nb_zones {
+            progress.inc();
+            if zones[i].is_maritime() {
+                continue;
+            }
+            let country_code_and_zone_type = type_zone(&zones[i], zones);
+            set_zone_type(&mut zones[i], country_code_and_zone_type, stats);
+        }
+    } else {
+        info!("typing zones");
+        // We type all the zones in parallele
+        // To not mutate the zones while doing it
+        // (the borrow checker would not be happy since we also need to access to the zone's vector
+        // to be able to transform the ZoneIndex to a zone)
+        // we collect all the types in a Vector, and assign the zone's zone_type as a post process
+        let zones_type: Vec<_> = zones
+            .par_iter()
+            .map(|z| {
+                // the maritime zones are already typed
+                let typing = (!z.is_maritime()).then(|| type_zone(z, zones));
+                progress.inc();
+                typing
+            })
+            .collect();
+
+        zones
+            .iter_mut()
+            .zip(zones_type)
+            .for_each(|(z, country_code_and_zone_type)| {
+                if let Some(country_code_and_zone_type) = country_code_and_zone_type {
+                    set_zone_type(z, country_code_and_zone_type, stats)
                 }
-            },
-        );
+            });
+    }
+
+    // the maritime zones are not typed by the countries' rules, they only get the country
+    // containing them (if any), and they are dropped if it is not an allowed country
+    for z in zones.iter_mut().filter(|z| z.is_maritime()) {
+        z.country_code =
+            get_country_code(&country_finder, z, country_code, &inclusions[z.id.index]);
+        if !config.countries.is_empty()
+            && !z.country_code.as_deref().is_some_and(is_allowed_country)
+        {
+            debug!("{} is not in the allowed countries, skipping", z.osm_id);
+            z.zone_type = None;
+            stats.zone_outside_countries += 1;
+        }
+    }
+
+    // with an allowlist, the zones already typed (like the place nodes) are also dropped
+    // if their country is unknown, like the maritime zones
+    if !config.countries.is_empty() {
+        for z in zones
+            .iter_mut()
+            .filter(|z| !z.is_maritime() && z.country_code.is_none())
+        {
+            z.zone_type = None;
+        }
+    }
+
+    Ok(())
+}
 
+fn read_zone_typer(config: &BuildConfig) -> Result<zone_typer::ZoneTyper, Error> {
+    info!("reading libpostal's rules");
+    match &config.rules_override_dir {
+        Some(dir) => zone_typer::ZoneTyper::with_overrides(dir),
+        None => zone_typer::ZoneTyper::new(),
+    }
+}
+
+fn check_countries(config: &BuildConfig, country_finder: &CountryFinder) -> Result<(), Error> {
+    if config.country_code.is_none() && country_finder.is_empty() {
+        return Err(anyhow!(
+            "no country_code has been provided and no country have been found, \
+             we won't be able to make a cosmogony",
+        ));
+    }
     Ok(())
 }
 
-fn compute_labels(zones: &mut [Zone], filter_langs: &[String]) {
+// the result of the typing of a zone:
+// None if no country has been found, Some(None) if the country is not in the allowlist
+type ZoneTyping = Option<Option<Result<(String, ZoneType), zone_typer::ZoneTyperError>>>;
+
+fn set_zone_type(z: &mut Zone, country_code_and_zone_type: ZoneTyping, stats: &mut CosmogonyStats) {
+    match country_code_and_zone_type {
+        None => {
+            info!(
+                "impossible to find a country for {} ({}), skipping",
+                z.osm_id, z.name
+            );
+            stats.zone_without_country += 1;
+        }
+        Some(None) => {
+            debug!("{} is not in the allowed countries, skipping", z.osm_id);
+            // some zones are already typed (like the place nodes)
+            z.zone_type = None;
+            stats.zone_outside_countries += 1;
+        }
+        Some(Some(Ok((country_code, t)))) => {
+            z.country_code = Some(country_code);
+            z.zone_type = Some(t)
+        }
+        Some(Some(Err(zone_typer::ZoneTyperError::InvalidCountry(c)))) => {
+            z.country_code = Some(c.clone());
+            info!("impossible to find rules for country {}", c);
+            *stats.zone_with_unkwown_country_rules.entry(c).or_insert(0) += 1;
+        }
+        Some(Some(Err(zone_typer::ZoneTyperError::UnkownLevel(lvl, country)))) => {
+            z.country_code = Some(country.clone());
+            debug!(
+                "impossible to find a rule for level {:?} for country {}",
+                lvl, country
+            );
+            stats.add_unhandled_admin_level(country, lvl.unwrap_or(0), &z.osm_id);
+        }
+    }
+}
+
+fn compute_labels(zones: &mut [Zone], filter_langs: &[String], label_format: &LabelFormat) {
     info!("computing all zones's label");
     let nb_zones = zones.len();
     for i in 0..nb_zones {
         let (mslice, z) = MutableSlice::init(zones, i);
-        z.compute_labels(&mslice, filter_langs);
+        z.compute_labels(&mslice, filter_langs, label_format);
+    }
+}
+
+// the tags used to fill the zones' fields, we always keep them
+const PROMOTED_TAGS: [&str; 11] = [
+    "name",
+    "loc_name",
+    "alt_name",
+    "admin_level",
+    "wikidata",
+    "wikipedia",
+    "website",
+    "addr:postcode",
+    "postal_code",
+    "place",
+    COUNTRY_CODE_TAG,
+];
+
+// only keep the allowlisted tags (and the ones used to fill the zones' fields) to shrink the output
+// an allowlisted tag can end with a '*' to keep all the tags starting with a prefix (eg: 'name:*')
+fn prune_tags(zones: &mut [Zone], keep_tags: &[String]) {
+    info!("pruning the zones' tags");
+    let keep = |key: &str| {
+        PROMOTED_TAGS.contains(&key)
+            || keep_tags.iter().any(|t| match t.strip_suffix('*') {
+                Some(prefix) => key.starts_with(prefix),
+                None => t == key,
+            })
+    };
+    for z in zones.iter_mut() {
+        z.tags = z
+            .tags
+            .iter()
+            .filter(|(k, _)| keep(k.as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        z.center_tags = z
+            .center_tags
+            .iter()
+            .filter(|(k, _)| keep(k.as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
     }
 }
 
+// type as NonAdministrative the zones without zone_type but with a country,
+// so they are not removed by `clean_untagged_zones`
+fn type_untyped_zones(zones: &mut [Zone]) {
+    info!("typing the untyped zones as non administrative");
+    let mut nb_typed = 0;
+    zones
+        .iter_mut()
+        .filter(|z| z.zone_type.is_none() && z.country_code.is_some())
+        .for_each(|z| {
+            z.zone_type = Some(ZoneType::NonAdministrative);
+            nb_typed += 1;
+        });
+    info!("{} zones typed as non administrative", nb_typed);
+}
+
 // we don't want to keep zone's without zone_type (but the zone_type could be ZoneType::NonAdministrative)
 fn clean_untagged_zones(zones: &mut Vec<Zone>) {
     info!("cleaning untagged zones");
@@ -200,28 +560,218 @@ fn clean_untagged_zones(zones: &mut Vec<Zone>) {
     info!("{} zones cleaned", (nb_zones - zones.len()));
 }
 
+// sort the zones in a deterministic order (by zone type, country and osm id),
+// and reindex them so the zones' ids are their position in the sorted vector
+fn sort_zones(zones: &mut [Zone]) {
+    info!("sorting the zones");
+    zones.sort_by(|a, b| {
+        (a.zone_type, &a.country_code, &a.osm_id).cmp(&(b.zone_type, &b.country_code, &b.osm_id))
+    });
+    reindex_zones(zones);
+}
+
+// only keep the zones of the given types.
+// The parent of a kept zone is its nearest kept ancestor, and the zones are reindexed.
+// Note: the labels and the depths are not changed, they still reflect the whole hierarchy
+fn filter_zone_types(zones: &mut Vec<Zone>, zone_types: &[ZoneType]) {
+    info!("only keeping the zones of types {:?}", zone_types);
+    let nb_zones = zones.len();
+    retain_zones(zones, |z| {
+        z.zone_type.is_some_and(|t| zone_types.contains(&t))
+    });
+    info!("{} zones filtered out", nb_zones - zones.len());
+}
+
+// only keep the zones with a name (or a `name:*` name) matching a regex.
+// The parent of a kept zone is its nearest kept ancestor, and the zones are reindexed.
+// Note: the labels and the depths are not changed, they still reflect the whole hierarchy
+fn filter_names(zones: &mut Vec<Zone>, name_filter: &Regex) {
+    info!(
+        "only keeping the zones with a name matching '{}'",
+        name_filter
+    );
+    let nb_zones = zones.len();
+    retain_zones(zones, |z| {
+        name_filter.is_match(&z.name)
+            || z.international_names
+                .values()
+                .any(|n| name_filter.is_match(n))
+            || z.tags
+                .iter()
+                .filter(|(k, _)| k.starts_with("name:"))
+                .any(|(_, n)| name_filter.is_match(n))
+    });
+    info!("{} zones filtered out", nb_zones - zones.len());
+}
+
+// only keep the country with the given country code and the zones whose center is in it.
+// The zones are reindexed, and the number of dropped zones is returned.
+fn filter_country(zones: &mut Vec<Zone>, country_code: &str) -> Result<usize, Error> {
+    let country = zones
+        .iter()
+        .filter(|z| z.admin_level == Some(2))
+        .find(|z| {
+            z.tags
+                .get(COUNTRY_CODE_TAG)
+                .is_some_and(|c| c.eq_ignore_ascii_case(country_code))
+        })
+        .cloned()
+        .ok_or_else(|| anyhow!("no country with the country code '{}'", country_code))?;
+    info!(
+        "only keeping the zones in {} ({})",
+        country.name, country.osm_id
+    );
+    let nb_zones = zones.len();
+    retain_zones(zones, |z| z.id == country.id || country.contains_center(z));
+    let nb_dropped = nb_zones - zones.len();
+    info!("{} zones out of {} filtered out", nb_dropped, country_code);
+    Ok(nb_dropped)
+}
+
+// clip the zones' boundaries with the polygon(s) of a geojson file, and drop the zones outside of it
+// (the zones without boundary are kept if their center is in it).
+// The zones are reindexed, and the number of dropped zones is returned.
+fn clip_zones(zones: &mut Vec<Zone>, clip_path: &Path) -> Result<usize, Error> {
+    use geo::{Centroid, Contains};
+    info!("clipping the zones with {}", clip_path.display());
+    let clip = geometry::read_clip_polygon(clip_path)?;
+    let clip_geos =
+        geos::Geometry::try_from(&clip).context("failed to convert the clip polygon to geos")?;
+
+    let mut outside = HashSet::new();
+    for z in zones.iter_mut() {
+        let boundary = match &z.boundary {
+            Some(boundary) => boundary,
+            None => {
+                if !z.center.is_some_and(|c| clip.contains(&c)) {
+                    outside.insert(z.id);
+                }
+                continue;
+            }
+        };
+        match geometry::clip_boundary(boundary, &clip_geos) {
+            Ok(Some(clipped)) => {
+                // the center (eg. the admin_centre node) can be in the clipped out part
+                if !z.center.is_some_and(|c| clipped.contains(&c)) {
+                    z.center = clipped.centroid();
+                }
+                z.bbox = clipped.bounding_rect();
+                z.perimeter = Some(boundary_perimeter(&clipped));
+                z.num_holes = count_holes(&clipped);
+                z.boundary = Some(clipped);
+            }
+            Ok(None) => {
+                outside.insert(z.id);
+            }
+            Err(err) => warn!("impossible to clip {}, kept as is: {:?}", z.osm_id, err),
+        }
+    }
+
+    retain_zones(zones, |z| !outside.contains(&z.id));
+    info!(
+        "{} zones outside of the clip polygon dropped",
+        outside.len()
+    );
+    Ok(outside.len())
+}
+
+/// Type the zones, build their hierarchy and compute their labels
+///
+/// The zones do not have to come from osm: the osm objects are only used to build
+/// the voronoi zones, so `parsed_pbf` is only needed if `config.disable_voronoi` is false.
+/// The ids of the zones must be their positions in `zones`.
 pub fn create_ontology(
     zones: &mut Vec<Zone>,
     stats: &mut CosmogonyStats,
-    country_code: Option<String>,
-    disable_voronoi: bool,
-    parsed_pbf: &BTreeMap<OsmId, OsmObj>,
-    filter_langs: &[String],
+    parsed_pbf: Option<&BTreeMap<OsmId, OsmObj>>,
+    config: &BuildConfig,
 ) -> Result<(), Error> {
+    let parsed_pbf = match parsed_pbf {
+        Some(parsed_pbf) => Some(parsed_pbf),
+        None if config.disable_voronoi => None,
+        None => {
+            return Err(anyhow!(
+                "the osm objects are needed to compute the voronoi zones"
+            ))
+        }
+    };
     info!("creating ontology for {} zones", zones.len());
     let (inclusions, ztree) = find_inclusions(zones);
 
-    type_zones(zones, stats, country_code, &inclusions)?;
+    type_zones(zones, stats, config, &inclusions)?;
+
+    if config.center_from_capital {
+        if let Some(parsed_pbf) = parsed_pbf {
+            set_capital_centers(zones, parsed_pbf);
+        }
+    }
+
+    stats.containment_conflicts = find_containment_conflicts(zones, &inclusions);
+
+    if config.keep_inclusions {
+        // for debugging purpose, we keep all the zones that contained a zone
+        zones
+            .iter_mut()
+            .zip(inclusions.iter())
+            .for_each(|(z, inclusion)| z.candidate_parents = inclusion.clone());
+    }
 
     build_hierarchy(zones, inclusions);
 
-    if !disable_voronoi {
-        compute_additional_places(zones, parsed_pbf, ztree);
+    if let Some(parsed_pbf) = parsed_pbf {
+        if !config.disable_voronoi {
+            compute_additional_places(
+                zones,
+                parsed_pbf,
+                ztree,
+                &config.voronoi_place_types,
+                config.voronoi_city_parents_only,
+                config.progress,
+            );
+        }
+    }
+
+    if config.merge_same_name_siblings {
+        // done after the voronoi, so the generated zones are also moved to the merged zones
+        let merged: HashSet<ZoneIndex> = merge_same_name_siblings(zones).into_iter().collect();
+        stats.merged_siblings = merged.len();
+        zones.retain(|z| !merged.contains(&z.id));
+        reindex_zones(zones);
+    }
+
+    zones
+        .iter_mut()
+        .for_each(|z| z.compute_names(config.inherit_center_wikidata));
+
+    // the tags are pruned after the names computation, since it needs all the name tags
+    if !config.keep_tags.is_empty() {
+        prune_tags(zones, &config.keep_tags);
     }
 
-    zones.iter_mut().for_each(|z| z.compute_names());
+    compute_labels(zones, &config.filter_langs, &config.label_format);
 
-    compute_labels(zones, filter_langs);
+    if config.keep_untyped {
+        // Note: this is done after the hierarchy building, so those zones, already
+        // untyped during the hierarchy building, still cannot be the parent of another zone
+        type_untyped_zones(zones);
+    }
+
+    if !config.keep_international_names {
+        // the names are only needed to compute the labels, we do not want to serialize them
+        zones
+            .iter_mut()
+            .for_each(|z| z.international_names = BTreeMap::default());
+    }
+
+    if !config.keep_provenance {
+        // the provenance of the admin levels is only useful to audit the typing
+        zones.iter_mut().for_each(|z| z.admin_level_source = None);
+    }
+
+    if !config.keep_voronoi_debug {
+        // the voronoi's debug info are only useful to diagnose the voronoi artifacts
+        zones.iter_mut().for_each(|z| z.voronoi_debug = None);
+    }
 
     // We remove the useless zones from cosmogony.
     //
@@ -230,41 +780,214 @@ pub fn create_ontology(
     // it's not elegant, but for the moment it'll do.
     clean_untagged_zones(zones);
 
+    compute_areas(zones, config.area_model);
+    stats.center_outside_boundary = check_centers(zones, config.relocate_outside_centers);
+
     Ok(())
 }
 
-pub fn build_cosmogony(
-    pbf_path: String,
-    country_code: Option<String>,
-    disable_voronoi: bool,
-    filter_langs: &[String],
-) -> Result<Cosmogony, Error> {
-    let path = Path::new(&pbf_path);
+// the boundaries do not change anymore, their area can be computed
+fn compute_areas(zones: &mut [Zone], model: AreaModel) {
+    use rayon::prelude::*;
+
+    info!("computing the zones' area");
+    zones.par_iter_mut().for_each(|z| {
+        z.area = z.boundary.as_ref().map(|b| boundary_area(b, model));
+    });
+}
+
+// find the zones with a center node outside of their boundary (and move their center
+// in the boundary if `relocate` is true), the osm_ids of those zones are returned.
+// The center comes from a node if the zone has center tags.
+fn check_centers(zones: &mut [Zone], relocate: bool) -> Vec<String> {
+    let mut outside = vec![];
+    for z in zones.iter_mut() {
+        if z.center_tags.is_empty() || z.boundary.is_none() {
+            continue;
+        }
+        if z.center.is_none_or(|c| z.contains_point(c.x(), c.y())) {
+            continue;
+        }
+        warn!("the center of {} is outside of its boundary", z.osm_id);
+        if relocate {
+            z.center = z.representative_point();
+        }
+        outside.push(z.osm_id.clone());
+    }
+    outside
+}
+
+fn read_pbf(
+    path: &Path,
+    filter: impl FnMut(&OsmObj) -> bool,
+) -> Result<BTreeMap<OsmId, OsmObj>, Error> {
     info!("Reading pbf with geometries...");
     let file = File::open(path).context("no pbf file")?;
     let file = BufReader::with_capacity(FILE_BUF_SIZE, file);
 
     let parsed_pbf = OsmPbfReader::new(file)
-        .get_objs_and_deps(|o| is_admin(o) || is_place(o))
+        .get_objs_and_deps(filter)
         .context("invalid osm file")?;
     info!("reading pbf done.");
+    Ok(parsed_pbf)
+}
 
-    let (mut zones, mut stats) = get_zones_and_stats(&parsed_pbf)?;
+// use the capital as the center of the countries and the states
+//
+// this is done once the zones are typed, since the admin_level of a state depends on the country
+fn set_capital_centers(zones: &mut [Zone], parsed_pbf: &BTreeMap<OsmId, OsmObj>) {
+    for zone in zones
+        .iter_mut()
+        .filter(|z| matches!(z.zone_type, Some(ZoneType::Country | ZoneType::State)))
+    {
+        let capital = zone
+            .osm_id
+            .strip_prefix("relation:")
+            .and_then(|id| id.parse().ok())
+            .and_then(|id| parsed_pbf.get(&OsmId::Relation(RelationId(id))))
+            .and_then(|obj| obj.relation())
+            .and_then(|relation| capital_centre(relation, parsed_pbf));
+        if let Some(capital) = capital {
+            debug!("the center of {} is its capital", zone.osm_id);
+            zone.center = Some(Coord::new(capital.lon(), capital.lat()));
+            zone.center_tags = capital.tags.clone();
+        }
+    }
+}
 
-    create_ontology(
-        &mut zones,
-        &mut stats,
-        country_code,
-        disable_voronoi,
-        &parsed_pbf,
-        filter_langs,
-    )?;
+// the zones read, their stats, and the osm objects still needed after the reading (if any)
+type ReadZones = (Vec<Zone>, CosmogonyStats, Option<BTreeMap<OsmId, OsmObj>>);
+
+// read the zones of the osm file (or of the checkpoint to resume from), with their geometries,
+// and filter them, before the ontology creation
+//
+// the zones read from the osm file are saved in `config.checkpoint` if `write_checkpoint` is true
+fn read_zones(config: &BuildConfig, write_checkpoint: bool) -> Result<ReadZones, Error> {
+    let path = config.pbf_path.as_path();
+
+    let (mut zones, mut stats, parsed_pbf) = match &config.resume_from {
+        Some(resume_from) => {
+            let zones = checkpoint::read_checkpoint(resume_from)?;
+            // the relations are also needed to find the capitals
+            let parsed_pbf = if config.disable_voronoi && !config.center_from_capital {
+                None
+            } else {
+                Some(read_pbf(path, |o| {
+                    is_place_of_types(o, &config.voronoi_place_types)
+                        || (config.center_from_capital && is_admin(o))
+                })?)
+            };
+            (zones, CosmogonyStats::default(), parsed_pbf)
+        }
+        None => {
+            let parsed_pbf = read_pbf(path, |o| {
+                is_admin(o)
+                    || is_disputed(o)
+                    || (config.include_maritime && is_maritime(o))
+                    || is_place_of_types(o, &config.voronoi_place_types)
+                    || (config.infer_missing_admin_level && is_admin_without_level(o))
+            })?;
+            let (zones, stats) = get_zones_and_stats(&parsed_pbf, config)?;
+            if let Some(checkpoint) = config.checkpoint.as_ref().filter(|_| write_checkpoint) {
+                checkpoint::write_checkpoint(&zones, checkpoint)?;
+            }
+            (zones, stats, Some(parsed_pbf))
+        }
+    };
+
+    if let Some(country_code) = &config.only_country {
+        // the filter is done before the typing and the voronoi, so they are not done on the dropped zones
+        stats.out_of_country_zones = filter_country(&mut zones, country_code)?;
+    }
+
+    if let Some(clip) = &config.clip {
+        stats.clipped_out_zones = clip_zones(&mut zones, clip)?;
+    }
+    Ok((zones, stats, parsed_pbf))
+}
+
+/// What a build would be made of, see [`dry_run`]
+#[derive(Debug, Default)]
+pub struct DryRunReport {
+    /// the stats of the zones read from the osm file (they are not typed yet)
+    pub stats: CosmogonyStats,
+    /// number of zones read from the osm file
+    pub nb_zones: usize,
+    /// the countries found (country code and name)
+    pub countries: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} zones", self.nb_zones)?;
+        write!(f, "{}", self.stats)?;
+        writeln!(f, "{} countries found", self.countries.len())?;
+        for (country_code, name) in &self.countries {
+            writeln!(f, "    {}: {}", country_code, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the osm file and the configuration, without building the cosmogony
+///
+/// The zones are read (with their geometries) and filtered like in [`build_cosmogony_with_config`],
+/// and their countries are searched, but the ontology is not created
+/// (and no checkpoint is written).
+/// This fails like the build if no country can be found, so the errors are known
+/// long before the end of a build.
+pub fn dry_run(config: &BuildConfig) -> Result<DryRunReport, Error> {
+    // a dry run does not write anything
+    let (zones, mut stats, _) = read_zones(config, false)?;
+    let zone_typer = read_zone_typer(config)?;
+    let country_finder = CountryFinder::init(&zones, &zone_typer, config.country_fallback);
+    check_countries(config, &country_finder)?;
+    stats.compute(&zones);
+    let countries = country_finder
+        .countries()
+        .map(|(idx, country_code)| (country_code.to_string(), zones[idx.index].name.clone()))
+        .collect();
+    Ok(DryRunReport {
+        stats,
+        nb_zones: zones.len(),
+        countries,
+    })
+}
+
+/// Build a cosmogony from an osm pbf file, see [`BuildConfig`] for the available options
+pub fn build_cosmogony_with_config(config: &BuildConfig) -> Result<Cosmogony, Error> {
+    let path = config.pbf_path.as_path();
+    let (mut zones, mut stats, parsed_pbf) = read_zones(config, true)?;
+
+    create_ontology(&mut zones, &mut stats, parsed_pbf.as_ref(), config)?;
+
+    if config.keep_osm_meta {
+        osm_meta::add_osm_meta(&mut zones, path)?;
+    }
+
+    if let Some(attributes) = &config.attributes {
+        attributes::join_attributes(&mut zones, attributes)?;
+    }
+
+    if !config.only_types.is_empty() {
+        // the filter is done after the ontology creation, so the labels still contain all the ancestors
+        filter_zone_types(&mut zones, &config.only_types);
+    }
+
+    if let Some(name_filter) = &config.name_filter {
+        filter_names(&mut zones, name_filter);
+    }
+
+    if config.sort {
+        sort_zones(&mut zones);
+    }
 
     stats.compute(&zones);
 
     let cosmogony = Cosmogony {
         zones,
         meta: CosmogonyMetadata {
+            schema_version: cosmogony::SCHEMA_VERSION,
             osm_filename: path
                 .file_name()
                 .and_then(|f| f.to_str())
@@ -275,3 +998,20 @@ pub fn build_cosmogony(
     };
     Ok(cosmogony)
 }
+
+/// Build a cosmogony from an osm pbf file, with the former positional parameters
+#[deprecated(note = "use `build_cosmogony_with_config` with a `BuildConfig` instead")]
+pub fn build_cosmogony(
+    pbf_path: String,
+    country_code: Option<String>,
+    disable_voronoi: bool,
+    filter_langs: &[String],
+) -> Result<Cosmogony, Error> {
+    let config = BuildConfig {
+        country_code,
+        ..BuildConfig::new(pbf_path)
+    }
+    .disable_voronoi(disable_voronoi)
+    .filter_langs(filter_langs.to_vec());
+    build_cosmogony_with_config(&config)
+}
@@ -0,0 +1,215 @@
+//! Batch reverse geocoding of points with the zones of a cosmogony
+//!
+//! The input CSV file must have a header with a `lon` and a `lat` column
+//! (the other columns are ignored), eg:
+//!
+//! ```text
+//! lon,lat
+//! 6.1296,49.6112
+//! ```
+//!
+//! The output CSV file has one row per input point, in the same order, with the columns:
+//!
+//! `lon,lat,zone_osm_id,zone_name,city_osm_id,city_name,state_osm_id,state_name,country_osm_id,country_name`
+//!
+//! The zone is the most precise zone containing the point
+//! (see [`CosmogonyIndex::query`](crate::CosmogonyIndex::query)), the city, state and country
+//! are the zone itself or its ancestors of those types.
+//! The columns of a missing zone are empty (so all the columns but `lon` and `lat` are
+//! empty for a point in no zone).
+
+use crate::hierarchy_builder::ZonesTree;
+use anyhow::{anyhow, Context, Result};
+use cosmogony::{load_cosmogony_from_file, Cosmogony, Zone, ZoneIndex, ZoneType};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The columns of the output CSV file
+pub const OUTPUT_HEADERS: [&str; 10] = [
+    "lon",
+    "lat",
+    "zone_osm_id",
+    "zone_name",
+    "city_osm_id",
+    "city_name",
+    "state_osm_id",
+    "state_name",
+    "country_osm_id",
+    "country_name",
+];
+
+/// Find the most precise zone containing each point (a `(lon, lat)`), in parallel
+///
+/// The same zones as [`CosmogonyIndex::query`](crate::CosmogonyIndex::query) are found,
+/// but the index is shared by all the threads: the GEOS prepared geometries of a
+/// [`CosmogonyIndex`](crate::CosmogonyIndex) cannot be shared,
+/// so the zones' boundaries are checked with `geo`, and only the rtree of their bboxes
+/// is built (once).
+pub fn locate_points(cosmogony: &Cosmogony, points: &[(f64, f64)]) -> Vec<Option<ZoneIndex>> {
+    let tree: ZonesTree = cosmogony
+        .zones
+        .iter()
+        .filter(|z| z.boundary.is_some())
+        .collect();
+    let zones_by_id: HashMap<ZoneIndex, &Zone> =
+        cosmogony.zones.iter().map(|z| (z.id, z)).collect();
+    points
+        .par_iter()
+        .map(|&(lon, lat)| {
+            tree.fetch_point(lon, lat)
+                .into_iter()
+                .filter_map(|id| zones_by_id.get(&id))
+                .filter(|z| z.contains_point(lon, lat))
+                .max_by_key(|z| z.depth)
+                .map(|z| z.id)
+        })
+        .collect()
+}
+
+/// The osm_ids and names of a zone and of its city, state and country,
+/// empty if there is no such zone
+pub fn zone_chain(cosmogony: &Cosmogony, zone: Option<&Zone>) -> [(String, String); 4] {
+    let chain: Vec<&Zone> = zone
+        .into_iter()
+        .flat_map(|z| std::iter::once(z).chain(cosmogony.ancestors(z.id)))
+        .collect();
+    let columns =
+        |z: Option<&&Zone>| z.map_or_else(Default::default, |z| (z.osm_id.clone(), z.name.clone()));
+    let of_type = |t| chain.iter().find(|z| z.zone_type == Some(t));
+    [
+        columns(chain.first()),
+        columns(of_type(ZoneType::City)),
+        columns(of_type(ZoneType::State)),
+        columns(of_type(ZoneType::Country)),
+    ]
+}
+
+// read the `lon` and `lat` columns of a CSV file
+fn read_points(path: &Path) -> Result<Vec<(f64, f64)>> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("impossible to read {}", path.display()))?;
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| anyhow!("no column '{}' in {}", name, path.display()))
+    };
+    let (lon_idx, lat_idx) = (column("lon")?, column("lat")?);
+    reader
+        .records()
+        .enumerate()
+        .map(|(pos, record)| {
+            let record = record?;
+            let coord = |idx: usize| -> Result<f64> {
+                let value = record.get(idx).unwrap_or_default();
+                value.trim().parse().with_context(|| {
+                    // the line number, with the header
+                    format!("invalid coordinate '{}' on line {}", value, pos + 2)
+                })
+            };
+            Ok((coord(lon_idx)?, coord(lat_idx)?))
+        })
+        .collect()
+}
+
+/// Locate the points of a CSV file in the zones of a cosmogony file,
+/// see the [module documentation](self) for the CSV files' schema
+pub fn locate_csv(input: &Path, points: &Path, output: &Path) -> Result<()> {
+    let cosmogony = load_cosmogony_from_file(input)?;
+    let points = read_points(points)?;
+    info!(
+        "locating {} points in the {} zones of {}",
+        points.len(),
+        cosmogony.zones.len(),
+        input.display()
+    );
+    let located = locate_points(&cosmogony, &points);
+
+    let mut writer = csv::Writer::from_path(output)
+        .with_context(|| format!("impossible to write {}", output.display()))?;
+    writer.write_record(OUTPUT_HEADERS)?;
+    let mut nb_located = 0;
+    for ((lon, lat), zone_idx) in points.iter().zip(located) {
+        let zone = zone_idx.and_then(|idx| cosmogony.zone_by_id(idx));
+        if zone.is_some() {
+            nb_located += 1;
+        }
+        let mut record = vec![lon.to_string(), lat.to_string()];
+        for (osm_id, name) in zone_chain(&cosmogony, zone) {
+            record.push(osm_id);
+            record.push(name);
+        }
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+    info!("{} of the {} points located", nb_located, points.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmogony::CosmogonyMetadata;
+    use geo::bounding_rect::BoundingRect;
+    use geo_types::{LineString, MultiPolygon, Polygon};
+
+    fn make_zone(index: usize, zone_type: ZoneType, size: f64, parent: Option<usize>) -> Zone {
+        let boundary = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![
+                (0., 0.),
+                (0., size),
+                (size, size),
+                (size, 0.),
+                (0., 0.),
+            ]),
+            vec![],
+        )]);
+        Zone {
+            id: ZoneIndex { index },
+            osm_id: format!("relation:{}", index),
+            name: format!("{:?}", zone_type),
+            zone_type: Some(zone_type),
+            parent: parent.map(|index| ZoneIndex { index }),
+            depth: index as u32,
+            bbox: boundary.bounding_rect(),
+            boundary: Some(boundary),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn locate_some_points() {
+        let cosmogony = Cosmogony {
+            zones: vec![
+                make_zone(0, ZoneType::Country, 10., None),
+                make_zone(1, ZoneType::City, 2., Some(0)),
+                make_zone(2, ZoneType::Suburb, 1., Some(1)),
+            ],
+            meta: CosmogonyMetadata::default(),
+        };
+        let points = vec![(0.5, 0.5), (1.5, 1.5), (5., 5.), (20., 20.)];
+        let located = locate_points(&cosmogony, &points);
+        assert_eq!(
+            located,
+            vec![
+                Some(ZoneIndex { index: 2 }),
+                Some(ZoneIndex { index: 1 }),
+                Some(ZoneIndex { index: 0 }),
+                None
+            ]
+        );
+
+        let chain = |idx: Option<ZoneIndex>| {
+            let zone = idx.and_then(|idx| cosmogony.zone_by_id(idx));
+            zone_chain(&cosmogony, zone).map(|(osm_id, _)| osm_id)
+        };
+        assert_eq!(
+            chain(located[0]),
+            ["relation:2", "relation:1", "", "relation:0"]
+        );
+        assert_eq!(chain(located[2]), ["relation:0", "", "", "relation:0"]);
+        assert_eq!(chain(None), ["", "", "", ""]);
+    }
+}
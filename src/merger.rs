@@ -1,5 +1,5 @@
-use anyhow::Result;
-use cosmogony::{file_format::OutputFormat, read_zones_from_file, Zone, ZoneIndex};
+use anyhow::{anyhow, Result};
+use cosmogony::{file_format, file_format::OutputFormat, read_zones_from_file, Zone, ZoneIndex};
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use std::path::{Path, PathBuf};
@@ -9,7 +9,7 @@ struct CosmogonyMerger {
     id_offset: usize,
 }
 
-fn to_json_stream(
+pub(crate) fn to_json_stream(
     mut writer: impl std::io::Write,
     zones: impl std::iter::Iterator<Item = Zone>,
 ) -> Result<()> {
@@ -25,7 +25,11 @@ impl CosmogonyMerger {
         &mut self,
         files: &[PathBuf],
         mut writer: impl std::io::Write,
+        with_schema_marker: bool,
     ) -> Result<()> {
+        if with_schema_marker {
+            file_format::write_schema_marker(&mut writer)?;
+        }
         for f in files {
             self.read_cosmogony(f, &mut writer)?;
         }
@@ -33,18 +37,23 @@ impl CosmogonyMerger {
     }
 
     fn read_cosmogony(&mut self, file: &Path, writer: impl std::io::Write) -> Result<()> {
-        let mut max_id = 0;
+        let mut next_offset = self.id_offset;
         let zones = read_zones_from_file(file)?
             .filter_map(|z| z.ok())
             .map(|mut z| {
                 z.id = self.get_updated_id(z.id);
-                max_id = std::cmp::max(max_id, z.id.index);
+                next_offset = std::cmp::max(next_offset, z.id.index + 1);
                 z.parent = z.parent.map(|p| self.get_updated_id(p));
+                z.candidate_parents = z
+                    .candidate_parents
+                    .iter()
+                    .map(|p| self.get_updated_id(*p))
+                    .collect();
                 z
             });
         to_json_stream(writer, zones)?;
         // we update the id_offset, for the next file
-        self.id_offset = max_id + 1;
+        self.id_offset = next_offset;
         Ok(())
     }
 
@@ -55,22 +64,73 @@ impl CosmogonyMerger {
     }
 }
 
-pub fn merge_cosmogony(files: &[PathBuf], output: &Path) -> Result<()> {
+// the first id after the ones of the zones of an already merged cosmogony
+fn next_id(file: &Path) -> Result<usize> {
+    let mut next_id = 0;
+    for z in read_zones_from_file(file)? {
+        next_id = std::cmp::max(next_id, z?.id.index + 1);
+    }
+    Ok(next_id)
+}
+
+/// Merge several streamed cosmogonies into one
+///
+/// In append mode, the zones are added at the end of the output if it already exists,
+/// with ids following the ones of the existing zones.
+/// Note: the parents are only remapped within a file, a zone cannot reference a parent
+/// in another file (nor in the existing output)
+pub fn merge_cosmogony(
+    files: &[PathBuf],
+    output: &Path,
+    compression: Compression,
+    zstd_level: i32,
+    append: bool,
+) -> Result<()> {
     let mut merger = CosmogonyMerger::default();
 
     let format = OutputFormat::from_filename(output)?;
-    let file = std::fs::File::create(output)?;
-    let mut stream = std::io::BufWriter::new(file);
+    let appending = append && output.exists() && output.metadata()?.len() > 0;
+    // the output is only opened once its format is known to be supported,
+    // so that an existing file is not truncated for nothing
+    let mut open_output = || -> Result<std::io::BufWriter<std::fs::File>> {
+        let file = if appending {
+            merger.id_offset = next_id(output)?;
+            info!(
+                "appending to {}, the new zones' ids start at {}",
+                output.display(),
+                merger.id_offset
+            );
+            std::fs::OpenOptions::new().append(true).open(output)?
+        } else {
+            std::fs::File::create(output)?
+        };
+        Ok(std::io::BufWriter::new(file))
+    };
     match format {
-        OutputFormat::JsonGz | OutputFormat::Json => panic!(
-            "cannot write real cosmogonies, only jsonl/jsonl.gz to be able to stream the files"
-        ),
+        OutputFormat::JsonGz
+        | OutputFormat::Json
+        | OutputFormat::JsonZst
+        | OutputFormat::FlatGeobuf
+        | OutputFormat::GeoJsonSeq
+        | OutputFormat::GeoPackage => {
+            return Err(anyhow!(
+                "cannot write real cosmogonies, only jsonl/jsonl.gz/jsonl.zst to be able to stream the files"
+            ))
+        }
         OutputFormat::JsonStream => {
-            merger.merge_cosmogony(files, &mut stream)?;
+            let mut stream = open_output()?;
+            merger.merge_cosmogony(files, &mut stream, !appending)?;
         }
         OutputFormat::JsonStreamGz => {
-            let mut e = GzEncoder::new(stream, Compression::default());
-            merger.merge_cosmogony(files, &mut e)?;
+            // when appending, a new gzip member is added after the existing ones
+            let mut e = GzEncoder::new(open_output()?, compression);
+            merger.merge_cosmogony(files, &mut e, !appending)?;
+        }
+        OutputFormat::JsonStreamZst => {
+            // when appending, a new zstd frame is added after the existing ones
+            let mut e = zstd::stream::write::Encoder::new(open_output()?, zstd_level)?;
+            merger.merge_cosmogony(files, &mut e, !appending)?;
+            e.finish()?;
         }
     };
     Ok(())
@@ -0,0 +1,122 @@
+//! Version and timestamp of the osm relations the zones are built from
+//!
+//! The osm objects read by osmpbfreader do not have their metadata, so they are read
+//! from the raw pbf blocks, in an additional pass over the file.
+
+use anyhow::{Context, Error};
+use cosmogony::Zone;
+use osmpbfreader::{primitive_block_from_blob, OsmPbfReader};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+const RELATION_PREFIX: &str = "relation:";
+
+/// The metadata of the last edit of an osm object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OsmMeta {
+    pub version: i32,
+    /// ISO 8601 UTC timestamp (eg: `2018-04-17T10:20:04Z`)
+    pub timestamp: String,
+}
+
+// days since the 1970-01-01 to (year, month, day), see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format a number of seconds since the epoch as an ISO 8601 UTC timestamp
+fn format_timestamp(secs: i64) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let secs_of_day = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Read the metadata of the given relations (by osm id) in an osm pbf file
+///
+/// The relations without metadata in the file are not returned.
+pub fn read_relations_meta(
+    path: &Path,
+    relation_ids: &HashSet<i64>,
+) -> Result<HashMap<i64, OsmMeta>, Error> {
+    info!("reading the relations' metadata...");
+    let file = File::open(path).context("no pbf file")?;
+    let mut reader = OsmPbfReader::new(BufReader::new(file));
+
+    let mut metas = HashMap::new();
+    for blob in reader.blobs() {
+        let block = primitive_block_from_blob(&blob.context("invalid osm file")?)
+            .context("invalid osm block")?;
+        // the timestamps are in date_granularity milliseconds units
+        let granularity = i64::from(block.get_date_granularity());
+        for relation in block.primitivegroup.iter().flat_map(|g| g.relations.iter()) {
+            let info = relation.get_info();
+            if !relation_ids.contains(&relation.get_id()) || !info.has_version() {
+                continue;
+            }
+            metas.insert(
+                relation.get_id(),
+                OsmMeta {
+                    version: info.get_version(),
+                    timestamp: format_timestamp(info.get_timestamp() * granularity / 1000),
+                },
+            );
+        }
+    }
+    info!("metadata read for {} relations", metas.len());
+    Ok(metas)
+}
+
+/// Fill the `osm_version` and `osm_timestamp` of the zones built from an osm relation
+pub fn add_osm_meta(zones: &mut [Zone], path: &Path) -> Result<(), Error> {
+    let positions: HashMap<i64, usize> = zones
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, z)| {
+            z.osm_id
+                .strip_prefix(RELATION_PREFIX)
+                .and_then(|id| id.parse().ok())
+                .map(|id| (id, pos))
+        })
+        .collect();
+
+    let relation_ids = positions.keys().copied().collect();
+    let metas = read_relations_meta(path, &relation_ids)?;
+    for (id, meta) in metas {
+        let zone = &mut zones[positions[&id]];
+        zone.osm_version = Some(meta.version);
+        zone.osm_timestamp = Some(meta.timestamp);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::format_timestamp;
+
+    #[test]
+    fn format_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1_523_960_404), "2018-04-17T10:20:04Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59Z");
+    }
+}
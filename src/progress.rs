@@ -0,0 +1,116 @@
+//! Periodic logging of the progress of the long steps of a build (typing, voronoi)
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// minimum delay between 2 logs of a step's progress
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Progress of a step, with a rough ETA based on the throughput since its start
+///
+/// The progress can be shared between threads. When it is disabled,
+/// counting an item only checks a boolean.
+pub(crate) struct Progress {
+    step: &'static str,
+    total: usize,
+    enabled: bool,
+    done: AtomicUsize,
+    start: Instant,
+    // milliseconds between the start and the last log
+    last_log: AtomicU64,
+}
+
+impl Progress {
+    pub fn new(step: &'static str, total: usize, enabled: bool) -> Self {
+        Self {
+            step,
+            total,
+            enabled,
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+            last_log: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a processed item, and log the progress if the last log is old enough
+    pub fn inc(&self) {
+        if !self.enabled {
+            return;
+        }
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let elapsed = self.start.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let last_log = self.last_log.load(Ordering::Relaxed);
+        if elapsed_ms < last_log + LOG_INTERVAL.as_millis() as u64 {
+            return;
+        }
+        // only one thread logs the progress
+        if self
+            .last_log
+            .compare_exchange(last_log, elapsed_ms, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        info!(
+            "{}: {}/{} done ({:.1}%), ETA {}",
+            self.step,
+            done,
+            self.total,
+            100. * done as f64 / self.total.max(1) as f64,
+            eta(elapsed, done, self.total)
+                .map(format_duration)
+                .unwrap_or_else(|| "unknown".into())
+        );
+    }
+}
+
+// the remaining time if the throughput stays the same
+fn eta(elapsed: Duration, done: usize, total: usize) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(done) as f64;
+    Some(elapsed.mul_f64(remaining / done as f64))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, s) => format!("{}h{:02}m{:02}s", h, m, s),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn eta_from_throughput() {
+        let elapsed = Duration::from_secs(10);
+        assert_eq!(eta(elapsed, 0, 100), None);
+        assert_eq!(eta(elapsed, 25, 100), Some(Duration::from_secs(30)));
+        assert_eq!(eta(elapsed, 100, 100), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(62)), "1m02s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+
+    #[test]
+    fn disabled_progress() {
+        let progress = Progress::new("test", 10, false);
+        progress.inc();
+        assert_eq!(progress.done.load(Ordering::Relaxed), 0);
+
+        let progress = Progress::new("test", 10, true);
+        progress.inc();
+        progress.inc();
+        assert_eq!(progress.done.load(Ordering::Relaxed), 2);
+    }
+}
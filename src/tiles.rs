@@ -0,0 +1,528 @@
+//! Vector tiles (MVT) of the zones' boundaries, packed in an MBTiles file
+//!
+//! The tiles have one layer per zone type (named after the type, eg: `city`), with the
+//! `name`, `osm_id` and `admin_level` of the zones as properties.
+//!
+//! The boundaries are simplified depending on the zoom level: the simplification tolerance is
+//! one pixel of a 256 px tile, so about 600 m at zoom 6, 150 m at zoom 8, 40 m at zoom 10
+//! and 2.4 m at zoom 14 (at the equator, it is smaller towards the poles).
+//! The geometries are then clipped to the tiles, with a small buffer.
+
+use crate::geometry::convert_to_geo;
+use anyhow::{anyhow, Context, Result};
+use cosmogony::{read_zones_from_file, Zone, ZoneType};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use geo::MapCoords;
+use geo_types::{Coord, LineString, MultiPolygon, Polygon, Rect};
+use geos::{Geom, Geometry};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+
+/// Size of a tile, in tile units
+const EXTENT: u32 = 4096;
+/// Simplification tolerance, in tile units (one pixel of a 256 px tile)
+const SIMPLIFICATION_TOLERANCE: f64 = EXTENT as f64 / 256.;
+/// Buffer around the tiles when clipping the geometries, in tile units
+const BUFFER: f64 = 64.;
+/// Maximum latitude of the web mercator projection
+const MAX_LATITUDE: f64 = 85.051_128_78;
+/// Maximum zoom level of the tiles
+pub const MAX_ZOOM: u8 = 18;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+/// Position of a lon/lat in the web mercator world at a zoom level, in tile units
+/// (the world is `2^zoom * EXTENT` wide, with the y axis pointing south)
+fn project(coord: Coord<f64>, zoom: u8) -> Coord<f64> {
+    let world_size = f64::from(1u32 << zoom) * f64::from(EXTENT);
+    let lat = coord.y.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    Coord {
+        x: (coord.x + 180.) / 360. * world_size,
+        y: (1. - (lat.tan() + 1. / lat.cos()).ln() / std::f64::consts::PI) / 2. * world_size,
+    }
+}
+
+/// The (x, y) tiles intersecting a bbox at a zoom level
+fn tiles_range(bbox: &Rect<f64>, zoom: u8) -> impl Iterator<Item = (u32, u32)> {
+    let max_tile = (1u32 << zoom) - 1;
+    let tile = |v: f64| ((v / f64::from(EXTENT)).floor().max(0.) as u32).min(max_tile);
+    // the north of the bbox is its min y once projected
+    let min = project(
+        Coord {
+            x: bbox.min().x,
+            y: bbox.max().y,
+        },
+        zoom,
+    );
+    let max = project(
+        Coord {
+            x: bbox.max().x,
+            y: bbox.min().y,
+        },
+        zoom,
+    );
+    let (x_range, y_range) = (tile(min.x)..=tile(max.x), tile(min.y)..=tile(max.y));
+    x_range.flat_map(move |x| y_range.clone().map(move |y| (x, y)))
+}
+
+fn zigzag(v: i32) -> u32 {
+    ((v << 1) ^ (v >> 31)) as u32
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    (id & 0x7) | ((count as u32) << 3)
+}
+
+// the rounded coordinates of a ring, without the closing point nor the repeated points
+fn ring_points(ring: &LineString<f64>) -> Vec<(i32, i32)> {
+    let mut points: Vec<(i32, i32)> = ring
+        .coords()
+        .map(|c| (c.x.round() as i32, c.y.round() as i32))
+        .collect();
+    points.dedup();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+// twice the signed area of a ring, positive if the ring is clockwise on screen (y pointing south)
+fn signed_area(points: &[(i32, i32)]) -> i64 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|((x1, y1), (x2, y2))| {
+            i64::from(*x1) * i64::from(*y2) - i64::from(*x2) * i64::from(*y1)
+        })
+        .sum()
+}
+
+/// Encode polygons (in tile units, relative to the tile) as MVT geometry commands
+///
+/// The exterior rings are clockwise and the interior rings counterclockwise (on screen),
+/// as required by the MVT specification. The rings with no area once rounded are skipped.
+fn encode_polygons(polygons: &MultiPolygon<f64>) -> Vec<u32> {
+    let mut commands = vec![];
+    let mut cursor = (0, 0);
+    let mut encode_ring = |mut points: Vec<(i32, i32)>, exterior: bool| -> bool {
+        let area = signed_area(&points);
+        if points.len() < 3 || area == 0 {
+            return false;
+        }
+        if (area > 0) != exterior {
+            points.reverse();
+        }
+        commands.push(command(MOVE_TO, 1));
+        for (pos, (x, y)) in points.iter().enumerate() {
+            if pos == 1 {
+                commands.push(command(LINE_TO, points.len() - 1));
+            }
+            commands.push(zigzag(x - cursor.0));
+            commands.push(zigzag(y - cursor.1));
+            cursor = (*x, *y);
+        }
+        commands.push(command(CLOSE_PATH, 1));
+        true
+    };
+    for polygon in polygons {
+        if encode_ring(ring_points(polygon.exterior()), true) {
+            for interior in polygon.interiors() {
+                encode_ring(ring_points(interior), false);
+            }
+        }
+    }
+    commands
+}
+
+/// Minimal protobuf writer, for the messages of the MVT specification
+#[derive(Default)]
+struct PbfWriter {
+    buf: Vec<u8>,
+}
+
+impl PbfWriter {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(u64::from(field << 3 | u32::from(wire_type)));
+    }
+
+    fn uint(&mut self, field: u32, v: u64) {
+        self.key(field, 0);
+        self.varint(v);
+    }
+
+    fn bytes(&mut self, field: u32, data: &[u8]) {
+        self.key(field, 2);
+        self.varint(data.len() as u64);
+        self.buf.extend_from_slice(data);
+    }
+
+    fn packed(&mut self, field: u32, values: &[u32]) {
+        let mut packed = PbfWriter::default();
+        values.iter().for_each(|v| packed.varint(u64::from(*v)));
+        self.bytes(field, &packed.buf);
+    }
+}
+
+/// A property value of a feature
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Value {
+    String(String),
+    Uint(u64),
+}
+
+/// A zone's boundary in a tile
+struct Feature {
+    id: u64,
+    properties: Vec<(&'static str, Value)>,
+    geometry: Vec<u32>,
+}
+
+impl Feature {
+    fn new(zone: &Zone, geometry: Vec<u32>) -> Self {
+        let mut properties = vec![
+            ("name", Value::String(zone.name.clone())),
+            ("osm_id", Value::String(zone.osm_id.clone())),
+        ];
+        if let Some(level) = zone.admin_level {
+            properties.push(("admin_level", Value::Uint(level.into())));
+        }
+        Feature {
+            id: zone.id.index as u64,
+            properties,
+            geometry,
+        }
+    }
+}
+
+/// Encode a layer of features as a MVT `Layer` message
+fn encode_layer(name: &str, features: &[Feature]) -> Vec<u8> {
+    let mut keys: Vec<&str> = vec![];
+    let mut values: Vec<&Value> = vec![];
+    let mut values_idx: HashMap<&Value, u32> = HashMap::new();
+
+    let mut layer = PbfWriter::default();
+    layer.uint(15, 2); // version
+    layer.bytes(1, name.as_bytes());
+    for feature in features {
+        let mut tags = vec![];
+        for (key, value) in &feature.properties {
+            let key_idx = match keys.iter().position(|k| k == key) {
+                Some(idx) => idx,
+                None => {
+                    keys.push(key);
+                    keys.len() - 1
+                }
+            };
+            let value_idx = *values_idx.entry(value).or_insert_with(|| {
+                values.push(value);
+                values.len() as u32 - 1
+            });
+            tags.extend([key_idx as u32, value_idx]);
+        }
+        let mut f = PbfWriter::default();
+        f.uint(1, feature.id);
+        f.packed(2, &tags);
+        f.uint(3, 3); // polygon
+        f.packed(4, &feature.geometry);
+        layer.bytes(2, &f.buf);
+    }
+    for key in keys {
+        layer.bytes(3, key.as_bytes());
+    }
+    for value in values {
+        let mut v = PbfWriter::default();
+        match value {
+            Value::String(s) => v.bytes(1, s.as_bytes()),
+            Value::Uint(u) => v.uint(5, *u),
+        }
+        layer.bytes(4, &v.buf);
+    }
+    layer.uint(5, u64::from(EXTENT));
+    layer.buf
+}
+
+/// Encode the layers of a tile as a MVT `Tile` message
+fn encode_tile(layers: &BTreeMap<ZoneType, Vec<Feature>>) -> Vec<u8> {
+    let mut tile = PbfWriter::default();
+    for (zone_type, features) in layers {
+        tile.bytes(3, &encode_layer(zone_type.as_str(), features));
+    }
+    tile.buf
+}
+
+// the simplified boundary of a zone in the tiles it intersects at a zoom level
+fn zone_features(zone: &Zone, zoom: u8) -> Result<Vec<((u32, u32), Feature)>> {
+    let (boundary, bbox) = match (&zone.boundary, &zone.bbox) {
+        (Some(boundary), Some(bbox)) => (boundary, bbox),
+        _ => return Ok(vec![]),
+    };
+    let projected = boundary.map_coords(|c| project(c, zoom));
+    let simplified =
+        Geometry::try_from(&projected)?.topology_preserve_simplify(SIMPLIFICATION_TOLERANCE)?;
+
+    let mut features = vec![];
+    for (x, y) in tiles_range(bbox, zoom) {
+        let (min_x, min_y) = (f64::from(x * EXTENT), f64::from(y * EXTENT));
+        let tile_rect = Rect::new(
+            Coord {
+                x: min_x - BUFFER,
+                y: min_y - BUFFER,
+            },
+            Coord {
+                x: min_x + f64::from(EXTENT) + BUFFER,
+                y: min_y + f64::from(EXTENT) + BUFFER,
+            },
+        );
+        let tile_polygon: Polygon<f64> = tile_rect.to_polygon();
+        let clipped = simplified.intersection(&Geometry::try_from(&tile_polygon)?)?;
+        if clipped.is_empty()? {
+            continue;
+        }
+        let clipped = match convert_to_geo(clipped)? {
+            Some(clipped) => clipped.map_coords(|c| Coord {
+                x: c.x - min_x,
+                y: c.y - min_y,
+            }),
+            None => continue,
+        };
+        let geometry = encode_polygons(&clipped);
+        if !geometry.is_empty() {
+            features.push(((x, y), Feature::new(zone, geometry)));
+        }
+    }
+    Ok(features)
+}
+
+// the encoded tiles of a zoom level
+fn render_zoom(zones: &[Zone], zoom: u8) -> BTreeMap<(u32, u32), Vec<u8>> {
+    let features: Vec<(ZoneType, (u32, u32), Feature)> = zones
+        .par_iter()
+        .filter_map(|z| z.zone_type.map(|t| (t, z)))
+        .flat_map_iter(|(zone_type, z)| {
+            zone_features(z, zoom)
+                .map_err(|e| warn!("impossible to render {} at zoom {}: {}", z.osm_id, zoom, e))
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |(tile, feature)| (zone_type, tile, feature))
+        })
+        .collect();
+
+    let mut tiles: BTreeMap<(u32, u32), BTreeMap<ZoneType, Vec<Feature>>> = BTreeMap::new();
+    for (zone_type, tile, feature) in features {
+        tiles
+            .entry(tile)
+            .or_default()
+            .entry(zone_type)
+            .or_default()
+            .push(feature);
+    }
+    tiles
+        .into_par_iter()
+        .map(|(tile, layers)| (tile, encode_tile(&layers)))
+        .collect()
+}
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+// the metadata of the MBTiles file, see https://github.com/mapbox/mbtiles-spec
+fn metadata(zones: &[Zone], name: &str, min_zoom: u8, max_zoom: u8) -> Vec<(&'static str, String)> {
+    let bounds = zones
+        .iter()
+        .filter_map(|z| z.bbox)
+        .reduce(|a, b| {
+            Rect::new(
+                Coord {
+                    x: a.min().x.min(b.min().x),
+                    y: a.min().y.min(b.min().y),
+                },
+                Coord {
+                    x: a.max().x.max(b.max().x),
+                    y: a.max().y.max(b.max().y),
+                },
+            )
+        })
+        .map(|b| format!("{},{},{},{}", b.min().x, b.min().y, b.max().x, b.max().y))
+        .unwrap_or_else(|| "-180,-85.05112878,180,85.05112878".into());
+    let mut zone_types: Vec<ZoneType> = zones.iter().filter_map(|z| z.zone_type).collect();
+    zone_types.sort();
+    zone_types.dedup();
+    let vector_layers: Vec<_> = zone_types
+        .iter()
+        .map(|t| {
+            serde_json::json!({
+                "id": t.as_str(),
+                "fields": {"name": "String", "osm_id": "String", "admin_level": "Number"},
+                "minzoom": min_zoom,
+                "maxzoom": max_zoom,
+            })
+        })
+        .collect();
+    vec![
+        ("name", name.to_string()),
+        ("format", "pbf".into()),
+        ("type", "overlay".into()),
+        ("minzoom", min_zoom.to_string()),
+        ("maxzoom", max_zoom.to_string()),
+        ("bounds", bounds),
+        (
+            "json",
+            serde_json::json!({ "vector_layers": vector_layers }).to_string(),
+        ),
+    ]
+}
+
+/// Render the zones of a cosmogony file as vector tiles, in an MBTiles file
+pub fn render_tiles(input: &Path, min_zoom: u8, max_zoom: u8, output: &Path) -> Result<()> {
+    if min_zoom > max_zoom || max_zoom > MAX_ZOOM {
+        return Err(anyhow!(
+            "invalid zoom levels {}-{}, the zoom levels must be between 0 and {}",
+            min_zoom,
+            max_zoom,
+            MAX_ZOOM
+        ));
+    }
+    let zones = read_zones_from_file(input)?.collect::<Result<Vec<_>, _>>()?;
+
+    if output.exists() {
+        std::fs::remove_file(output)
+            .with_context(|| format!("impossible to overwrite {}", output.display()))?;
+    }
+    let mut conn = rusqlite::Connection::open(output)?;
+    conn.execute_batch(
+        "CREATE TABLE metadata (name text, value text);
+         CREATE TABLE tiles (zoom_level integer, tile_column integer, tile_row integer, tile_data blob);
+         CREATE UNIQUE INDEX tile_index on tiles (zoom_level, tile_column, tile_row);",
+    )?;
+    let name = input
+        .file_stem()
+        .and_then(|f| f.to_str())
+        .unwrap_or("cosmogony");
+    for (key, value) in metadata(&zones, name, min_zoom, max_zoom) {
+        conn.execute(
+            "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+            (key, value),
+        )?;
+    }
+
+    for zoom in min_zoom..=max_zoom {
+        let tiles = render_zoom(&zones, zoom);
+        info!("{} tiles rendered at zoom {}", tiles.len(), zoom);
+        let transaction = conn.transaction()?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for ((x, y), data) in tiles {
+                // the MBTiles rows follow the TMS scheme (y pointing north)
+                let row = (1u32 << zoom) - 1 - y;
+                insert.execute((zoom, x, row, gzip(&data)?))?;
+            }
+        }
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn project_coords() {
+        let center = project(Coord { x: 0., y: 0. }, 0);
+        assert_eq!((center.x, center.y.round()), (2048., 2048.));
+
+        let top_left = project(Coord { x: -180., y: 89. }, 1);
+        assert_eq!((top_left.x, top_left.y.round()), (0., 0.));
+
+        // Luxembourg is in the tile 8/132/87
+        let bbox = Rect::new(Coord { x: 6.1, y: 49.6 }, Coord { x: 6.2, y: 49.7 });
+        assert_eq!(tiles_range(&bbox, 8).collect::<Vec<_>>(), vec![(132, 87)]);
+        assert_eq!(tiles_range(&bbox, 0).collect::<Vec<_>>(), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn encode_square() {
+        // a counterclockwise square on screen, to be reversed
+        let square = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![(0., 0.), (0., 10.), (10., 10.), (10., 0.), (0., 0.)]),
+            vec![],
+        )]);
+        assert_eq!(
+            encode_polygons(&square),
+            vec![
+                command(MOVE_TO, 1),
+                zigzag(10),
+                0,
+                command(LINE_TO, 3),
+                0,
+                zigzag(10),
+                zigzag(-10),
+                0,
+                0,
+                zigzag(-10),
+                command(CLOSE_PATH, 1),
+            ]
+        );
+
+        // a flat ring is skipped
+        let flat = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![(0., 0.), (0.1, 0.), (0.2, 0.1), (0., 0.)]),
+            vec![],
+        )]);
+        assert!(encode_polygons(&flat).is_empty());
+    }
+
+    #[test]
+    fn encode_pbf() {
+        let mut w = PbfWriter::default();
+        w.varint(300);
+        assert_eq!(w.buf, vec![0xac, 0x02]);
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2), 3);
+    }
+
+    #[test]
+    fn encode_layer_with_shared_values() {
+        let zone = Zone {
+            name: "Luxembourg".into(),
+            osm_id: "relation:407489".into(),
+            admin_level: Some(8),
+            ..Default::default()
+        };
+        let features = vec![
+            Feature::new(&zone, vec![command(MOVE_TO, 1), 0, 0]),
+            Feature::new(&zone, vec![command(MOVE_TO, 1), 2, 2]),
+        ];
+        let layer = encode_layer("city", &features);
+        // the keys and values are only written once
+        let count = |s: &str| {
+            layer
+                .windows(s.len())
+                .filter(|w| *w == s.as_bytes())
+                .count()
+        };
+        assert_eq!(count("relation:407489"), 1);
+        assert_eq!(count("admin_level"), 1);
+        assert_eq!(count("city"), 1);
+    }
+}
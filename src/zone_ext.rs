@@ -2,9 +2,13 @@
 // The Zone's capabilities have been split in order to hide some functions specific to cosmogony
 // and that we do not want to expose in the model
 
-use cosmogony::{mutable_slice::MutableSlice, Coord, Zone, ZoneIndex, ZoneType};
+use cosmogony::{mutable_slice::MutableSlice, AdminLevelSource, Coord, Zone, ZoneIndex, ZoneType};
 use geo::algorithm::bounding_rect::BoundingRect;
-use geo::prelude::{Contains, Intersects};
+use geo::orient::{Direction, Orient};
+use geo::prelude::{
+    ChamberlainDuquetteArea, Contains, GeodesicArea, GeodesicLength, HaversineDistance, Intersects,
+};
+use geo::{LineString, MultiPolygon, Point, Polygon, Rect};
 use geos::Geom;
 use geos::Geometry;
 use itertools::Itertools;
@@ -12,16 +16,21 @@ use osm_boundaries_utils::build_boundary;
 use osmpbfreader::objects::{Node, OsmId, OsmObj, Relation, Tags};
 use regex::Regex;
 use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
 
 pub trait ZoneExt {
     /// create a zone from an osm node
     fn from_osm_node(node: &Node, index: ZoneIndex) -> Option<Zone>;
 
     /// create a zone from an osm relation and a geometry
+    ///
+    /// The `name:*` tags of the relation's label node are added to the zone's tags,
+    /// `names_policy` tells which one is kept if both have the same tag
     fn from_osm_relation(
         relation: &Relation,
         objects: &BTreeMap<OsmId, OsmObj>,
         index: ZoneIndex,
+        names_policy: NamesMergePolicy,
     ) -> Option<Zone>;
 
     /// check is a zone contains another zone
@@ -34,15 +43,39 @@ pub trait ZoneExt {
     fn intersects(&self, other: &Zone) -> bool;
 
     /// compute the labels of a zone
-    fn compute_labels(&mut self, all_zones: &MutableSlice<'_>, filter_langs: &[String]);
+    fn compute_labels(
+        &mut self,
+        all_zones: &MutableSlice<'_>,
+        filter_langs: &[String],
+        label_format: &LabelFormat,
+    );
 
     /// compute the names of a zone
-    fn compute_names(&mut self);
+    ///
+    /// If `inherit_center_wikidata` is true, a city without wikidata id takes
+    /// the one of its center node (if any)
+    fn compute_names(&mut self, inherit_center_wikidata: bool);
 
     /// a zone can be a child of another zone z if:
     /// z is an admin (we don't want to have non administrative zones as parent)
-    /// z's type is larger (so a State cannot have a City as parent)
+    /// z's type has a higher rank (so a State cannot have a City as parent)
+    /// z is not disputed, unless the zone is also disputed
+    /// the zone is not maritime (the maritime zones are not in the land hierarchy)
     fn can_be_child_of(&self, z: &Zone) -> bool;
+
+    /// check if the zone is a disputed boundary
+    fn is_disputed(&self) -> bool;
+}
+
+/// a boundary is disputed if it's tagged `disputed=yes` or `boundary=disputed`
+pub fn has_disputed_tags(tags: &Tags) -> bool {
+    tags.get("disputed").is_some_and(|v| v == "yes")
+        || tags.get("boundary").is_some_and(|v| v == "disputed")
+}
+
+/// a maritime boundary (like an exclusive economic zone) is tagged `boundary=maritime`
+pub fn has_maritime_tags(tags: &Tags) -> bool {
+    tags.get("boundary").is_some_and(|v| v == "maritime")
 }
 
 impl ZoneExt for Zone {
@@ -76,6 +109,8 @@ impl ZoneExt for Zone {
             .sorted()
             .collect();
         let wikidata = tags.get("wikidata").map(|s| s.to_string());
+        let wikipedia = tags.get("wikipedia").map(|s| s.to_string());
+        let website = tags.get("website").map(|s| s.to_string());
         let loc_name = tags
             .get("loc_name")
             .map(|s| s.to_string())
@@ -94,7 +129,10 @@ impl ZoneExt for Zone {
         Some(Self {
             id: index,
             osm_id: osm_id_str,
+            osm_version: None,
+            osm_timestamp: None,
             admin_level: level,
+            admin_level_source: None,
             zone_type,
             name: name.to_string(),
             loc_name,
@@ -102,9 +140,15 @@ impl ZoneExt for Zone {
             boundary: None,
             bbox: None,
             parent: None,
+            depth: 0,
             tags: tags.clone(),
             center_tags: Tags::new(),
             wikidata,
+            wikipedia,
+            website,
+            perimeter: None,
+            area: None,
+            num_holes: 0,
             center: None,
             international_labels: BTreeMap::default(),
             international_names,
@@ -112,6 +156,8 @@ impl ZoneExt for Zone {
             zip_codes,
             is_generated: true,
             country_code: None,
+            candidate_parents: vec![],
+            voronoi_debug: None,
         })
     }
 
@@ -119,9 +165,8 @@ impl ZoneExt for Zone {
         relation: &Relation,
         objects: &BTreeMap<OsmId, OsmObj>,
         index: ZoneIndex,
+        names_policy: NamesMergePolicy,
     ) -> Option<Self> {
-        use geo::centroid::Centroid;
-
         // Skip administrative region without name
         let name = match relation.tags.get("name") {
             Some(val) => val,
@@ -133,11 +178,6 @@ impl ZoneExt for Zone {
                 return None;
             }
         };
-        let level = relation
-            .tags
-            .get("admin_level")
-            .and_then(|s| s.parse().ok());
-
         let zip_code = relation
             .tags
             .get("addr:postcode")
@@ -150,6 +190,8 @@ impl ZoneExt for Zone {
             .sorted()
             .collect();
         let wikidata = relation.tags.get("wikidata").map(|s| s.to_string());
+        let wikipedia = relation.tags.get("wikipedia").map(|s| s.to_string());
+        let website = relation.tags.get("website").map(|s| s.to_string());
         let loc_name = relation
             .tags
             .get("loc_name")
@@ -170,18 +212,30 @@ impl ZoneExt for Zone {
             .and_then(|r| objects.get(&r.member))
             .and_then(|o| o.node());
 
+        let level = parse_admin_level(&relation.tags);
+        let level_source = level.map(|_| AdminLevelSource::RelationTag);
+
         let mut tags = relation.tags.clone();
         if let Some(node) = label_node {
             node.tags
                 .iter()
                 .filter(|(k, _)| k.starts_with("name:") || *k == "population")
                 .for_each(|(k, v)| {
-                    tags.entry(k.clone()).or_insert(v.clone());
+                    if names_policy == NamesMergePolicy::LabelWins && k.starts_with("name:") {
+                        tags.insert(k.clone(), v.clone());
+                    } else {
+                        tags.entry(k.clone()).or_insert(v.clone());
+                    }
                 })
         }
 
-        let boundary = build_boundary(relation, objects);
+        let boundary = build_zone_boundary(relation, objects);
         let bbox = boundary.as_ref().and_then(|b| b.bounding_rect());
+        let perimeter = boundary.as_ref().map(boundary_perimeter);
+        let num_holes = boundary.as_ref().map_or(0, count_holes);
+        if boundary.as_ref().and_then(antimeridian_bboxes).is_some() {
+            info!("{} crosses the antimeridian", osm_id);
+        }
 
         let refs = &relation.refs;
         let osm_center = refs
@@ -193,26 +247,19 @@ impl ZoneExt for Zone {
         let center_tags = osm_center.map_or(Tags::new(), |n| n.tags.clone());
 
         let center = osm_center.map_or(
-            boundary.as_ref().and_then(|b| {
-                b.centroid().filter(|p| {
-                    /*
-                        On a broken polygon Geo may return Some(NaN,NaN) centroid.
-                        It should NOT be serialized as [null,null] in the JSON output.
-                    */
-                    if p.x().is_nan() || p.y().is_nan() {
-                        warn!("NaN in centroid {:?} for {}", p, osm_id);
-                        return false;
-                    }
-                    true
-                })
-            }),
+            boundary
+                .as_ref()
+                .and_then(|b| boundary_centroid(b, &osm_id)),
             |node| Some(Coord::new(node.lon(), node.lat())),
         );
 
         Some(Zone {
             id: index,
             osm_id,
+            osm_version: None,
+            osm_timestamp: None,
             admin_level: level,
+            admin_level_source: level_source,
             zone_type: None,
             name: name.to_string(),
             loc_name,
@@ -225,15 +272,30 @@ impl ZoneExt for Zone {
             boundary,
             bbox,
             parent: None,
+            depth: 0,
             tags,
             center_tags,
             wikidata,
+            wikipedia,
+            website,
+            perimeter,
+            area: None,
+            num_holes,
             is_generated: false,
             country_code: None,
+            candidate_parents: vec![],
+            voronoi_debug: None,
         })
     }
 
     fn contains(&self, other: &Zone) -> bool {
+        // fast path: no need to convert the geometries to GEOS
+        // if the bounding boxes make the containment impossible
+        if let (Some(bbox1), Some(bbox2)) = (&self.bbox, &other.bbox) {
+            if !bbox_contains(bbox1, bbox2) {
+                return false;
+            }
+        }
         match (&self.boundary, &other.boundary) {
             (Some(mpoly1), Some(mpoly2)) => {
                 let m_self: Result<Geometry, _> = mpoly1.try_into();
@@ -306,8 +368,13 @@ impl ZoneExt for Zone {
     /// We compute a default label, and a label per language
     /// Note: for the moment we use the same format for every language,
     /// but in the future we might use opencage's configuration for this
-    fn compute_labels(&mut self, all_zones: &MutableSlice<'_>, filter_langs: &[String]) {
-        let label = create_lbl(self, all_zones, |z: &Zone| z.name.clone());
+    fn compute_labels(
+        &mut self,
+        all_zones: &MutableSlice<'_>,
+        filter_langs: &[String],
+        label_format: &LabelFormat,
+    ) {
+        let label = create_lbl(self, all_zones, label_format, |z: &Zone| z.name.clone());
 
         // we compute a label per language
         let it = self
@@ -323,7 +390,7 @@ impl ZoneExt for Zone {
         let international_labels = all_lang
             .iter()
             .map(|lang| {
-                let lbl = create_lbl(self, all_zones, |z: &Zone| {
+                let lbl = create_lbl(self, all_zones, label_format, |z: &Zone| {
                     z.international_names.get(lang).unwrap_or(&z.name).clone()
                 });
                 (lang.to_string(), lbl)
@@ -334,7 +401,7 @@ impl ZoneExt for Zone {
         self.label = label;
     }
 
-    fn compute_names(&mut self) {
+    fn compute_names(&mut self, inherit_center_wikidata: bool) {
         let center_wikidata = self.center_tags.get("wikidata").map(|s| s.to_string());
 
         // Names from the center node can be used as additional tags, with some precautions:
@@ -352,46 +419,534 @@ impl ZoneExt for Zone {
                 })
         }
         self.international_names = get_international_names(&self.tags, &self.name);
+
+        if inherit_center_wikidata
+            && self.wikidata.is_none()
+            && self.zone_type == Some(ZoneType::City)
+        {
+            self.wikidata = center_wikidata;
+        }
     }
 
     /// a zone can be a child of another zone z if:
     /// z is an admin (we don't want to have non administrative zones as parent)
-    /// z's type is larger (so a State cannot have a City as parent)
+    /// z's type has a higher rank (so a State cannot have a City as parent)
     fn can_be_child_of(&self, z: &Zone) -> bool {
-        z.is_admin() && (!self.is_admin() || self.zone_type < z.zone_type)
+        !self.is_maritime()
+            && z.is_admin()
+            && (!self.is_admin()
+                || self.zone_type.map(|t| t.rank()) < z.zone_type.map(|t| t.rank()))
+            && (!z.is_disputed() || self.is_disputed())
+    }
+
+    fn is_disputed(&self) -> bool {
+        has_disputed_tags(&self.tags)
+    }
+}
+
+/// geodesic length (in meters) of the outer rings of a boundary
+///
+/// the holes are not counted in the perimeter
+pub fn boundary_perimeter(boundary: &MultiPolygon<f64>) -> f64 {
+    boundary
+        .iter()
+        .map(|poly| poly.exterior().geodesic_length())
+        .sum()
+}
+
+/// How the area of the zones is computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AreaModel {
+    /// on a sphere: fast, but less accurate
+    Spherical,
+    /// on the WGS84 ellipsoid (Karney's algorithm): slower, but accurate
+    ///
+    /// The relative difference with the spherical area is well under 1% for most zones
+    /// (it grows with the zone's size and is at most about 0.7%, depending on the latitude).
+    #[default]
+    Ellipsoidal,
+}
+
+impl FromStr for AreaModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spherical" => Ok(AreaModel::Spherical),
+            "ellipsoidal" => Ok(AreaModel::Ellipsoidal),
+            _ => Err(anyhow::anyhow!(
+                "unknown area model '{}', the accepted values are 'spherical' and 'ellipsoidal'",
+                s
+            )),
+        }
     }
 }
 
-fn create_lbl<'a, F>(zone: &'a Zone, all_zones: &'a MutableSlice<'_>, f: F) -> String
+/// area (in square meters) of a boundary, without its holes
+pub fn boundary_area(boundary: &MultiPolygon<f64>, model: AreaModel) -> f64 {
+    match model {
+        AreaModel::Spherical => boundary.chamberlain_duquette_unsigned_area(),
+        // the geodesic area of a clockwise ring is the area of the rest of the earth,
+        // and the osm boundaries can have any orientation
+        AreaModel::Ellipsoidal => boundary.orient(Direction::Default).geodesic_area_unsigned(),
+    }
+}
+
+/// The centroid of a boundary, used as the center of the zones without a center node
+pub fn boundary_centroid(boundary: &MultiPolygon<f64>, osm_id: &str) -> Option<Coord> {
+    use geo::centroid::Centroid;
+
+    boundary.centroid().filter(|p| {
+        /*
+            On a broken polygon Geo may return Some(NaN,NaN) centroid.
+            It should NOT be serialized as [null,null] in the JSON output.
+        */
+        if p.x().is_nan() || p.y().is_nan() {
+            warn!("NaN in centroid {:?} for {}", p, osm_id);
+            return false;
+        }
+        true
+    })
+}
+
+fn parse_admin_level(tags: &Tags) -> Option<u32> {
+    tags.get("admin_level").and_then(|s| s.parse().ok())
+}
+
+/// The `admin_level` of the label node of a relation
+///
+/// It can be used when the relation has no valid `admin_level`
+/// (see the `--admin-level-from-label-node` option)
+pub fn label_node_admin_level(
+    relation: &Relation,
+    objects: &BTreeMap<OsmId, OsmObj>,
+) -> Option<u32> {
+    relation
+        .refs
+        .iter()
+        .find(|r| &r.role == "label")
+        .and_then(|r| objects.get(&r.member))
+        .and_then(|o| o.node())
+        .and_then(|n| parse_admin_level(&n.tags))
+}
+
+/// The `admin_centre` node of a relation tagged as a capital
+///
+/// The node must be tagged `capital=yes` or `capital=<admin_level>`. If there are
+/// several of them, the most important capital is taken (`capital=yes` being like `capital=2`).
+pub fn capital_centre<'a>(
+    relation: &Relation,
+    objects: &'a BTreeMap<OsmId, OsmObj>,
+) -> Option<&'a Node> {
+    let capital_level = |node: &Node| -> Option<u32> {
+        match node.tags.get("capital")?.as_str() {
+            "yes" => Some(2),
+            level => level.parse().ok(),
+        }
+    };
+    relation
+        .refs
+        .iter()
+        .filter(|r| &r.role == "admin_centre")
+        .filter_map(|r| objects.get(&r.member).and_then(|o| o.node()))
+        .filter_map(|node| capital_level(node).map(|level| (level, node)))
+        .min_by_key(|(level, _)| *level)
+        .map(|(_, node)| node)
+}
+
+// margin (in degrees) to consider that a boundary reaches the antimeridian
+const ANTIMERIDIAN_EPSILON: f64 = 1e-4;
+
+/// Whether a bbox spans all the longitudes, as the bbox of a boundary crossing the antimeridian
+pub fn reaches_antimeridian(bbox: &Rect<f64>) -> bool {
+    bbox.min().x <= -180. + ANTIMERIDIAN_EPSILON && bbox.max().x >= 180. - ANTIMERIDIAN_EPSILON
+}
+
+/// The bboxes of the parts of a boundary on each side of the antimeridian, if it crosses it
+///
+/// In osm, the boundaries crossing the antimeridian are split at 180°, so their bbox
+/// spans all the longitudes. This returns the east and the west bboxes instead,
+/// the polygons being assigned to a side by the center of their bbox.
+pub fn antimeridian_bboxes(boundary: &MultiPolygon<f64>) -> Option<(Rect<f64>, Rect<f64>)> {
+    if !reaches_antimeridian(&boundary.bounding_rect()?) {
+        return None;
+    }
+    let bboxes: Vec<Rect<f64>> = boundary.iter().filter_map(|p| p.bounding_rect()).collect();
+    // a polygon spanning more than half of the longitudes is not split at the antimeridian
+    if bboxes.iter().any(|b| b.width() > 180.) {
+        return None;
+    }
+    let union = |a: Rect<f64>, b: Rect<f64>| {
+        Rect::new(
+            geo::Coord {
+                x: a.min().x.min(b.min().x),
+                y: a.min().y.min(b.min().y),
+            },
+            geo::Coord {
+                x: a.max().x.max(b.max().x),
+                y: a.max().y.max(b.max().y),
+            },
+        )
+    };
+    let (east, west): (Vec<_>, Vec<_>) = bboxes.into_iter().partition(|b| b.center().x > 0.);
+    Some((
+        east.into_iter().reduce(union)?,
+        west.into_iter().reduce(union)?,
+    ))
+}
+
+/// number of holes (interior rings) of a boundary
+pub fn count_holes(boundary: &MultiPolygon<f64>) -> usize {
+    boundary.iter().map(|poly| poly.interiors().len()).sum()
+}
+
+/// Maximum distance (in meters) between two ends of ways to join them,
+/// when the boundary of a relation cannot be built as is
+pub const RING_CLOSURE_TOLERANCE: f64 = 50.;
+
+/// Build the boundary of a relation
+///
+/// If the boundary cannot be built as is, a second try joins the ends of the ways
+/// that are less than `RING_CLOSURE_TOLERANCE` apart, to close the nearly closed rings.
+/// The reason of the failure is logged (at the debug level) if both tries fail.
+pub fn build_zone_boundary(
+    relation: &Relation,
+    objects: &BTreeMap<OsmId, OsmObj>,
+) -> Option<MultiPolygon<f64>> {
+    if let Some(boundary) = build_boundary(relation, objects) {
+        return Some(boundary);
+    }
+    match build_boundary_closing_rings(relation, objects) {
+        Ok(boundary) => {
+            info!(
+                "relation/{}: boundary built by closing its nearly closed rings",
+                relation.id.0
+            );
+            Some(boundary)
+        }
+        Err(reason) => {
+            debug!(
+                "relation/{}: the boundary cannot be built: {}",
+                relation.id.0, reason
+            );
+            None
+        }
+    }
+}
+
+// build the boundary of a relation from its outer and inner ways,
+// joining the ends of the ways that are nearly joined
+fn build_boundary_closing_rings(
+    relation: &Relation,
+    objects: &BTreeMap<OsmId, OsmObj>,
+) -> Result<MultiPolygon<f64>, String> {
+    let mut outer_ways = vec![];
+    let mut inner_ways = vec![];
+    for r in relation.refs.iter().filter(|r| r.member.is_way()) {
+        let way = objects
+            .get(&r.member)
+            .and_then(|o| o.way())
+            .ok_or_else(|| format!("missing way {}", r.member.inner_id()))?;
+        let coords = way
+            .nodes
+            .iter()
+            .map(|n| {
+                objects
+                    .get(&OsmId::Node(*n))
+                    .and_then(|o| o.node())
+                    .map(|n| geo::Coord {
+                        x: n.lon(),
+                        y: n.lat(),
+                    })
+                    .ok_or_else(|| format!("missing node {} of way {}", n.0, way.id.0))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match r.role.as_str() {
+            "outer" | "" => outer_ways.push(coords),
+            "inner" => inner_ways.push(coords),
+            _ => {}
+        }
+    }
+    if outer_ways.is_empty() {
+        return Err("no outer way".into());
+    }
+    let mut polygons: Vec<Polygon<f64>> = close_rings(outer_ways)?
+        .into_iter()
+        .map(|ring| Polygon::new(ring, vec![]))
+        .collect();
+    for ring in close_rings(inner_ways)? {
+        let first = Point::from(ring[0]);
+        match polygons.iter_mut().find(|p| p.contains(&first)) {
+            Some(polygon) => polygon.interiors_push(ring),
+            None => return Err("inner ring outside of the outer rings".into()),
+        }
+    }
+    Ok(MultiPolygon(polygons))
+}
+
+// chain ways (as lists of coordinates) into closed rings
+//
+// a way is joined to the end of a ring if they share a node,
+// or else if the gap between them is less than `RING_CLOSURE_TOLERANCE`
+fn close_rings(ways: Vec<Vec<geo::Coord<f64>>>) -> Result<Vec<LineString<f64>>, String> {
+    let distance =
+        |a: geo::Coord<f64>, b: geo::Coord<f64>| Point::from(a).haversine_distance(&Point::from(b));
+    let mut ways: Vec<_> = ways.into_iter().filter(|w| !w.is_empty()).collect();
+    let mut rings = vec![];
+    while let Some(mut ring) = ways.pop() {
+        loop {
+            let (first, last) = (ring[0], ring[ring.len() - 1]);
+            if first == last && ring.len() > 1 {
+                break;
+            }
+            // the way continuing the ring, with the gap to it
+            let next = ways
+                .iter()
+                .enumerate()
+                .flat_map(|(pos, w)| {
+                    [
+                        (pos, false, distance(last, w[0])),
+                        (pos, true, distance(last, w[w.len() - 1])),
+                    ]
+                })
+                .min_by(|a, b| a.2.total_cmp(&b.2));
+            let gap_to_first = distance(last, first);
+            match next {
+                Some((pos, reversed, gap)) if gap == 0. || gap < gap_to_first => {
+                    if gap > RING_CLOSURE_TOLERANCE {
+                        return Err(format!(
+                            "unclosed ring, {:.0}m gap near ({}, {})",
+                            gap, last.x, last.y
+                        ));
+                    }
+                    let mut way = ways.swap_remove(pos);
+                    if reversed {
+                        way.reverse();
+                    }
+                    let skip = usize::from(way[0] == last);
+                    ring.extend(way.into_iter().skip(skip));
+                }
+                _ => {
+                    if gap_to_first > RING_CLOSURE_TOLERANCE {
+                        return Err(format!(
+                            "unclosed ring, {:.0}m gap near ({}, {})",
+                            gap_to_first, last.x, last.y
+                        ));
+                    }
+                    ring.push(first);
+                }
+            }
+        }
+        if ring.len() < 4 {
+            return Err("ring with less than 3 points".into());
+        }
+        rings.push(LineString::from(ring));
+    }
+    Ok(rings)
+}
+
+/// Infer the admin_level of an administrative boundary without `admin_level` tag
+///
+/// The level is inferred from the `border_type` tag, then from the `place` tag,
+/// then from some words of the name (like "county").
+/// Returns None if nothing gives a level.
+pub fn infer_admin_level(tags: &Tags) -> Option<u32> {
+    let level_of_kind = |kind: &str| match kind {
+        "nation" | "country" => Some(2),
+        "state" | "province" | "region" => Some(4),
+        "county" | "district" | "department" => Some(6),
+        "municipality" | "city" | "town" | "village" | "commune" => Some(8),
+        "borough" | "suburb" => Some(9),
+        "quarter" | "neighbourhood" => Some(10),
+        _ => None,
+    };
+    tags.get("border_type")
+        .and_then(|v| level_of_kind(v))
+        .or_else(|| tags.get("place").and_then(|v| level_of_kind(v)))
+        .or_else(|| {
+            let name = tags.get("name")?.to_lowercase();
+            name.split(|c: char| !c.is_alphanumeric())
+                .find_map(level_of_kind)
+        })
+}
+
+// tolerance used to not wrongly reject zones touching the boundary
+// because of floating point approximations
+const BBOX_EPSILON: f64 = 1e-7;
+
+/// check if a bbox contains another one
+fn bbox_contains(outer: &Rect<f64>, inner: &Rect<f64>) -> bool {
+    outer.min().x - BBOX_EPSILON <= inner.min().x
+        && outer.min().y - BBOX_EPSILON <= inner.min().y
+        && outer.max().x + BBOX_EPSILON >= inner.max().x
+        && outer.max().y + BBOX_EPSILON >= inner.max().y
+}
+
+/// Which `name:*` tag is kept when a relation and its label node have the same one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamesMergePolicy {
+    /// the relation's tag is kept
+    #[default]
+    RelationWins,
+    /// the label node's tag is kept (for the languages where the label node is authoritative)
+    LabelWins,
+}
+
+impl FromStr for NamesMergePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relation-wins" => Ok(NamesMergePolicy::RelationWins),
+            "label-wins" => Ok(NamesMergePolicy::LabelWins),
+            _ => Err(anyhow::anyhow!(
+                "unknown names merge policy '{}', the accepted values are 'relation-wins' and 'label-wins'",
+                s
+            )),
+        }
+    }
+}
+
+/// How the zip codes of a zone are displayed in its label
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ZipFormat {
+    /// the range of the zip codes, eg: "Paris (75000-75116)"
+    #[default]
+    Range,
+    /// all the zip codes, eg: "Paris (75000, 75001, 75002)"
+    List,
+}
+
+/// Format of the zones' labels
+///
+/// The default format is "Paris (75000-75116), Île-de-France, France"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelFormat {
+    /// separator between the zones of the hierarchy
+    pub separator: String,
+    /// display the zip codes of the zone after its name
+    pub include_zip: bool,
+    pub zip_format: ZipFormat,
+    /// number of ancestors displayed after the zone's name (all of them if None),
+    /// eg: "Paris (75000-75116), Île-de-France" with a depth of 1
+    pub depth: Option<usize>,
+}
+
+impl Default for LabelFormat {
+    fn default() -> Self {
+        LabelFormat {
+            separator: ", ".into(),
+            include_zip: true,
+            zip_format: ZipFormat::Range,
+            depth: None,
+        }
+    }
+}
+
+fn create_lbl<'a, F>(
+    zone: &'a Zone,
+    all_zones: &'a MutableSlice<'_>,
+    label_format: &LabelFormat,
+    f: F,
+) -> String
 where
     F: Fn(&Zone) -> String,
 {
-    let mut hierarchy: Vec<String> = zone.iter_hierarchy(all_zones).map(f).dedup().collect();
+    let mut hierarchy: Vec<String> = zone
+        .iter_hierarchy(all_zones)
+        .map(f)
+        .dedup()
+        .take(label_format.depth.map_or(usize::MAX, |depth| depth + 1))
+        .collect();
 
-    if let Some(ref mut zone_name) = hierarchy.first_mut() {
-        zone_name.push_str(&format_zip_code(&zone.zip_codes));
+    if label_format.include_zip {
+        if let Some(ref mut zone_name) = hierarchy.first_mut() {
+            zone_name.push_str(&format_zip_code(&zone.zip_codes, label_format.zip_format));
+        }
     }
-    hierarchy.join(", ")
+    hierarchy.join(&label_format.separator)
 }
 
 /// format the zone's zip code
 /// if no zipcode, we return an empty string
 /// if only one zipcode, we return it between ()
-/// if more than one we display the range of zips code
+/// if more than one we display the range of zips code (or all of them with `ZipFormat::List`)
 ///
 /// This way for example Paris will get " (75000-75116)"
 ///
 /// ruthlessly taken from mimir
-fn format_zip_code(zip_codes: &[String]) -> String {
-    match zip_codes.len() {
-        0 => "".to_string(),
-        1 => format!(" ({})", zip_codes.first().unwrap()),
-        _ => format!(
+fn format_zip_code(zip_codes: &[String], zip_format: ZipFormat) -> String {
+    match (zip_codes.len(), zip_format) {
+        (0, _) => "".to_string(),
+        (1, _) => format!(" ({})", zip_codes.first().unwrap()),
+        (_, ZipFormat::Range) => format!(
             " ({}-{})",
             zip_codes.first().unwrap_or(&"".to_string()),
             zip_codes.last().unwrap_or(&"".to_string())
         ),
+        (_, ZipFormat::List) => format!(" ({})", zip_codes.join(", ")),
+    }
+}
+
+/// suffixes of the `name:*` tags that are not languages
+/// (eg: `name:left`, `name:etymology`, ...)
+const NON_LANGUAGE_NAME_SUFFIXES: &[&str] = &[
+    "left",
+    "right",
+    "source",
+    "etymology",
+    "prefix",
+    "suffix",
+    "pronunciation",
+    "old",
+    "short",
+    "official",
+    "signed",
+    "genitive",
+    "ref",
+    "note",
+    "wikipedia",
+    "wikidata",
+];
+
+/// normalize the language part of a `name:<lang>` tag
+///
+/// Only the ISO 639 / BCP-47 like language tags are kept (eg: `fr`, `zh-Hant`, `pt-BR`),
+/// with their case normalized (language in lowercase, script in titlecase, region in uppercase).
+/// None is returned if the suffix is not a language.
+fn normalize_lang(lang: &str) -> Option<String> {
+    let lang = lang.to_lowercase();
+    if NON_LANGUAGE_NAME_SUFFIXES.contains(&lang.as_str()) {
+        return None;
+    }
+    let mut subtags = lang.split('-');
+    let primary = subtags.next()?;
+    if !(2..=3).contains(&primary.len()) || !primary.chars().all(|c| c.is_ascii_lowercase()) {
+        return None;
+    }
+
+    let mut normalized = primary.to_string();
+    for subtag in subtags {
+        if subtag.is_empty()
+            || subtag.len() > 8
+            || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        normalized.push('-');
+        match subtag.len() {
+            // script subtag, eg: zh-Hant
+            4 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => {
+                let mut chars = subtag.chars();
+                normalized.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+                normalized.push_str(chars.as_str());
+            }
+            // region subtag, eg: pt-BR
+            2 => normalized.push_str(&subtag.to_uppercase()),
+            _ => normalized.push_str(subtag),
+        }
     }
+    Some(normalized)
 }
 
 /// get all the international names from the osm tags
@@ -399,6 +954,9 @@ fn format_zip_code(zip_codes: &[String]) -> String {
 /// the names in osm are in a tag names `name:<lang>`,
 /// eg `name:fr`, `name:de`, ...
 ///
+/// the tags that are not about a language (eg `name:etymology`) are skipped,
+/// and the language is normalized (see `normalize_lang`)
+///
 /// we don't add the international names that are equivalent to the default name
 /// to reduce the size of the map
 fn get_international_names(tags: &Tags, default_name: &str) -> BTreeMap<String, String> {
@@ -410,8 +968,9 @@ fn get_international_names(tags: &Tags, default_name: &str) -> BTreeMap<String,
         .filter(|&(_, v)| v != default_name)
         .filter_map(|(k, v)| {
             let lang = LANG_NAME_REG.captures(k)?.get(1)?;
+            let lang = normalize_lang(lang.as_str())?;
 
-            Some((lang.as_str().into(), v.clone().into()))
+            Some((lang, v.clone().into()))
         })
         .collect()
 }
@@ -428,7 +987,10 @@ mod test {
         Zone {
             id: ZoneIndex { index: id },
             osm_id: "".into(),
+            osm_version: None,
+            osm_timestamp: None,
             admin_level: None,
+            admin_level_source: None,
             zone_type: Some(ZoneType::City),
             name: name.into(),
             loc_name: "".into(),
@@ -440,21 +1002,131 @@ mod test {
             boundary: None,
             bbox: None,
             parent: parent.map(|p| ZoneIndex { index: p }),
+            depth: 0,
             tags: Tags::new(),
             center_tags: Tags::new(),
             wikidata: None,
+            wikipedia: None,
+            website: None,
+            perimeter: None,
+            area: None,
+            num_holes: 0,
             zip_codes: zips.iter().map(|s| s.to_string()).collect(),
             is_generated: false,
             country_code: None,
+            candidate_parents: vec![],
+            voronoi_debug: None,
         }
     }
 
+    fn make_square_zone(min: (f64, f64), max: (f64, f64)) -> Zone {
+        use geo_types::{LineString, MultiPolygon, Polygon};
+
+        let ls = LineString::from(vec![
+            (min.0, min.1),
+            (min.0, max.1),
+            (max.0, max.1),
+            (max.0, min.1),
+            (min.0, min.1),
+        ]);
+        let boundary = MultiPolygon(vec![Polygon::new(ls, vec![])]);
+        Zone {
+            bbox: boundary.bounding_rect(),
+            boundary: Some(boundary),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn area_models_test() {
+        // a large lat/lon rectangle in the north, like Canada, with a vertex every degree
+        // for its edges to follow the parallels
+        let mut ring: Vec<(f64, f64)> = (-140..=-60).map(|lon| (lon as f64, 45.)).collect();
+        ring.extend((-140..=-60).rev().map(|lon| (lon as f64, 70.)));
+        ring.push((-140., 45.));
+        let boundary = MultiPolygon(vec![Polygon::new(LineString::from(ring), vec![])]);
+
+        let spherical = boundary_area(&boundary, AreaModel::Spherical);
+        let ellipsoidal = boundary_area(&boundary, AreaModel::Ellipsoidal);
+        // R² * Δλ * (sin(70°) - sin(45°)), about 13 millions km²
+        let expected = 6_371_000f64.powi(2)
+            * 80f64.to_radians()
+            * (70f64.to_radians().sin() - 45f64.to_radians().sin());
+        assert!((spherical - expected).abs() / expected < 0.01);
+
+        // at these latitudes the ellipsoid is flatter than the sphere, the difference is measurable
+        let relative_diff = (ellipsoidal - spherical).abs() / ellipsoidal;
+        assert!(relative_diff > 0.001, "{}", relative_diff);
+        assert!(relative_diff < 0.01, "{}", relative_diff);
+
+        // the orientation of the rings does not matter
+        let mut clockwise = boundary.clone();
+        clockwise.0[0].exterior_mut(|ring| ring.0.reverse());
+        let clockwise_ellipsoidal = boundary_area(&clockwise, AreaModel::Ellipsoidal);
+        assert!((clockwise_ellipsoidal - ellipsoidal).abs() / ellipsoidal < 1e-9);
+        let clockwise_spherical = boundary_area(&clockwise, AreaModel::Spherical);
+        assert!((clockwise_spherical - spherical).abs() / spherical < 1e-9);
+
+        assert_eq!(
+            AreaModel::from_str("spherical").unwrap(),
+            AreaModel::Spherical
+        );
+        assert_eq!(AreaModel::default(), AreaModel::Ellipsoidal);
+        assert!(AreaModel::from_str("flat").is_err());
+    }
+
+    #[test]
+    fn perimeter_test() {
+        // a 1°x1° square on the equator, its perimeter is about 4 * 111km
+        let zone = make_square_zone((0., 0.), (1., 1.));
+        let perimeter = boundary_perimeter(zone.boundary.as_ref().unwrap());
+        assert!((perimeter - 4. * 111_000.).abs() < 4. * 1_000.);
+
+        // the holes are not counted
+        let mut holed = zone.boundary.clone().unwrap();
+        holed.0[0].interiors_push(vec![
+            (0.2, 0.2),
+            (0.2, 0.8),
+            (0.8, 0.8),
+            (0.8, 0.2),
+            (0.2, 0.2),
+        ]);
+        assert_eq!(boundary_perimeter(&holed), perimeter);
+        assert_eq!(count_holes(zone.boundary.as_ref().unwrap()), 0);
+        assert_eq!(count_holes(&holed), 1);
+    }
+
+    #[test]
+    fn contains_test() {
+        let big = make_square_zone((0., 0.), (10., 10.));
+        let small = make_square_zone((2., 2.), (8., 8.));
+        let touching = make_square_zone((0., 0.), (10., 5.));
+        let outside = make_square_zone((8., 8.), (12., 12.));
+
+        assert!(big.contains(&small));
+        assert!(big.contains(&touching));
+        assert!(!small.contains(&big));
+        assert!(!big.contains(&outside));
+    }
+
+    #[test]
+    fn contains_rejected_by_bbox() {
+        let big = make_square_zone((0., 0.), (10., 10.));
+        let mut small = make_square_zone((2., 2.), (8., 8.));
+
+        // we give a wrong bbox to the small zone, if GEOS was called
+        // the containment would be true, so this ensures the bbox check
+        // is done before
+        small.bbox = make_square_zone((20., 20.), (30., 30.)).bbox;
+        assert!(!big.contains(&small));
+    }
+
     #[test]
     fn simple_label_test() {
         let mut zones = vec![make_zone("toto", 0)];
 
         let (mslice, z) = MutableSlice::init(&mut zones, 0);
-        z.compute_labels(&mslice, &[]);
+        z.compute_labels(&mslice, &[], &LabelFormat::default());
         assert_eq!(z.label, "toto");
     }
 
@@ -467,7 +1139,7 @@ mod test {
         ];
 
         let (mslice, z) = MutableSlice::init(&mut zones, 0);
-        z.compute_labels(&mslice, &[]);
+        z.compute_labels(&mslice, &[], &LabelFormat::default());
         assert_eq!(z.label, "bob (75020-75022), bob sur mer, bobette's land");
     }
 
@@ -484,7 +1156,7 @@ mod test {
         ];
 
         let (mslice, z) = MutableSlice::init(&mut zones, 0);
-        z.compute_labels(&mslice, &[]);
+        z.compute_labels(&mslice, &[], &LabelFormat::default());
         assert_eq!(z.label, "bob (75020), bob sur mer, bobette's land");
     }
 
@@ -499,10 +1171,152 @@ mod test {
         ];
 
         let (mslice, z) = MutableSlice::init(&mut zones, 0);
-        z.compute_labels(&mslice, &[]);
+        z.compute_labels(&mslice, &[], &LabelFormat::default());
         assert_eq!(z.label, "bob (75020), bob sur mer, bob");
     }
 
+    #[test]
+    fn label_with_custom_format() {
+        let make_zones = || {
+            vec![
+                make_zone_and_zip("bob", 0, vec!["75020", "75021", "75022"], Some(1)),
+                make_zone("bobette's land", 1),
+            ]
+        };
+
+        let mut zones = make_zones();
+        let (mslice, z) = MutableSlice::init(&mut zones, 0);
+        let label_format = LabelFormat {
+            separator: " / ".into(),
+            ..Default::default()
+        };
+        z.compute_labels(&mslice, &[], &label_format);
+        assert_eq!(z.label, "bob (75020-75022) / bobette's land");
+
+        let mut zones = make_zones();
+        let (mslice, z) = MutableSlice::init(&mut zones, 0);
+        let label_format = LabelFormat {
+            include_zip: false,
+            ..Default::default()
+        };
+        z.compute_labels(&mslice, &[], &label_format);
+        assert_eq!(z.label, "bob, bobette's land");
+
+        let mut zones = make_zones();
+        let (mslice, z) = MutableSlice::init(&mut zones, 0);
+        let label_format = LabelFormat {
+            zip_format: ZipFormat::List,
+            ..Default::default()
+        };
+        z.compute_labels(&mslice, &[], &label_format);
+        assert_eq!(z.label, "bob (75020, 75021, 75022), bobette's land");
+    }
+
+    #[test]
+    fn label_with_depth() {
+        let make_zones = || {
+            let mut zones = vec![
+                make_zone_and_zip("bob", 0, vec!["75020"], Some(1)),
+                make_zone_and_zip("bobette's land", 1, vec![], Some(2)),
+                make_zone("bob's world", 2),
+            ];
+            zones[0].international_names = vec![("fr".to_string(), "bobby".to_string())]
+                .into_iter()
+                .collect();
+            zones[2].international_names = vec![("fr".to_string(), "monde".to_string())]
+                .into_iter()
+                .collect();
+            zones
+        };
+        let label_with_depth = |depth| {
+            let mut zones = make_zones();
+            let (mslice, z) = MutableSlice::init(&mut zones, 0);
+            let label_format = LabelFormat {
+                depth,
+                ..Default::default()
+            };
+            z.compute_labels(&mslice, &[], &label_format);
+            (z.label.clone(), z.international_labels["fr"].clone())
+        };
+
+        assert_eq!(
+            label_with_depth(Some(0)),
+            ("bob (75020)".into(), "bobby (75020)".into())
+        );
+        assert_eq!(
+            label_with_depth(Some(1)),
+            (
+                "bob (75020), bobette's land".into(),
+                "bobby (75020), bobette's land".into()
+            )
+        );
+        let full = (
+            "bob (75020), bobette's land, bob's world".to_string(),
+            "bobby (75020), bobette's land, monde".to_string(),
+        );
+        assert_eq!(label_with_depth(None), full);
+        assert_eq!(label_with_depth(Some(5)), full);
+    }
+
+    #[test]
+    fn label_with_script_subtags() {
+        let names = |names: &[(&str, &str)]| {
+            names
+                .iter()
+                .map(|(lang, name)| (lang.to_string(), name.to_string()))
+                .collect()
+        };
+        let mut zones = vec![
+            make_zone_and_zip("Taipei", 0, vec![], Some(1)),
+            make_zone("Taiwan", 1),
+        ];
+        // the city has no traditional Han name, its default name is used instead
+        zones[0].international_names = names(&[("zh", "臺北市"), ("zh-Hans", "台北市")]);
+        zones[1].international_names =
+            names(&[("zh", "臺灣"), ("zh-Hans", "台湾"), ("zh-Hant", "臺灣")]);
+
+        let (mslice, z) = MutableSlice::init(&mut zones, 0);
+        z.compute_labels(&mslice, &[], &LabelFormat::default());
+        assert_eq!(z.label, "Taipei, Taiwan");
+        assert_eq!(z.international_labels["zh-Hans"], "台北市, 台湾");
+        assert_eq!(z.international_labels["zh-Hant"], "Taipei, 臺灣");
+        assert_eq!(z.international_labels["zh"], "臺北市, 臺灣");
+    }
+
+    #[test]
+    fn inherit_center_wikidata() {
+        let make_city = || {
+            let mut city = make_zone("Bobville", 0);
+            city.center_tags.insert("wikidata".into(), "Q42".into());
+            city.center_tags
+                .insert("name:fr".into(), "Bobville-sur-Mer".into());
+            city
+        };
+
+        // by default, the wikidata id is not taken from the center node
+        let mut city = make_city();
+        city.compute_names(false);
+        assert_eq!(city.wikidata, None);
+        assert_eq!(city.international_names["fr"], "Bobville-sur-Mer");
+
+        let mut city = make_city();
+        city.compute_names(true);
+        assert_eq!(city.wikidata.as_deref(), Some("Q42"));
+        assert_eq!(city.international_names["fr"], "Bobville-sur-Mer");
+
+        // the relation's wikidata id is kept
+        let mut city = make_city();
+        city.wikidata = Some("Q1".into());
+        city.compute_names(true);
+        assert_eq!(city.wikidata.as_deref(), Some("Q1"));
+
+        // only the cities take the wikidata id of their center
+        let mut state = make_city();
+        state.zone_type = Some(ZoneType::State);
+        state.compute_names(true);
+        assert_eq!(state.wikidata, None);
+    }
+
     #[test]
     fn test_international_names() {
         let tags = vec![
@@ -511,6 +1325,7 @@ mod test {
             ("name:es", "bobito"),
             ("name", "bobito"),
             ("name:a_strange_lang_name", "bibi"),
+            ("name:DE", "bobi"),
         ]
         .into_iter()
         .map(|(k, v)| (k.into(), v.into()))
@@ -520,10 +1335,268 @@ mod test {
 
         assert_eq!(
             names,
-            vec![("es", "bobito"), ("a_strange_lang_name", "bibi")]
+            vec![("es", "bobito"), ("de", "bobi")]
                 .into_iter()
                 .map(|(k, v)| (k.into(), v.into()))
                 .collect()
         );
     }
+
+    #[test]
+    fn test_international_names_non_language() {
+        let tags = vec![
+            ("name", "bob"),
+            ("name:etymology", "a famous bob"),
+            ("name:left", "bob left"),
+            ("name:etymology:wikidata", "Q42"),
+            ("name:zh-Hant", "鮑勃"),
+            ("name:zh-hans", "鲍勃"),
+            ("name:pt-br", "bobão"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.into(), v.into()))
+        .collect();
+
+        let names = get_international_names(&tags, "bob");
+
+        assert_eq!(
+            names,
+            vec![("zh-Hant", "鮑勃"), ("zh-Hans", "鲍勃"), ("pt-BR", "bobão")]
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_international_names_subtags() {
+        let tags = vec![
+            ("name", "Beograd"),
+            ("name:sr", "Београд"),
+            ("name:sr-Latn", "Beograd Latn"),
+            ("name:zh", "贝尔格莱德"),
+            ("name:zh-Hant", "貝爾格萊德"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.into(), v.into()))
+        .collect();
+
+        let names = get_international_names(&tags, "Beograd");
+
+        // the names with a script subtag are kept distinct from the language's name
+        assert_eq!(
+            names,
+            vec![
+                ("sr", "Београд"),
+                ("sr-Latn", "Beograd Latn"),
+                ("zh", "贝尔格莱德"),
+                ("zh-Hant", "貝爾格萊德"),
+            ]
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_infer_admin_level() {
+        let tags = |tags: &[(&str, &str)]| {
+            let mut osm_tags = Tags::new();
+            for (k, v) in tags {
+                osm_tags.insert((*k).into(), (*v).into());
+            }
+            osm_tags
+        };
+        assert_eq!(
+            infer_admin_level(&tags(&[("border_type", "state")])),
+            Some(4)
+        );
+        // the border_type has the priority over the place
+        assert_eq!(
+            infer_admin_level(&tags(&[("border_type", "county"), ("place", "city")])),
+            Some(6)
+        );
+        assert_eq!(infer_admin_level(&tags(&[("place", "town")])), Some(8));
+        assert_eq!(
+            infer_admin_level(&tags(&[("name", "Orange County")])),
+            Some(6)
+        );
+        assert_eq!(infer_admin_level(&tags(&[("name", "Springfield")])), None);
+        assert_eq!(
+            infer_admin_level(&tags(&[("border_type", "unknown")])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_capital_centre() {
+        use osmpbfreader::objects::{NodeId, Ref, RelationId};
+
+        let node = |id: i64, capital: Option<&str>| {
+            let mut tags = Tags::new();
+            if let Some(capital) = capital {
+                tags.insert("capital".into(), capital.into());
+            }
+            OsmObj::Node(Node {
+                id: NodeId(id),
+                tags,
+                decimicro_lat: 0,
+                decimicro_lon: 0,
+            })
+        };
+        let country = |refs: &[(i64, &str)]| Relation {
+            id: RelationId(1),
+            tags: Tags::new(),
+            refs: refs
+                .iter()
+                .map(|(id, role)| Ref {
+                    member: OsmId::Node(NodeId(*id)),
+                    role: (*role).into(),
+                })
+                .collect(),
+        };
+        let objects: BTreeMap<OsmId, OsmObj> = [
+            node(1, None),
+            node(2, Some("4")),
+            node(3, Some("yes")),
+            node(4, Some("yes")),
+        ]
+        .into_iter()
+        .map(|o| (o.id(), o))
+        .collect();
+        let capital_id =
+            |refs: &[(i64, &str)]| capital_centre(&country(refs), &objects).map(|n| n.id.0);
+
+        // the national capital is preferred to a state capital, whatever the refs order
+        assert_eq!(
+            capital_id(&[
+                (1, "admin_centre"),
+                (2, "admin_centre"),
+                (3, "admin_centre")
+            ]),
+            Some(3)
+        );
+        assert_eq!(
+            capital_id(&[(2, "admin_centre"), (1, "admin_centre")]),
+            Some(2)
+        );
+        // only the admin_centre nodes are considered
+        assert_eq!(capital_id(&[(1, "admin_centre"), (4, "label")]), None);
+        assert_eq!(capital_id(&[]), None);
+    }
+
+    #[test]
+    fn test_names_merge_policy() {
+        use osmpbfreader::objects::{NodeId, Ref, RelationId};
+
+        let tags = |tags: &[(&str, &str)]| {
+            let mut osm_tags = Tags::new();
+            for (k, v) in tags {
+                osm_tags.insert((*k).into(), (*v).into());
+            }
+            osm_tags
+        };
+        let label = OsmObj::Node(Node {
+            id: NodeId(1),
+            tags: tags(&[("name:de", "Luxemburg"), ("name:fr", "Luxembourg")]),
+            decimicro_lat: 0,
+            decimicro_lon: 0,
+        });
+        let relation = Relation {
+            id: RelationId(2),
+            tags: tags(&[("name", "Lëtzebuerg"), ("name:de", "Luxemburg (Stadt)")]),
+            refs: vec![Ref {
+                member: label.id(),
+                role: "label".into(),
+            }],
+        };
+        let objects: BTreeMap<OsmId, OsmObj> = [(label.id(), label)].into_iter().collect();
+        let name_tag = |policy, lang: &str| {
+            Zone::from_osm_relation(&relation, &objects, ZoneIndex { index: 0 }, policy)
+                .unwrap()
+                .tags
+                .get(lang)
+                .map(|n| n.to_string())
+        };
+
+        assert_eq!(
+            name_tag(NamesMergePolicy::RelationWins, "name:de").as_deref(),
+            Some("Luxemburg (Stadt)")
+        );
+        assert_eq!(
+            name_tag(NamesMergePolicy::LabelWins, "name:de").as_deref(),
+            Some("Luxemburg")
+        );
+        // the names missing in the relation are always added
+        for policy in [NamesMergePolicy::RelationWins, NamesMergePolicy::LabelWins] {
+            assert_eq!(name_tag(policy, "name:fr").as_deref(), Some("Luxembourg"));
+        }
+        assert_eq!(
+            NamesMergePolicy::from_str("label-wins").unwrap(),
+            NamesMergePolicy::LabelWins
+        );
+        assert!(NamesMergePolicy::from_str("label").is_err());
+    }
+
+    #[test]
+    fn test_antimeridian_bboxes() {
+        let square = |min_x: f64, max_x: f64| {
+            Polygon::new(
+                LineString::from(vec![
+                    (min_x, -18.),
+                    (max_x, -18.),
+                    (max_x, -16.),
+                    (min_x, -16.),
+                    (min_x, -18.),
+                ]),
+                vec![],
+            )
+        };
+        // Fiji like
+        let (east, west) = antimeridian_bboxes(&MultiPolygon(vec![
+            square(177., 180.),
+            square(-180., -179.),
+            square(-179.5, -178.),
+        ]))
+        .unwrap();
+        assert_eq!((east.min().x, east.max().x), (177., 180.));
+        assert_eq!((west.min().x, west.max().x), (-180., -178.));
+
+        // not reaching the antimeridian
+        assert!(antimeridian_bboxes(&MultiPolygon(vec![square(177., 179.)])).is_none());
+        // all the polygons on one side
+        assert!(antimeridian_bboxes(&MultiPolygon(vec![square(-180., 180.)])).is_none());
+    }
+
+    #[test]
+    fn test_close_rings() {
+        let way = |coords: &[(f64, f64)]| -> Vec<geo::Coord<f64>> {
+            coords.iter().map(|&(x, y)| geo::Coord { x, y }).collect()
+        };
+
+        // two ways sharing their ends, one of them to be reversed
+        let rings = close_rings(vec![
+            way(&[(0., 0.), (1., 0.), (1., 1.)]),
+            way(&[(0., 0.), (0., 1.), (1., 1.)]),
+        ])
+        .unwrap();
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].0.len(), 5);
+        assert!(rings[0].is_closed());
+
+        // a gap of about 10m between the ways, and 10m between the ends of the ring
+        let rings = close_rings(vec![
+            way(&[(0., 0.), (0.01, 0.), (0.01, 0.01)]),
+            way(&[(0.0101, 0.0101), (0., 0.01), (0.0001, 0.)]),
+        ])
+        .unwrap();
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].0.len(), 7);
+        assert!(rings[0].is_closed());
+
+        // a gap of about 1km cannot be closed
+        let err =
+            close_rings(vec![way(&[(0., 0.), (0.01, 0.), (0.01, 0.01), (0., 0.01)])]).unwrap_err();
+        assert!(err.contains("unclosed ring"));
+    }
 }
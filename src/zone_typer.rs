@@ -1,9 +1,10 @@
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, Context, Error};
 use cosmogony::{Zone, ZoneIndex, ZoneType};
-use log::warn;
+use log::{info, warn};
 use serde_derive::*;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::path::Path;
 
 use include_dir::{include_dir, Dir};
 
@@ -41,6 +42,8 @@ impl fmt::Display for OsmPrimaryObjects {
 
 #[derive(Default, Debug)]
 struct RulesOverrides {
+    // the rules are keyed by the full osm_id of the object (eg: `relation:42`, `node:42`, `way:42`),
+    // to match the zones' osm_id whatever their osm primitive type
     contained_by: BTreeMap<String, CountryAdminTypeRules>,
     id_rules: BTreeMap<String, Option<ZoneType>>,
 }
@@ -57,6 +60,17 @@ struct CountryAdminTypeRules {
     // we don't implement libpostal's 'use_admin_center' as we don't need it
 }
 
+/// The libpostal rule that gave the type of a zone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeReason {
+    /// the default type of the zone's admin_level in its country
+    AdminLevel,
+    /// a rule for the zone's osm_id
+    IdOverride,
+    /// a rule for the zones contained by another zone (with the osm_id of this zone)
+    ContainedBy(String),
+}
+
 pub enum ZoneTyperError {
     InvalidCountry(String),
     UnkownLevel(Option<u32>, String),
@@ -64,9 +78,34 @@ pub enum ZoneTyperError {
 
 impl ZoneTyper {
     pub fn new() -> Result<ZoneTyper, Error> {
-        let z = ZoneTyper {
-            countries_rules: read_libpostal_yaml_folder()?,
-        };
+        Self::from_rules(read_libpostal_yaml_folder()?)
+    }
+
+    /// Load the libpostal rules from a directory instead of the embedded ones
+    ///
+    /// The directory must contain one `<country_code>.yaml` file per country,
+    /// like libpostal's `resources/boundaries/osm` directory.
+    pub fn from_dir(dir: &Path) -> Result<ZoneTyper, Error> {
+        Self::from_rules(read_libpostal_yaml_dir(dir)?)
+    }
+
+    /// Load the embedded libpostal rules, overridden by the rules of a directory
+    ///
+    /// A `<country_code>.yaml` file of the directory replaces the embedded rules of its
+    /// country, the other countries keep the embedded rules.
+    pub fn with_overrides(dir: &Path) -> Result<ZoneTyper, Error> {
+        let mut rules = read_libpostal_yaml_folder()?;
+        for (country_code, country_rules) in read_libpostal_yaml_dir(dir)? {
+            info!("the rules of {} are overridden", country_code);
+            rules.insert(country_code, country_rules);
+        }
+        Self::from_rules(rules)
+    }
+
+    fn from_rules(
+        countries_rules: BTreeMap<String, CountryAdminTypeRules>,
+    ) -> Result<ZoneTyper, Error> {
+        let z = ZoneTyper { countries_rules };
         if z.countries_rules.is_empty() {
             Err(anyhow!(
                 "no country rules have been loaded, the libpostal directory \
@@ -84,12 +123,24 @@ impl ZoneTyper {
         zone_inclusions: &[ZoneIndex],
         all_zones: &[Zone],
     ) -> Result<ZoneType, ZoneTyperError> {
+        self.get_zone_type_explained(zone, country_code, zone_inclusions, all_zones)
+            .map(|(zone_type, _)| zone_type)
+    }
+
+    /// Same as `get_zone_type`, but also give the rule that gave the type (to debug the typing)
+    pub fn get_zone_type_explained(
+        &self,
+        zone: &Zone,
+        country_code: &str,
+        zone_inclusions: &[ZoneIndex],
+        all_zones: &[Zone],
+    ) -> Result<(ZoneType, TypeReason), ZoneTyperError> {
         let country_rules = self
             .countries_rules
             .get(country_code)
             .ok_or_else(|| ZoneTyperError::InvalidCountry(country_code.to_string()))?;
         country_rules
-            .get_zone_type(zone, zone_inclusions, all_zones)
+            .get_zone_type_explained(zone, zone_inclusions, all_zones)
             .ok_or_else(|| ZoneTyperError::UnkownLevel(zone.admin_level, country_code.to_string()))
     }
 
@@ -103,12 +154,23 @@ impl CountryAdminTypeRules {
     ///
     /// First we look if there is a specific rule for the zone,
     /// else we take the default osm's admin_level rule
+    #[cfg(test)]
     fn get_zone_type(
         &self,
         zone: &Zone,
         zone_inclusions: &[ZoneIndex],
         all_zones: &[Zone],
     ) -> Option<ZoneType> {
+        self.get_zone_type_explained(zone, zone_inclusions, all_zones)
+            .map(|(zone_type, _)| zone_type)
+    }
+
+    fn get_zone_type_explained(
+        &self,
+        zone: &Zone,
+        zone_inclusions: &[ZoneIndex],
+        all_zones: &[Zone],
+    ) -> Option<(ZoneType, TypeReason)> {
         let overrides = self
             .overrides
             .get_overrided_type(zone, zone_inclusions, all_zones);
@@ -117,7 +179,7 @@ impl CountryAdminTypeRules {
             None => self
                 .type_by_level
                 .get(&zone.admin_level.unwrap_or(0).to_string())
-                .cloned(),
+                .map(|zone_type| (*zone_type, TypeReason::AdminLevel)),
         }
     }
 }
@@ -125,7 +187,7 @@ impl CountryAdminTypeRules {
 impl RulesOverrides {
     /// find the overrided type if it exists
     ///
-    /// This returns an Option<Option<ZoneType>>:
+    /// This returns an Option<Option<(ZoneType, TypeReason)>>:
     /// Some(val) => if we have a specific rule for the zone (and val can be None, this is a way for libpostal to explicitly not type some zones)
     /// None => We have no specific rule for the zone
     fn get_overrided_type(
@@ -133,26 +195,32 @@ impl RulesOverrides {
         zone: &Zone,
         zone_inclusions: &[ZoneIndex],
         all_zones: &[Zone],
-    ) -> Option<Option<ZoneType>> {
+    ) -> Option<Option<(ZoneType, TypeReason)>> {
         // check id overrides
         let id_overrides = self.id_rules.get(&zone.osm_id);
         // if there is no override for this specific object, we check the contained_by overrides
         match id_overrides {
-            Some(overrides) => Some(*overrides),
+            Some(overrides) => Some(overrides.map(|t| (t, TypeReason::IdOverride))),
             None => {
                 if self.contained_by.is_empty() {
                     return None;
                 }
+                // the parents can be any osm primitive, their osm_id contains their type
+                // so a `node:42` rule does not match a `relation:42` parent
                 let mut parents_osm_id = zone_inclusions
                     .iter()
                     .map(|idx| &all_zones[idx.index].osm_id);
 
                 parents_osm_id
-                    .find_map(|parent_osm_id| self.contained_by.get(parent_osm_id))
-                    .and_then(|country_rules| {
+                    .find_map(|parent_osm_id| {
+                        self.contained_by
+                            .get(parent_osm_id)
+                            .map(|rules| (parent_osm_id, rules))
+                    })
+                    .and_then(|(parent_osm_id, country_rules)| {
                         country_rules
-                            .get_zone_type(zone, zone_inclusions, all_zones)
-                            .map(Some)
+                            .get_zone_type_explained(zone, zone_inclusions, all_zones)
+                            .map(|(t, _)| Some((t, TypeReason::ContainedBy(parent_osm_id.clone()))))
                     })
             }
         }
@@ -188,6 +256,33 @@ fn read_libpostal_yaml_folder() -> Result<BTreeMap<String, CountryAdminTypeRules
     }).collect())
 }
 
+fn read_libpostal_yaml_dir(dir: &Path) -> Result<BTreeMap<String, CountryAdminTypeRules>, Error> {
+    let mut rules = BTreeMap::new();
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("impossible to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("yaml") {
+            continue;
+        }
+        let country_code = match path.file_stem().and_then(|f| f.to_str()) {
+            Some(c) => c.to_uppercase(),
+            None => continue,
+        };
+        let contents = std::fs::read_to_string(&path)?;
+        match read_libpostal_yaml(&contents) {
+            Ok(levels) => {
+                rules.insert(country_code, levels);
+            }
+            Err(e) => warn!(
+                "Levels corresponding to file: {:?} have been skipped due to {}",
+                path, e
+            ),
+        }
+    }
+    Ok(rules)
+}
+
 fn read_libpostal_yaml(contents: &str) -> Result<CountryAdminTypeRules, Error> {
     Ok(serde_yaml::from_str(contents)?)
 }
@@ -239,7 +334,8 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::CountryAdminTypeRules;
+    use super::{CountryAdminTypeRules, TypeReason, ZoneTyper};
+    use crate::zone_ext::ZoneExt;
     use crate::zone_typer::read_libpostal_yaml;
     use cosmogony::{Zone, ZoneIndex, ZoneType};
 
@@ -498,5 +594,166 @@ mod test {
         // no specific stuff for big_zone and very_big zone
         assert_eq!(get_zone_type("big_zone"), Some(ZoneType::State));
         assert_eq!(get_zone_type("very_big_zone"), Some(ZoneType::Country));
+
+        // the rule that gave the type
+        let get_reason = |osm_id: &str| {
+            let z = zones
+                .iter()
+                .find(|z| z.osm_id == format!("relation:{}", osm_id))
+                .unwrap();
+            rules
+                .get_zone_type_explained(z, &inclusions[z.id.index], &zones)
+                .map(|(_, reason)| reason)
+        };
+        assert_eq!(get_reason("z1"), Some(TypeReason::IdOverride));
+        assert_eq!(get_reason("z2"), Some(TypeReason::AdminLevel));
+        assert_eq!(
+            get_reason("z3"),
+            Some(TypeReason::ContainedBy("relation:big_zone".into()))
+        );
+        assert_eq!(get_reason("z4"), None);
+        assert_eq!(get_reason("big_zone"), Some(TypeReason::AdminLevel));
+    }
+
+    #[test]
+    fn contained_by_node_and_way_test() {
+        let yaml = r#"---
+    admin_level:
+        "8": "city"
+        "9": "suburb"
+
+    overrides:
+        contained_by:
+            node:
+                "42":
+                    admin_level:
+                        "9": "city_district"
+            way:
+                "43":
+                    admin_level:
+                        "9": "state_district"
+                "#;
+        let rules = read_libpostal_yaml(yaml).expect("invalid yaml");
+
+        let make_zone = |idx: usize, osm_id: &str, lvl| Zone {
+            id: ZoneIndex { index: idx },
+            osm_id: osm_id.into(),
+            admin_level: lvl,
+            ..Default::default()
+        };
+        let zones = vec![
+            make_zone(0, "node:42", Some(8)),
+            make_zone(1, "way:43", Some(8)),
+            make_zone(2, "relation:42", Some(8)),
+            make_zone(3, "relation:100", Some(9)),
+            make_zone(4, "relation:101", Some(9)),
+            make_zone(5, "relation:102", Some(9)),
+        ];
+        let get_zone_type = |idx: usize, parents: Vec<usize>| {
+            let inclusions: Vec<_> = parents
+                .into_iter()
+                .map(|index| ZoneIndex { index })
+                .collect();
+            rules.get_zone_type(&zones[idx], &inclusions, &zones)
+        };
+
+        // contained by a node with a specific rule
+        assert_eq!(get_zone_type(3, vec![0]), Some(ZoneType::CityDistrict));
+        // contained by a way with a specific rule
+        assert_eq!(get_zone_type(4, vec![1]), Some(ZoneType::StateDistrict));
+        // contained by a relation with the same id as the node, the node's rule should not apply
+        assert_eq!(get_zone_type(5, vec![2]), Some(ZoneType::Suburb));
+    }
+
+    #[test]
+    fn neighbourhood_level_test() {
+        let yaml = r#"---
+    admin_level:
+        "8": "city"
+        "10": "suburb"
+        "11": "neighbourhood"
+
+    overrides:
+        contained_by:
+            relation:
+                "42":
+                    admin_level:
+                        "10": "neighborhood"
+                "#;
+        let rules = read_libpostal_yaml(yaml).expect("invalid yaml");
+
+        let make_zone = |idx: usize, lvl| Zone {
+            id: ZoneIndex { index: idx },
+            osm_id: format!("relation:{}", 40 + idx),
+            admin_level: Some(lvl),
+            ..Default::default()
+        };
+        let zones = vec![
+            make_zone(0, 8),
+            make_zone(1, 10),
+            make_zone(2, 8),
+            make_zone(3, 11),
+            make_zone(4, 10),
+        ];
+        let get_zone_type = |idx: usize, parents: Vec<usize>| {
+            let inclusions: Vec<_> = parents
+                .into_iter()
+                .map(|index| ZoneIndex { index })
+                .collect();
+            rules.get_zone_type(&zones[idx], &inclusions, &zones)
+        };
+
+        assert_eq!(get_zone_type(1, vec![0]), Some(ZoneType::Suburb));
+        assert_eq!(get_zone_type(3, vec![1, 0]), Some(ZoneType::Neighbourhood));
+        // the american spelling of the rules is also accepted
+        assert_eq!(get_zone_type(4, vec![2]), Some(ZoneType::Neighbourhood));
+
+        // a neighbourhood can be a child of a suburb, not the other way around
+        let mut suburb = zones[1].clone();
+        suburb.zone_type = Some(ZoneType::Suburb);
+        let mut neighbourhood = zones[3].clone();
+        neighbourhood.zone_type = Some(ZoneType::Neighbourhood);
+        assert!(neighbourhood.can_be_child_of(&suburb));
+        assert!(!suburb.can_be_child_of(&neighbourhood));
+    }
+
+    #[test]
+    fn rules_with_overrides() {
+        let dir = std::env::temp_dir().join("cosmogony_libpostal_overrides");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lu.yaml"), "admin_level:\n  \"8\": \"suburb\"\n").unwrap();
+
+        let zone = Zone {
+            admin_level: Some(8),
+            ..Default::default()
+        };
+        let zone_type = |typer: &ZoneTyper, country_code| {
+            typer.get_zone_type(&zone, country_code, &[], &[]).ok()
+        };
+        let embedded = ZoneTyper::new().unwrap();
+        let overridden = ZoneTyper::with_overrides(&dir).unwrap();
+
+        assert_eq!(zone_type(&embedded, "LU"), Some(ZoneType::City));
+        assert_eq!(zone_type(&overridden, "LU"), Some(ZoneType::Suburb));
+        // the other countries keep the embedded rules
+        assert_eq!(zone_type(&overridden, "FR"), zone_type(&embedded, "FR"));
+        assert_eq!(zone_type(&overridden, "FR"), Some(ZoneType::City));
+    }
+
+    #[test]
+    fn rules_from_dir() {
+        let dir = std::env::temp_dir().join("cosmogony_libpostal_rules");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lu.yaml"), "admin_level:\n  \"2\": \"country\"\n").unwrap();
+        std::fs::write(dir.join("README.md"), "not a rule").unwrap();
+
+        let typer = ZoneTyper::from_dir(&dir).unwrap();
+        assert!(typer.contains_rule("LU"));
+        assert!(!typer.contains_rule("FR"));
+
+        // there must be at least one rule
+        let empty_dir = std::env::temp_dir().join("cosmogony_no_libpostal_rules");
+        std::fs::create_dir_all(&empty_dir).unwrap();
+        assert!(ZoneTyper::from_dir(&empty_dir).is_err());
     }
 }
@@ -1,9 +1,14 @@
 #[macro_use]
 extern crate approx;
 
-use cosmogony::{Cosmogony, Zone, ZoneIndex, ZoneType};
-use cosmogony_builder::{create_ontology, get_zones_and_stats, is_admin, is_place};
-use geo_types::Point;
+use cosmogony::{AdminLevelSource, Cosmogony, CosmogonyStats, Zone, ZoneIndex, ZoneType};
+use cosmogony_builder::zone_typer::ZoneTyper;
+use cosmogony_builder::{
+    create_ontology, get_zones_and_stats, is_admin, is_maritime, is_place, is_place_of_types,
+    BuildConfig, CosmogonyExt, CosmogonyIndex, LabelFormat,
+};
+use geo::bounding_rect::BoundingRect;
+use geo_types::{LineString, MultiPolygon, Point, Polygon};
 use osmpbfreader::OsmPbfReader;
 use std::collections::BTreeMap;
 use std::fs::File;
@@ -12,6 +17,22 @@ use std::process::{Command, Output};
 
 type Coord = Point<f64>;
 
+const LUX_PBF: &str = concat!(
+    env!("OUT_DIR"),
+    "/../../../../../tests/data/luxembourg_filtered.osm.pbf"
+);
+const IVORY_COAST_PBF: &str = concat!(
+    env!("OUT_DIR"),
+    "/../../../../../tests/data/ivory-coast.pbf"
+);
+
+// the configuration of most of the Luxembourg tests (the voronoi are slow to build)
+fn lux_config() -> BuildConfig {
+    BuildConfig::new(LUX_PBF)
+        .country_code("lu")
+        .disable_voronoi(true)
+}
+
 fn launch_command_line(args: Vec<&str>) -> Output {
     let cosmogony_bin = concat!(env!("OUT_DIR"), "/../../../cosmogony");
     Command::new(cosmogony_bin)
@@ -42,151 +63,1342 @@ fn test_cmd_with_json_output() {
     ]);
     assert!(output.status.success());
 
-    let cosmo = cosmogony::load_cosmogony_from_file(out_file).unwrap();
-    assert_eq!(cosmo.zones.len(), 208);
+    let cosmo = cosmogony::load_cosmogony_from_file(out_file).unwrap();
+    assert_eq!(cosmo.zones.len(), 208);
+}
+
+#[test]
+fn test_cmd_global_bbox() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_bbox.json");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+    ]);
+    assert!(output.status.success());
+
+    // the global bounding box should contain all the zones' bbox
+    let cosmo = cosmogony::load_cosmogony_from_file(out_file).unwrap();
+    let bbox = cosmo.bounding_rect().unwrap();
+    for z in cosmo.zones.iter().filter_map(|z| z.bbox) {
+        assert!(bbox.min().x <= z.min().x && bbox.min().y <= z.min().y);
+        assert!(bbox.max().x >= z.max().x && bbox.max().y >= z.max().y);
+    }
+}
+
+#[test]
+fn test_cmd_with_json_stream_output() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.jsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+    ]);
+    assert!(output.status.success());
+
+    // we try also the streaming zone's reader
+    let zones = cosmogony::read_zones_from_file(out_file).unwrap();
+    assert_eq!(zones.count(), 208);
+}
+
+#[test]
+fn test_cmd_schema_version() {
+    let json_file = concat!(env!("OUT_DIR"), "/test_cosmogony_schema.json");
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_schema.jsonl");
+    for file in [json_file, out_file] {
+        let output = launch_command_line(vec![
+            "-i",
+            "./tests/data/luxembourg_filtered.osm.pbf",
+            "-o",
+            file,
+        ]);
+        assert!(output.status.success());
+    }
+
+    let cosmo = cosmogony::load_cosmogony_from_file(json_file).unwrap();
+    assert_eq!(cosmo.meta.schema_version, cosmogony::SCHEMA_VERSION);
+
+    // the schema version of a json stream is read from the marker
+    let cosmo = cosmogony::load_cosmogony_from_file(out_file).unwrap();
+    assert_eq!(cosmo.zones.len(), 208);
+    assert_eq!(cosmo.meta.schema_version, cosmogony::SCHEMA_VERSION);
+
+    // a file without marker is a version 0 file
+    let old_file = concat!(env!("OUT_DIR"), "/test_cosmogony_v0.jsonl");
+    let content = std::fs::read_to_string(out_file).unwrap();
+    let (_marker, zones) = content.split_once('\n').unwrap();
+    std::fs::write(old_file, zones).unwrap();
+    let cosmo = cosmogony::load_cosmogony_from_file(old_file).unwrap();
+    assert_eq!(cosmo.zones.len(), 208);
+    assert_eq!(cosmo.meta.schema_version, 0);
+}
+
+#[test]
+fn test_cmd_with_json_stream_gz_output() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.jsonl.gz");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+    ]);
+    assert!(output.status.success());
+
+    // we try also the streaming zone's reader
+    let zones = cosmogony::read_zones_from_file(out_file).unwrap();
+    assert_eq!(zones.count(), 208);
+}
+
+#[test]
+fn test_cmd_with_zstd_outputs() {
+    for (out_file, level) in [
+        (concat!(env!("OUT_DIR"), "/test_cosmogony.jsonl.zst"), "19"),
+        (concat!(env!("OUT_DIR"), "/test_cosmogony.json.zst"), "3"),
+    ] {
+        let output = launch_command_line(vec![
+            "-i",
+            "./tests/data/luxembourg_filtered.osm.pbf",
+            "-o",
+            out_file,
+            "--zstd-level",
+            level,
+        ]);
+        assert!(output.status.success());
+
+        let zones = cosmogony::read_zones_from_file(out_file).unwrap();
+        assert_eq!(zones.count(), 208);
+    }
+
+    // the zstd levels are between 1 and 22
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        concat!(env!("OUT_DIR"), "/test_cosmogony_invalid_level.jsonl.zst"),
+        "--zstd-level",
+        "23",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cmd_dry_run() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_dry_run.json");
+    let checkpoint = concat!(env!("OUT_DIR"), "/test_cosmogony_dry_run_checkpoint.jsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--dry-run",
+        "--checkpoint",
+        checkpoint,
+    ]);
+    assert!(output.status.success());
+    assert!(!Path::new(out_file).exists());
+    // nothing is written, not even the checkpoint
+    assert!(!Path::new(checkpoint).exists());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Admin level 8: "));
+    assert!(stdout.contains("LU: Lëtzebuerg"));
+
+    // the same errors as the build are reported
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--dry-run",
+        "--only-country",
+        "fr",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cmd_split_by_country() {
+    let out_dir = concat!(env!("OUT_DIR"), "/test_cosmogony_by_country");
+    let _ = std::fs::remove_dir_all(out_dir);
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "--split-by-country",
+        "--output-dir",
+        out_dir,
+    ]);
+    assert!(output.status.success());
+
+    let lux_file = Path::new(out_dir).join("LU.jsonl.gz");
+    // the hierarchy of each country file is consistent
+    let cosmogony = cosmogony::load_cosmogony_from_file(&lux_file).expect("invalid LU file");
+    assert!(!cosmogony.zones.is_empty());
+    assert!(cosmogony
+        .zones
+        .iter()
+        .all(|z| z.country_code.as_deref() == Some("LU")));
+
+    // the output directory is mandatory
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "--split-by-country",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cmd_validate_output() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_validated.jsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--validate-output",
+    ]);
+    assert!(output.status.success());
+
+    let out_dir = concat!(env!("OUT_DIR"), "/test_cosmogony_validated_by_country");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "--split-by-country",
+        "--output-dir",
+        out_dir,
+        "--validate-output",
+    ]);
+    assert!(output.status.success());
+
+    // the formats that cannot be read back are rejected
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_validated.geojsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--validate-output",
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be validated"));
+}
+
+#[test]
+fn test_cmd_with_json_stream_gz_output_without_compression() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_level_0.jsonl.gz");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--compression-level",
+        "0",
+    ]);
+    assert!(output.status.success());
+
+    // even without compression, it should be a valid gzip stream
+    let zones = cosmogony::read_zones_from_file(out_file).unwrap();
+    assert_eq!(zones.count(), 208);
+}
+
+#[test]
+fn test_cmd_with_filter_langs_file() {
+    let langs_file = concat!(env!("OUT_DIR"), "/test_filter_langs.txt");
+    std::fs::write(
+        langs_file,
+        "# the langs to keep\nfr\n\nde # german\nnot a lang\n",
+    )
+    .unwrap();
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_filter_langs.jsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--filter-langs",
+        "br",
+        "--filter-langs-file",
+        langs_file,
+    ]);
+    assert!(output.status.success());
+
+    let zones: Vec<_> = cosmogony::read_zones_from_file(out_file)
+        .unwrap()
+        .map(|z| z.unwrap())
+        .collect();
+    let lux = zones
+        .iter()
+        .find(|z| z.name == "Luxembourg" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    let mut langs: Vec<_> = lux.international_labels.keys().collect();
+    langs.sort();
+    assert_eq!(langs, vec!["br", "de", "fr"]);
+}
+
+#[test]
+fn test_cmd_with_stdout_output() {
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        "-",
+        "--format",
+        "jsonl",
+    ]);
+    assert!(output.status.success());
+
+    // the stdout only contains the schema marker and the zones, the logs are in stderr
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("{\"_cosmogony_schema\":1}"));
+    let zones: Vec<Zone> = lines.map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(zones.len(), 208);
+}
+
+#[test]
+fn test_cmd_with_stdout_output_without_format() {
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        "-",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cmd_with_flatgeobuf_output() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.fgb");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+    ]);
+    assert!(output.status.success());
+
+    let content = std::fs::read(out_file).unwrap();
+    // FlatGeobuf magic bytes
+    assert_eq!(&content[..3], b"fgb");
+    // but it cannot be read back as a cosmogony
+    assert!(cosmogony::load_cosmogony_from_file(out_file).is_err());
+}
+
+#[test]
+fn test_cmd_with_geopackage_output() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.gpkg");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+    ]);
+    assert!(output.status.success());
+
+    let conn = rusqlite::Connection::open(out_file).unwrap();
+    let application_id: i64 = conn
+        .query_row("PRAGMA application_id", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(application_id, 0x47504B47);
+    let data_type: String = conn
+        .query_row(
+            "SELECT data_type FROM gpkg_contents WHERE table_name = 'cosmogony'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(data_type, "features");
+
+    let (label, geom): (String, Vec<u8>) = conn
+        .query_row(
+            "SELECT label, geom FROM cosmogony WHERE osm_id = 'relation:407489'",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(label, "Luxembourg, Canton Luxembourg, Lëtzebuerg");
+    assert_eq!(&geom[..2], b"GP");
+
+    // all the zones with a geometry are in the spatial index
+    let nb_zones: i64 = conn
+        .query_row(
+            "SELECT count(*) FROM cosmogony WHERE geom IS NOT NULL",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    let nb_indexed: i64 = conn
+        .query_row("SELECT count(*) FROM rtree_cosmogony_geom", [], |r| {
+            r.get(0)
+        })
+        .unwrap();
+    assert!(nb_zones > 0);
+    assert_eq!(nb_zones, nb_indexed);
+}
+
+#[test]
+fn test_cmd_with_geojson_seq_output() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.geojsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+    ]);
+    assert!(output.status.success());
+
+    // each line is a GeoJSON Feature (without schema marker)
+    let content = std::fs::read_to_string(out_file).unwrap();
+    let features: Vec<geojson::Feature> = content
+        .lines()
+        .map(|l| l.parse::<geojson::GeoJson>().unwrap().try_into().unwrap())
+        .collect();
+    assert_eq!(features.len(), 208);
+    let lux = features
+        .iter()
+        .find(|f| f.property("osm_id") == Some(&"relation:407489".into()))
+        .unwrap();
+    assert_eq!(lux.property("zone_type"), Some(&"city".into()));
+    assert!(lux.geometry.is_some());
+}
+
+#[test]
+fn test_cmd_with_json_gz_output() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.json.gz");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+    ]);
+    assert!(output.status.success());
+    let cosmo = cosmogony::load_cosmogony_from_file(out_file).unwrap();
+    assert_eq!(cosmo.zones.len(), 208);
+}
+
+#[test]
+fn test_cmd_with_drop_geometry() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_without_geometry.jsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--drop-geometry",
+    ]);
+    assert!(output.status.success());
+
+    let zones: Vec<_> = cosmogony::read_zones_from_file(out_file)
+        .unwrap()
+        .map(|z| z.unwrap())
+        .collect();
+    assert_eq!(zones.len(), 208);
+    assert!(zones.iter().all(|z| z.boundary.is_none()));
+    assert!(zones.iter().all(|z| z.bbox.is_some()));
+}
+
+#[test]
+fn test_cmd_with_keep_tags() {
+    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony_keep_tags.jsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        out_file,
+        "--keep-tags",
+        "population,name:*",
+    ]);
+    assert!(output.status.success());
+
+    let zones: Vec<_> = cosmogony::read_zones_from_file(out_file)
+        .unwrap()
+        .map(|z| z.unwrap())
+        .collect();
+    assert_eq!(zones.len(), 208);
+    let allowed = |k: &str| {
+        k.starts_with("name:")
+            || [
+                "population",
+                "name",
+                "loc_name",
+                "alt_name",
+                "admin_level",
+                "wikidata",
+                "wikipedia",
+                "website",
+                "addr:postcode",
+                "postal_code",
+                "place",
+                "ISO3166-1:alpha2",
+            ]
+            .contains(&k)
+    };
+    for z in &zones {
+        assert!(z.tags.iter().all(|(k, _)| allowed(k.as_str())));
+        assert!(z.center_tags.iter().all(|(k, _)| allowed(k.as_str())));
+    }
+
+    // the labels are still computed with all the names
+    let lux = zones
+        .iter()
+        .find(|z| z.name == "Luxembourg" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    assert_eq!(
+        lux.international_labels.get("de"),
+        Some(&"Luxemburg, Kanton Luxemburg, Luxemburg".to_string())
+    );
+    assert_eq!(lux.tags.get("name"), Some(&"Luxembourg".into()));
+}
+
+#[test]
+fn test_cmd_merge_append() {
+    let lux_file = concat!(env!("OUT_DIR"), "/test_cosmogony_to_merge.jsonl.gz");
+    let merged_file = concat!(env!("OUT_DIR"), "/test_cosmogony_merged.jsonl.gz");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        lux_file,
+    ]);
+    assert!(output.status.success());
+
+    let output = launch_command_line(vec!["merge", lux_file, "-o", merged_file]);
+    assert!(output.status.success());
+    let output = launch_command_line(vec!["merge", "--append", lux_file, "-o", merged_file]);
+    assert!(output.status.success());
+
+    // the zones have been appended, with new ids
+    let cosmo = cosmogony::load_cosmogony_from_file(merged_file).unwrap();
+    assert_eq!(cosmo.zones.len(), 2 * 208);
+    assert_eq!(cosmo.meta.schema_version, cosmogony::SCHEMA_VERSION);
+    let ids: std::collections::BTreeSet<_> = cosmo.zones.iter().map(|z| z.id).collect();
+    assert_eq!(ids.len(), cosmo.zones.len());
+    // the parents and candidate parents of the appended zones follow their new ids
+    let (first, appended) = cosmo.zones.split_at(208);
+    let shifted = |id: &ZoneIndex| ZoneIndex {
+        index: id.index + 208,
+    };
+    for (z, appended_z) in first.iter().zip(appended) {
+        assert_eq!(appended_z.id, shifted(&z.id));
+        assert_eq!(appended_z.parent, z.parent.as_ref().map(shifted));
+        assert_eq!(
+            appended_z.candidate_parents,
+            z.candidate_parents.iter().map(shifted).collect::<Vec<_>>()
+        );
+    }
+
+    // without append, the output is overwritten
+    let output = launch_command_line(vec!["merge", lux_file, "-o", merged_file]);
+    assert!(output.status.success());
+    let cosmo = cosmogony::load_cosmogony_from_file(merged_file).unwrap();
+    assert_eq!(cosmo.zones.len(), 208);
+
+    // the merge fails on an unsupported format, without touching the existing output
+    let json_file = concat!(env!("OUT_DIR"), "/test_cosmogony_merged.json");
+    std::fs::write(json_file, "existing").unwrap();
+    let output = launch_command_line(vec!["merge", lux_file, "-o", json_file]);
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read_to_string(json_file).unwrap(), "existing");
+}
+
+#[test]
+fn test_cmd_locate() {
+    let lux_file = concat!(env!("OUT_DIR"), "/test_cosmogony_to_locate.jsonl");
+    let points_file = concat!(env!("OUT_DIR"), "/test_cosmogony_points.csv");
+    let located_file = concat!(env!("OUT_DIR"), "/test_cosmogony_located.csv");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        lux_file,
+    ]);
+    assert!(output.status.success());
+
+    // a point in Luxembourg city, and one in the sea
+    std::fs::write(points_file, "id,lon,lat\n1,6.1296,49.6112\n2,-30,0\n").unwrap();
+    let output = launch_command_line(vec![
+        "locate",
+        "-i",
+        lux_file,
+        "--points",
+        points_file,
+        "-o",
+        located_file,
+    ]);
+    assert!(output.status.success());
+
+    let located = std::fs::read_to_string(located_file).unwrap();
+    let rows: Vec<Vec<&str>> = located.lines().map(|l| l.split(',').collect()).collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0][..4], ["lon", "lat", "zone_osm_id", "zone_name"]);
+    assert_eq!(rows[1][0], "6.1296");
+    assert_eq!(rows[1][5], "Luxembourg");
+    assert_eq!(rows[1][9], "Lëtzebuerg");
+    assert_eq!(rows[2][0], "-30");
+    assert!(rows[2][2..].iter().all(|c| c.is_empty()));
+}
+
+#[test]
+fn test_cmd_extract() {
+    let lux_file = concat!(env!("OUT_DIR"), "/test_cosmogony_to_extract.jsonl");
+    let extract_file = concat!(env!("OUT_DIR"), "/test_cosmogony_extracted.jsonl");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        lux_file,
+    ]);
+    assert!(output.status.success());
+
+    let output = launch_command_line(vec![
+        "extract",
+        "-i",
+        lux_file,
+        "--root",
+        "relation:407489",
+        "-o",
+        extract_file,
+    ]);
+    assert!(output.status.success());
+
+    let full = cosmogony::load_cosmogony_from_file(lux_file).unwrap();
+    let full_lux = full
+        .zones
+        .iter()
+        .find(|z| z.osm_id == "relation:407489")
+        .unwrap();
+    let nb_children = full
+        .zones
+        .iter()
+        .filter(|z| z.parent == Some(full_lux.id))
+        .count();
+    assert!(nb_children > 0);
+
+    let extracted = cosmogony::load_cosmogony_from_file(extract_file).unwrap();
+    assert_eq!(extracted.meta.schema_version, cosmogony::SCHEMA_VERSION);
+    assert!(extracted.zones.len() > nb_children);
+    assert!(extracted.zones.len() < full.zones.len());
+
+    // the root is the only zone without parent, all the zones being reindexed
+    let lux = &extracted.zones[0];
+    assert_eq!(lux.osm_id, "relation:407489");
+    assert_eq!(lux.parent, None);
+    for (index, z) in extracted.zones.iter().enumerate() {
+        assert_eq!(z.id.index, index);
+        if index > 0 {
+            let parent = z.parent.expect("only the root has no parent");
+            assert!(parent.index < extracted.zones.len());
+        }
+    }
+    let lux_children = extracted
+        .zones
+        .iter()
+        .filter(|z| z.parent == Some(lux.id))
+        .count();
+    assert_eq!(lux_children, nb_children);
+}
+
+#[test]
+fn test_cmd_tiles() {
+    let lux_file = concat!(env!("OUT_DIR"), "/test_cosmogony_to_render.jsonl");
+    let tiles_file = concat!(env!("OUT_DIR"), "/test_cosmogony.mbtiles");
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        lux_file,
+    ]);
+    assert!(output.status.success());
+
+    let output = launch_command_line(vec![
+        "tiles",
+        "-i",
+        lux_file,
+        "--min-zoom",
+        "6",
+        "--max-zoom",
+        "8",
+        "-o",
+        tiles_file,
+    ]);
+    assert!(output.status.success());
+
+    let conn = rusqlite::Connection::open(tiles_file).unwrap();
+    let format: String = conn
+        .query_row(
+            "SELECT value FROM metadata WHERE name = 'format'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(format, "pbf");
+    let json: String = conn
+        .query_row("SELECT value FROM metadata WHERE name = 'json'", [], |r| {
+            r.get(0)
+        })
+        .unwrap();
+    assert!(json.contains(r#""id":"city""#));
+
+    // Luxembourg is in the tile 6/33/21 (row 42 in the TMS scheme)
+    let nb_tiles: u32 = conn
+        .query_row(
+            "SELECT count(*) FROM tiles WHERE zoom_level = 6 AND tile_column = 33 AND tile_row = 42",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(nb_tiles, 1);
+    let zooms: Vec<u8> = conn
+        .prepare("SELECT DISTINCT zoom_level FROM tiles ORDER BY zoom_level")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(zooms, vec![6, 7, 8]);
+
+    // the zoom levels are checked
+    let output = launch_command_line(vec![
+        "tiles",
+        "-i",
+        lux_file,
+        "--min-zoom",
+        "9",
+        "--max-zoom",
+        "8",
+        "-o",
+        tiles_file,
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_cmd_check() {
+    let output = launch_command_line(vec![
+        "check",
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+    ]);
+    assert!(output.status.success());
+    // each invalid boundary is reported as 'osm_id<TAB>name<TAB>reason'
+    let report = String::from_utf8_lossy(&output.stdout);
+    for line in report.lines() {
+        assert_eq!(line.split('\t').count(), 3, "invalid report line: {}", line);
+        assert!(line.starts_with("relation:"));
+    }
+
+    // in strict mode, the check fails only if there is an invalid boundary
+    let strict_output = launch_command_line(vec![
+        "check",
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "--strict",
+    ]);
+    assert_eq!(strict_output.status.success(), report.is_empty());
+}
+
+#[test]
+fn test_cmd_with_unknown_format() {
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "-o",
+        "cosmogony.bad",
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unable to detect the file format"));
+}
+
+fn create_cosmogony_for_lux() -> Cosmogony {
+    cosmogony_builder::build_cosmogony_with_config(&lux_config()).expect("invalid cosmogony")
+}
+
+// points spread over the bounding box of a cosmogony, always the same ones
+fn pseudo_random_points(cosmogony: &Cosmogony, nb_points: usize) -> Vec<(f64, f64)> {
+    let bbox = cosmogony.bounding_rect().unwrap();
+    let mut seed: u64 = 42;
+    let mut next = move || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 11) as f64 / (1u64 << 53) as f64
+    };
+    (0..nb_points)
+        .map(|_| {
+            (
+                bbox.min().x + next() * bbox.width(),
+                bbox.min().y + next() * bbox.height(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_cosmogony_index() {
+    let cosmogony = create_cosmogony_for_lux();
+    let index = CosmogonyIndex::build(&cosmogony);
+
+    let lux = index
+        .query(6.1296, 49.6112)
+        .expect("no zone in Luxembourg city");
+    assert!(lux.contains_point(6.1296, 49.6112));
+    assert!(lux.zone_type.unwrap() <= ZoneType::City);
+
+    // the same zones as a full scan
+    for (lon, lat) in pseudo_random_points(&cosmogony, 1_000) {
+        let deepest = cosmogony
+            .zones
+            .iter()
+            .filter(|z| z.contains_point(lon, lat))
+            .map(|z| z.depth)
+            .max();
+        assert_eq!(index.query(lon, lat).map(|z| z.depth), deepest);
+    }
+}
+
+// throughput of the index on 1M points, to be timed with
+// `time cargo test --release -- --ignored cosmogony_index_throughput`
+#[test]
+#[ignore]
+fn cosmogony_index_throughput() {
+    let cosmogony = create_cosmogony_for_lux();
+    let index = CosmogonyIndex::build(&cosmogony);
+
+    let points = pseudo_random_points(&cosmogony, 1_000_000);
+    let found = points
+        .iter()
+        .filter(|(lon, lat)| index.query(*lon, *lat).is_some())
+        .count();
+    assert!(found > 0);
+}
+
+fn test_wrapper_for_lux_admin_levels(a_cosmogony: &Cosmogony) {
+    // Ensure that all well-defined (with closed boundaries)
+    // administrative zones are loaded from the sample .osm.pbf file,
+    // with correct counts per admin_level.
+    let level_counts = a_cosmogony.meta.stats.level_counts.clone();
+    let wikidata_counts = a_cosmogony.meta.stats.wikidata_counts.clone();
+
+    fn assert_count(counts: &BTreeMap<u32, u64>, key: u32, value: u64) {
+        assert_eq!(
+            *counts.get(&key).unwrap_or(&0),
+            value,
+            "Expected {} admins at level {}",
+            value,
+            key
+        )
+    }
+
+    assert_count(&level_counts, 2, 1); // 1 x admin_level==2
+    assert_count(&wikidata_counts, 2, 1);
+    assert_count(&level_counts, 3, 0); // 0 x admin_level==3
+    assert_count(&wikidata_counts, 3, 0);
+    assert_count(&level_counts, 4, 0); // etc.
+    assert_count(&wikidata_counts, 4, 0);
+    assert_count(&level_counts, 5, 0);
+    assert_count(&wikidata_counts, 5, 0);
+    assert_count(&level_counts, 6, 13); // 12 cantons + 1 territory (DE-LU)
+    assert_count(&wikidata_counts, 6, 13);
+    assert_count(&level_counts, 7, 0);
+    assert_count(&wikidata_counts, 7, 0);
+    assert_count(&level_counts, 8, 105); // 104 + 1 outside LU
+    assert_count(&wikidata_counts, 8, 105);
+    assert_count(&level_counts, 9, 79);
+    // the level 10 is not defined in the libpostal hierarchy, we should not have level 10 admins
+    assert_count(&level_counts, 10, 0);
+}
+
+fn test_wrapper_for_lux_zones(a_cosmogony: &Cosmogony) {
+    let zone = a_cosmogony
+        .zones
+        .iter()
+        .find(|z| z.name == "Esch-sur-Alzette" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+
+    let bbox = zone.bbox.unwrap();
+    assert_relative_eq!(bbox.min().x, 5.9432118, epsilon = 1e-8);
+    assert_relative_eq!(bbox.min().y, 49.460907, epsilon = 1e-8);
+    assert_relative_eq!(bbox.max().x, 6.005144, epsilon = 1e-8);
+    assert_relative_eq!(bbox.max().y, 49.518616, epsilon = 1e-8);
+}
+
+#[test]
+fn test_lux_cosmogony() {
+    // Check some random values in the built cosmogony
+    // from the sample .osm.pbf file,
+    let cosmogony = create_cosmogony_for_lux();
+    assert_eq!(cosmogony.meta.osm_filename, "luxembourg_filtered.osm.pbf");
+    assert_eq!(cosmogony.zones.len(), 198);
+
+    test_wrapper_for_lux_admin_levels(&cosmogony);
+    test_wrapper_for_lux_zones(&cosmogony);
+}
+
+#[test]
+fn test_lux_cosmogony_retype() {
+    let rules_dir = std::env::temp_dir().join("cosmogony_retype_rules");
+    std::fs::create_dir_all(&rules_dir).unwrap();
+    std::fs::write(
+        rules_dir.join("lu.yaml"),
+        "admin_level:\n  \"2\": \"country\"\n  \"6\": \"state\"\n  \"8\": \"city\"\n",
+    )
+    .unwrap();
+    let typer = ZoneTyper::from_dir(&rules_dir).unwrap();
+
+    let mut cosmogony = create_cosmogony_for_lux();
+    let nb_zones = cosmogony.zones.len();
+    cosmogony
+        .retype(&typer, &[], &LabelFormat::default())
+        .unwrap();
+    assert_eq!(cosmogony.zones.len(), nb_zones);
+
+    let types_of_level = |cosmogony: &Cosmogony, level| -> Vec<Option<ZoneType>> {
+        cosmogony
+            .zones
+            .iter()
+            .filter(|z| z.admin_level == Some(level))
+            .map(|z| z.zone_type)
+            .collect()
+    };
+    assert!(types_of_level(&cosmogony, 6)
+        .iter()
+        .all(|t| *t == Some(ZoneType::State)));
+    // there is no rule for the level 9 anymore
+    assert!(types_of_level(&cosmogony, 9).iter().all(|t| t.is_none()));
+
+    // the hierarchy and the labels are updated with the new types
+    let lux = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.name == "Luxembourg" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    let parent = &cosmogony.zones[lux.parent.unwrap().index];
+    assert_eq!(parent.zone_type, Some(ZoneType::State));
+    assert!(lux.label.contains(&parent.name));
+    assert!(cosmogony
+        .zones
+        .iter()
+        .filter_map(|z| z.parent)
+        .all(|p| cosmogony.zones[p.index].zone_type.is_some()));
+
+    // without geometries, nothing is done
+    let mut cosmogony = create_cosmogony_for_lux();
+    cosmogony.zones.iter_mut().for_each(|z| z.boundary = None);
+    let types: Vec<_> = cosmogony.zones.iter().map(|z| z.zone_type).collect();
+    cosmogony
+        .retype(&typer, &[], &LabelFormat::default())
+        .unwrap();
+    assert_eq!(
+        cosmogony
+            .zones
+            .iter()
+            .map(|z| z.zone_type)
+            .collect::<Vec<_>>(),
+        types
+    );
+}
+
+#[test]
+fn test_retype_with_voronoi() {
+    let mut cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&BuildConfig::new(IVORY_COAST_PBF))
+            .expect("invalid cosmogony");
+    let generated = |cosmogony: &Cosmogony| -> Vec<(String, Option<ZoneType>, Option<String>)> {
+        cosmogony
+            .zones
+            .iter()
+            .filter(|z| z.is_generated)
+            .map(|z| {
+                let parent = z.parent.map(|p| cosmogony.zones[p.index].osm_id.clone());
+                (z.osm_id.clone(), z.zone_type, parent)
+            })
+            .collect()
+    };
+    // the zones are reindexed by the retyping, the parents are looked up by position
+    cosmogony::reindex_zones(&mut cosmogony.zones);
+    let generated_zones = generated(&cosmogony);
+    let nb_zones = cosmogony.zones.len();
+    assert!(generated_zones.len() > 1000);
+
+    // the voronoi places keep their type and their parent
+    cosmogony
+        .retype(&ZoneTyper::new().unwrap(), &[], &LabelFormat::default())
+        .unwrap();
+    assert_eq!(cosmogony.zones.len(), nb_zones);
+    assert_eq!(generated(&cosmogony), generated_zones);
+}
+
+#[test]
+fn test_lux_cosmogony_max_raw_admin_level() {
+    let build = |config: BuildConfig| {
+        cosmogony_builder::build_cosmogony_with_config(&config).expect("invalid cosmogony")
+    };
+
+    // the default maximum is the one of the command line
+    assert_eq!(
+        BuildConfig::new(LUX_PBF).max_raw_admin_level,
+        Some(cosmogony_builder::DEFAULT_MAX_RAW_ADMIN_LEVEL)
+    );
+
+    // there is no admin_level above 11 in Luxembourg
+    let cosmogony = build(lux_config());
+    assert_eq!(cosmogony.zones.len(), 198);
+    assert_eq!(cosmogony.meta.stats.dropped_admin_levels, 0);
+    test_wrapper_for_lux_admin_levels(&cosmogony);
+
+    // the cities (admin_level 8) are dropped
+    let cosmogony = build(lux_config().max_raw_admin_level(7));
+    assert!(cosmogony.meta.stats.dropped_admin_levels >= 105);
+    assert!(cosmogony.zones.iter().all(|z| z.admin_level <= Some(7)));
+
+    // or clamped
+    let cosmogony = build(
+        lux_config()
+            .max_raw_admin_level(7)
+            .clamp_raw_admin_level(true),
+    );
+    assert_eq!(cosmogony.meta.stats.dropped_admin_levels, 0);
+    assert!(cosmogony.meta.stats.clamped_admin_levels >= 105);
+    assert!(cosmogony.zones.iter().all(|z| z.admin_level <= Some(7)));
+}
+
+#[test]
+fn test_lux_cosmogony_with_serialisation() {
+    // Serialize and deserialize a built cosmogony
+    // and check again the admin_level counts.
+    let cosmogony = create_cosmogony_for_lux();
+
+    let cosmogony_as_json = serde_json::to_string(&cosmogony).unwrap();
+    let cosmogony_from_json: Cosmogony = serde_json::from_str(&cosmogony_as_json).unwrap();
+
+    test_wrapper_for_lux_admin_levels(&cosmogony_from_json);
+    test_wrapper_for_lux_zones(&cosmogony_from_json);
+}
+
+#[test]
+fn test_lux_cosmogony_from_checkpoint() {
+    let checkpoint = Path::new(concat!(env!("OUT_DIR"), "/lux_checkpoint.jsonl"));
+
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().checkpoint(checkpoint))
+            .expect("invalid cosmogony");
+    assert!(checkpoint.exists());
+
+    // resuming from the checkpoint should give the same cosmogony
+    let resumed_cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().resume_from(checkpoint))
+            .expect("invalid cosmogony");
+
+    assert_eq!(resumed_cosmogony.zones.len(), cosmogony.zones.len());
+    test_wrapper_for_lux_admin_levels(&resumed_cosmogony);
+    test_wrapper_for_lux_zones(&resumed_cosmogony);
+}
+
+#[test]
+fn test_lux_cosmogony_with_inclusions() {
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().keep_inclusions(true))
+            .expect("invalid cosmogony");
+
+    // the parent of a zone is one of the zones containing it
+    let lux = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.name == "Luxembourg" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    assert!(lux.candidate_parents.contains(&lux.parent.unwrap()));
+
+    // by default, the inclusions are not kept
+    assert!(create_cosmogony_for_lux()
+        .zones
+        .iter()
+        .all(|z| z.candidate_parents.is_empty()));
+}
+
+#[test]
+fn test_lux_cosmogony_keep_untyped() {
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().keep_untyped(true))
+            .expect("invalid cosmogony");
+
+    // the untyped zones are kept as non administrative zones
+    assert!(cosmogony.zones.len() >= create_cosmogony_for_lux().zones.len());
+    assert!(cosmogony.zones.iter().all(|z| z.zone_type.is_some()));
+    assert!(cosmogony
+        .zones
+        .iter()
+        .filter(|z| z.zone_type == Some(ZoneType::NonAdministrative))
+        .all(|z| z.country_code.is_some()));
+}
+
+#[test]
+fn test_lux_cosmogony_keep_international_names() {
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(
+        &lux_config().keep_international_names(true),
+    )
+    .expect("invalid cosmogony");
+
+    // the names survive a serialization round-trip
+    let json = serde_json::to_string(&cosmogony).unwrap();
+    let loaded: Cosmogony = serde_json::from_str(&json).unwrap();
+    let lux = loaded
+        .zones
+        .iter()
+        .find(|z| z.name == "Luxembourg" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    assert_eq!(
+        lux.international_names.get("de"),
+        Some(&"Luxemburg".to_string())
+    );
+
+    // by default, the names are not kept
+    assert!(create_cosmogony_for_lux()
+        .zones
+        .iter()
+        .all(|z| z.international_names.is_empty()));
+}
+
+#[test]
+fn test_lux_cosmogony_keep_provenance() {
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().keep_provenance(true))
+            .expect("invalid cosmogony");
+
+    let lux = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.name == "Luxembourg" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    assert_eq!(lux.admin_level_source, Some(AdminLevelSource::RelationTag));
+    assert!(cosmogony
+        .zones
+        .iter()
+        .all(|z| z.admin_level.is_some() == z.admin_level_source.is_some()));
+
+    // by default, the provenance is not kept nor serialized
+    let cosmogony = create_cosmogony_for_lux();
+    assert!(cosmogony
+        .zones
+        .iter()
+        .all(|z| z.admin_level_source.is_none()));
+    let json = serde_json::to_string(&cosmogony.zones[0]).unwrap();
+    assert!(!json.contains("admin_level_source"));
+}
+
+#[test]
+fn test_lux_cosmogony_merge_same_name_siblings() {
+    let config = lux_config();
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&config.clone()).expect("invalid cosmogony");
+    let merged_cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&config.merge_same_name_siblings(true))
+            .expect("invalid cosmogony");
+
+    let nb_merged = merged_cosmogony.meta.stats.merged_siblings;
+    assert_eq!(cosmogony.meta.stats.merged_siblings, 0);
+    assert_eq!(
+        merged_cosmogony.zones.len() + nb_merged,
+        cosmogony.zones.len()
+    );
+
+    // the parents still reference existing zones
+    for z in &merged_cosmogony.zones {
+        if let Some(parent) = z.parent {
+            assert!(parent.index < merged_cosmogony.zones.len());
+        }
+    }
+}
+
+#[test]
+fn test_lux_zones_by_name() {
+    let cosmogony = create_cosmogony_for_lux();
+    let index = cosmogony::NameIndex::build(&cosmogony);
+
+    let esch = index.find("esch sur alzette");
+    assert!(!esch.is_empty());
+    assert!(esch.iter().all(|z| z.name == "Esch-sur-Alzette"));
+    assert_eq!(index.find("ESCH-SUR-ALZETTE").len(), esch.len());
+    assert!(index.find("esch").is_empty());
+
+    let prefixed = index.find_by_prefix("esch");
+    assert!(prefixed.len() >= esch.len());
+    assert!(prefixed
+        .iter()
+        .all(|z| cosmogony::normalize_name(&z.name).starts_with("esch")));
+}
+
+#[test]
+fn test_lux_cosmogony_keep_osm_meta() {
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().keep_osm_meta(true))
+            .expect("invalid cosmogony");
+
+    // the metadata are only read for the relations, and they come together
+    for z in &cosmogony.zones {
+        assert_eq!(z.osm_version.is_some(), z.osm_timestamp.is_some());
+        if z.osm_version.is_some() {
+            assert!(z.osm_id.starts_with("relation:"));
+            assert!(z.osm_timestamp.as_ref().unwrap().ends_with('Z'));
+        }
+    }
+
+    // the metadata of the country's relation in the osm file
+    let country = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.osm_id == "relation:2171347")
+        .unwrap();
+    assert_eq!(country.osm_version, Some(86));
+    assert_eq!(
+        country.osm_timestamp.as_deref(),
+        Some("2017-12-06T12:22:11Z")
+    );
+
+    // by default, the metadata are not kept nor serialized
+    let cosmogony = create_cosmogony_for_lux();
+    assert!(cosmogony.zones.iter().all(|z| z.osm_version.is_none()));
+    let json = serde_json::to_string(&cosmogony.zones[0]).unwrap();
+    assert!(!json.contains("osm_version"));
+    assert!(!json.contains("osm_timestamp"));
 }
 
 #[test]
-fn test_cmd_with_json_stream_output() {
-    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.jsonl");
-    let output = launch_command_line(vec![
-        "-i",
-        "./tests/data/luxembourg_filtered.osm.pbf",
-        "-o",
-        out_file,
-    ]);
-    assert!(output.status.success());
+fn test_lux_cosmogony_single_threaded_typing() {
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().single_threaded_typing(true))
+            .expect("invalid cosmogony");
 
-    // we try also the streaming zone's reader
-    let zones = cosmogony::read_zones_from_file(out_file).unwrap();
-    assert_eq!(zones.count(), 208);
+    // the zones are typed exactly as with the parallel typing
+    let types = |cosmogony: &Cosmogony| -> BTreeMap<String, Option<ZoneType>> {
+        cosmogony
+            .zones
+            .iter()
+            .map(|z| (z.osm_id.clone(), z.zone_type))
+            .collect()
+    };
+    let parallel_cosmogony = create_cosmogony_for_lux();
+    assert_eq!(types(&cosmogony), types(&parallel_cosmogony));
+    assert_eq!(cosmogony.meta.stats, parallel_cosmogony.meta.stats);
 }
 
 #[test]
-fn test_cmd_with_json_stream_gz_output() {
-    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.jsonl.gz");
-    let output = launch_command_line(vec![
-        "-i",
-        "./tests/data/luxembourg_filtered.osm.pbf",
-        "-o",
-        out_file,
-    ]);
-    assert!(output.status.success());
+fn test_lux_cosmogony_countries_allowlist() {
+    let build = |countries: Vec<String>| {
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().countries(countries))
+            .expect("invalid cosmogony")
+    };
 
-    // we try also the streaming zone's reader
-    let zones = cosmogony::read_zones_from_file(out_file).unwrap();
-    assert_eq!(zones.count(), 208);
-}
+    // the allowlist is case insensitive
+    let lux = build(vec!["fr".into(), "lu".into()]);
+    assert_eq!(lux.zones.len(), create_cosmogony_for_lux().zones.len());
+    assert_eq!(lux.meta.stats.zone_outside_countries, 0);
 
-#[test]
-fn test_cmd_with_json_gz_output() {
-    let out_file = concat!(env!("OUT_DIR"), "/test_cosmogony.json.gz");
-    let output = launch_command_line(vec![
-        "-i",
-        "./tests/data/luxembourg_filtered.osm.pbf",
-        "-o",
-        out_file,
-    ]);
-    assert!(output.status.success());
-    let cosmo = cosmogony::load_cosmogony_from_file(out_file).unwrap();
-    assert_eq!(cosmo.zones.len(), 208);
+    let not_lux = build(vec!["FR".into()]);
+    assert!(not_lux.zones.is_empty());
+    // the zones dropped since their level is not typed are also counted
+    assert!(not_lux.meta.stats.zone_outside_countries >= lux.zones.len());
 }
 
 #[test]
-fn test_cmd_with_unknown_format() {
-    let output = launch_command_line(vec![
-        "-i",
-        "./tests/data/luxembourg_filtered.osm.pbf",
-        "-o",
-        "cosmogony.bad",
-    ]);
-    assert!(!output.status.success());
-    assert!(String::from_utf8_lossy(&output.stderr).contains("Unable to detect the file format"));
-}
+fn test_lux_cosmogony_sorted() {
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(&lux_config().sort(true))
+        .expect("invalid cosmogony");
 
-fn create_cosmogony_for_lux() -> Cosmogony {
-    let test_file = concat!(
-        env!("OUT_DIR"),
-        "/../../../../../tests/data/luxembourg_filtered.osm.pbf"
+    assert_eq!(
+        cosmogony.zones.len(),
+        create_cosmogony_for_lux().zones.len()
     );
+    // the zones are sorted and their ids are their position
+    for (pos, z) in cosmogony.zones.iter().enumerate() {
+        assert_eq!(z.id.index, pos);
+    }
+    assert!(cosmogony.zones.windows(2).all(|w| {
+        (w[0].zone_type, &w[0].country_code, &w[0].osm_id)
+            <= (w[1].zone_type, &w[1].country_code, &w[1].osm_id)
+    }));
 
-    cosmogony_builder::build_cosmogony(test_file.into(), Some("lu".into()), true, &[])
-        .expect("invalid cosmogony")
+    // the hierarchy is still valid
+    cosmogony.validate_hierarchy().unwrap();
+    let lux = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.name == "Luxembourg" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    assert_eq!(
+        cosmogony.zones[lux.parent.unwrap().index].name,
+        "Canton Luxembourg"
+    );
 }
 
-fn test_wrapper_for_lux_admin_levels(a_cosmogony: &Cosmogony) {
-    // Ensure that all well-defined (with closed boundaries)
-    // administrative zones are loaded from the sample .osm.pbf file,
-    // with correct counts per admin_level.
-    let level_counts = a_cosmogony.meta.stats.level_counts.clone();
-    let wikidata_counts = a_cosmogony.meta.stats.wikidata_counts.clone();
+#[test]
+fn test_lux_cosmogony_only_cities() {
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(
+        &lux_config().only_types(vec![ZoneType::City]),
+    )
+    .expect("invalid cosmogony");
 
-    fn assert_count(counts: &BTreeMap<u32, u64>, key: u32, value: u64) {
-        assert_eq!(
-            *counts.get(&key).unwrap_or(&0),
-            value,
-            "Expected {} admins at level {}",
-            value,
-            key
-        )
+    assert_eq!(cosmogony.zones.len(), 105);
+    assert!(cosmogony
+        .zones
+        .iter()
+        .all(|z| z.zone_type == Some(ZoneType::City)));
+    // the zones are reindexed, and there is no city parent of a city in Luxembourg
+    for (pos, z) in cosmogony.zones.iter().enumerate() {
+        assert_eq!(z.id.index, pos);
+        assert_eq!(z.parent, None);
     }
+    cosmogony.validate_hierarchy().unwrap();
 
-    assert_count(&level_counts, 2, 1); // 1 x admin_level==2
-    assert_count(&wikidata_counts, 2, 1);
-    assert_count(&level_counts, 3, 0); // 0 x admin_level==3
-    assert_count(&wikidata_counts, 3, 0);
-    assert_count(&level_counts, 4, 0); // etc.
-    assert_count(&wikidata_counts, 4, 0);
-    assert_count(&level_counts, 5, 0);
-    assert_count(&wikidata_counts, 5, 0);
-    assert_count(&level_counts, 6, 13); // 12 cantons + 1 territory (DE-LU)
-    assert_count(&wikidata_counts, 6, 13);
-    assert_count(&level_counts, 7, 0);
-    assert_count(&wikidata_counts, 7, 0);
-    assert_count(&level_counts, 8, 105); // 104 + 1 outside LU
-    assert_count(&wikidata_counts, 8, 105);
-    assert_count(&level_counts, 9, 79);
-    // the level 10 is not defined in the libpostal hierarchy, we should not have level 10 admins
-    assert_count(&level_counts, 10, 0);
-}
-
-fn test_wrapper_for_lux_zones(a_cosmogony: &Cosmogony) {
-    let zone = a_cosmogony
+    // the labels still contain the filtered out ancestors
+    let lux = cosmogony
         .zones
         .iter()
-        .find(|z| z.name == "Esch-sur-Alzette" && z.zone_type == Some(ZoneType::City))
+        .find(|z| z.name == "Luxembourg")
         .unwrap();
-
-    let bbox = zone.bbox.unwrap();
-    assert_relative_eq!(bbox.min().x, 5.9432118, epsilon = 1e-8);
-    assert_relative_eq!(bbox.min().y, 49.460907, epsilon = 1e-8);
-    assert_relative_eq!(bbox.max().x, 6.005144, epsilon = 1e-8);
-    assert_relative_eq!(bbox.max().y, 49.518616, epsilon = 1e-8);
+    assert_eq!(lux.label, "Luxembourg, Canton Luxembourg, Lëtzebuerg");
 }
 
 #[test]
-fn test_lux_cosmogony() {
-    // Check some random values in the built cosmogony
-    // from the sample .osm.pbf file,
-    let cosmogony = create_cosmogony_for_lux();
-    assert_eq!(cosmogony.meta.osm_filename, "luxembourg_filtered.osm.pbf");
-    assert_eq!(cosmogony.zones.len(), 198);
+fn test_lux_cosmogony_only_suburbs_and_cities() {
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(
+        &lux_config().only_types(vec![ZoneType::City, ZoneType::Suburb]),
+    )
+    .expect("invalid cosmogony");
 
-    test_wrapper_for_lux_admin_levels(&cosmogony);
-    test_wrapper_for_lux_zones(&cosmogony);
+    assert_eq!(cosmogony.zones.len(), 105 + 55);
+    cosmogony.validate_hierarchy().unwrap();
+    // the parents are the nearest kept ancestors
+    for z in &cosmogony.zones {
+        match z.zone_type {
+            Some(ZoneType::City) => assert_eq!(z.parent, None),
+            _ => {
+                if let Some(parent) = z.parent {
+                    let parent = &cosmogony.zones[parent.index];
+                    assert_eq!(parent.zone_type, Some(ZoneType::City));
+                }
+            }
+        }
+    }
 }
 
 #[test]
-fn test_lux_cosmogony_with_serialisation() {
-    // Serialize and deserialize a built cosmogony
-    // and check again the admin_level counts.
+fn test_lux_distance_between() {
     let cosmogony = create_cosmogony_for_lux();
+    let city = |name: &str| {
+        cosmogony
+            .zones
+            .iter()
+            .find(|z| z.name == name && z.zone_type == Some(ZoneType::City))
+            .unwrap()
+            .id
+    };
 
-    let cosmogony_as_json = serde_json::to_string(&cosmogony).unwrap();
-    let cosmogony_from_json: Cosmogony = serde_json::from_str(&cosmogony_as_json).unwrap();
-
-    test_wrapper_for_lux_admin_levels(&cosmogony_from_json);
-    test_wrapper_for_lux_zones(&cosmogony_from_json);
+    // Luxembourg and Esch-sur-Alzette are about 15km apart
+    let distance = cosmogony
+        .distance_between(city("Luxembourg"), city("Esch-sur-Alzette"))
+        .unwrap();
+    assert!((12_000. ..20_000.).contains(&distance), "{}", distance);
 }
 
 fn get_zone<'a>(cosmogony: &'a Cosmogony, idx: &'a ZoneIndex) -> Option<&'a Zone> {
@@ -284,15 +1496,34 @@ fn test_lux_zone_types() {
     assert_eq!(lux.tags.get("population"), Some(&"493500".into()));
 }
 
+#[test]
+fn test_lux_zone_depths() {
+    let cosmogony = create_cosmogony_for_lux();
+    let depth = |name: &str, zone_type: ZoneType| {
+        cosmogony
+            .zones
+            .iter()
+            .find(|z| z.name == name && z.zone_type == Some(zone_type))
+            .map(|z| z.depth)
+    };
+    // Lëtzebuerg > Canton Luxembourg > Luxembourg
+    assert_eq!(depth("Lëtzebuerg", ZoneType::Country), Some(0));
+    assert_eq!(depth("Canton Luxembourg", ZoneType::StateDistrict), Some(1));
+    assert_eq!(depth("Luxembourg", ZoneType::City), Some(2));
+}
+
 #[test]
 fn test_center_label() {
     let ottawa_test_file = concat!(
         env!("OUT_DIR"),
         "/../../../../../tests/data/gatineau.osm.pbf"
     );
-    let cosmogony =
-        cosmogony_builder::build_cosmogony(ottawa_test_file.into(), Some("ca".into()), true, &[])
-            .expect("invalid cosmogony");
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(
+        &BuildConfig::new(ottawa_test_file)
+            .country_code("ca")
+            .disable_voronoi(true),
+    )
+    .expect("invalid cosmogony");
 
     let gati = cosmogony
         .zones
@@ -312,22 +1543,504 @@ fn test_center_label() {
 
 #[test]
 fn test_voronoi() {
-    let ivory_test_file = concat!(
-        env!("OUT_DIR"),
-        "/../../../../../tests/data/ivory-coast.pbf"
-    );
-    let path = Path::new(&ivory_test_file);
+    let path = Path::new(IVORY_COAST_PBF);
     let file = File::open(path).expect("no pbf file");
 
     let parsed_pbf = OsmPbfReader::new(file)
         .get_objs_and_deps(|o| is_admin(o) || is_place(o))
         .expect("invalid osm file");
 
-    let (mut zones, mut stats) =
-        get_zones_and_stats(&parsed_pbf).expect("get_zones_and_stats failed");
+    let (mut zones, mut stats) = get_zones_and_stats(&parsed_pbf, &BuildConfig::default())
+        .expect("get_zones_and_stats failed");
 
     assert_eq!(zones.len(), 118);
-    create_ontology(&mut zones, &mut stats, None, false, &parsed_pbf, &[])
-        .expect("create_ontology failed");
+    create_ontology(
+        &mut zones,
+        &mut stats,
+        Some(&parsed_pbf),
+        &BuildConfig::default(),
+    )
+    .expect("create_ontology failed");
     assert_eq!(zones.len(), 4471);
 }
+
+#[test]
+fn test_voronoi_is_deterministic() {
+    let file = File::open(Path::new(IVORY_COAST_PBF)).expect("no pbf file");
+    let parsed_pbf = OsmPbfReader::new(file)
+        .get_objs_and_deps(|o| is_admin(o) || is_place(o))
+        .expect("invalid osm file");
+
+    let build_with_threads = |num_threads: usize| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let (mut zones, mut stats) = get_zones_and_stats(&parsed_pbf, &BuildConfig::default())
+                .expect("get_zones_and_stats failed");
+            create_ontology(
+                &mut zones,
+                &mut stats,
+                Some(&parsed_pbf),
+                &BuildConfig::default(),
+            )
+            .expect("create_ontology failed");
+            // the ids are not the zones' positions once the untyped zones are removed
+            let osm_ids: BTreeMap<_, _> = zones.iter().map(|z| (z.id, z.osm_id.clone())).collect();
+            zones
+                .iter()
+                .map(|z| (z.osm_id.clone(), z.parent.map(|p| osm_ids[&p].clone())))
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let single_threaded = build_with_threads(1);
+    assert_eq!(single_threaded.len(), 4471);
+    assert_eq!(single_threaded, build_with_threads(4));
+}
+
+#[test]
+fn test_maritime_zones() {
+    use osmpbfreader::objects::{Node, NodeId, OsmObj, Ref, Relation, RelationId, Way, WayId};
+
+    let file = File::open(Path::new(LUX_PBF)).expect("no pbf file");
+    let mut parsed_pbf = OsmPbfReader::new(file)
+        .get_objs_and_deps(|o| is_admin(o) || is_maritime(o))
+        .expect("invalid osm file");
+
+    // a maritime relation around the whole Luxembourg (the maritime zones can overlap the land)
+    let node_ids: Vec<NodeId> = (0..4).map(|i| NodeId(1_000_000_000 + i)).collect();
+    let corners = [(5., 49.), (7., 49.), (7., 51.), (5., 51.)];
+    for (id, (lon, lat)) in node_ids.iter().zip(corners) {
+        let node = OsmObj::Node(Node {
+            id: *id,
+            tags: osmpbfreader::Tags::new(),
+            decimicro_lat: (lat * 1e7) as i32,
+            decimicro_lon: (lon * 1e7) as i32,
+        });
+        parsed_pbf.insert(node.id(), node);
+    }
+    let way = OsmObj::Way(Way {
+        id: WayId(1_000_000_000),
+        tags: osmpbfreader::Tags::new(),
+        nodes: node_ids.iter().chain(node_ids.first()).copied().collect(),
+    });
+    let mut tags = osmpbfreader::Tags::new();
+    tags.insert("boundary".into(), "maritime".into());
+    tags.insert("border_type".into(), "eez".into());
+    tags.insert("name".into(), "Exclusive Economic Zone".into());
+    let relation = OsmObj::Relation(Relation {
+        id: RelationId(1_000_000_000),
+        tags,
+        refs: vec![Ref {
+            member: way.id(),
+            role: "outer".into(),
+        }],
+    });
+    assert!(is_maritime(&relation) && !is_admin(&relation));
+    parsed_pbf.insert(way.id(), way);
+    parsed_pbf.insert(relation.id(), relation);
+
+    let build = |config: &BuildConfig| {
+        let (mut zones, mut stats) =
+            get_zones_and_stats(&parsed_pbf, config).expect("get_zones_and_stats failed");
+        create_ontology(&mut zones, &mut stats, None, config).expect("create_ontology failed");
+        zones
+    };
+    let config = lux_config();
+
+    // the maritime zones are only built on demand
+    assert!(build(&config).iter().all(|z| !z.is_maritime()));
+
+    let zones = build(&config.include_maritime(true));
+    let maritime: Vec<&Zone> = zones.iter().filter(|z| z.is_maritime()).collect();
+    assert_eq!(maritime.len(), 1);
+    let maritime = maritime[0];
+    assert_eq!(maritime.osm_id, "relation:1000000000");
+    assert_eq!(maritime.zone_type, Some(ZoneType::Maritime));
+
+    // they are not in the hierarchy of the land zones
+    assert_eq!(maritime.parent, None);
+    assert!(zones.iter().all(|z| z.parent != Some(maritime.id)));
+    let country = zones
+        .iter()
+        .find(|z| z.zone_type == Some(ZoneType::Country))
+        .expect("no country");
+    assert_eq!(country.parent, None);
+    assert!(country.contains_point(6.13, 49.61));
+    assert!(maritime.contains_point(6.13, 49.61));
+}
+
+#[test]
+fn test_admin_level_from_label_node() {
+    use osmpbfreader::objects::{Node, NodeId, OsmObj, Ref, Relation, RelationId, Way, WayId};
+
+    // a relation with an invalid admin_level, and a label node with a valid one
+    let mut parsed_pbf = BTreeMap::new();
+    let node_ids: Vec<NodeId> = (0..4).map(NodeId).collect();
+    let corners = [(5., 49.), (6., 49.), (6., 50.), (5., 50.)];
+    for (id, (lon, lat)) in node_ids.iter().zip(corners) {
+        let node = OsmObj::Node(Node {
+            id: *id,
+            tags: osmpbfreader::Tags::new(),
+            decimicro_lat: (lat * 1e7) as i32,
+            decimicro_lon: (lon * 1e7) as i32,
+        });
+        parsed_pbf.insert(node.id(), node);
+    }
+    let mut label_tags = osmpbfreader::Tags::new();
+    label_tags.insert("admin_level".into(), "8".into());
+    let label = OsmObj::Node(Node {
+        id: NodeId(10),
+        tags: label_tags,
+        decimicro_lat: 495_000_000,
+        decimicro_lon: 55_000_000,
+    });
+    let way = OsmObj::Way(Way {
+        id: WayId(1),
+        tags: osmpbfreader::Tags::new(),
+        nodes: node_ids.iter().chain(node_ids.first()).copied().collect(),
+    });
+    let mut tags = osmpbfreader::Tags::new();
+    tags.insert("boundary".into(), "administrative".into());
+    tags.insert("admin_level".into(), "8;9".into());
+    tags.insert("name".into(), "Labelville".into());
+    let relation = OsmObj::Relation(Relation {
+        id: RelationId(1),
+        tags,
+        refs: vec![
+            Ref {
+                member: way.id(),
+                role: "outer".into(),
+            },
+            Ref {
+                member: label.id(),
+                role: "label".into(),
+            },
+        ],
+    });
+    assert!(is_admin(&relation));
+    parsed_pbf.insert(label.id(), label);
+    parsed_pbf.insert(way.id(), way);
+    parsed_pbf.insert(relation.id(), relation);
+
+    let zone = |config: &BuildConfig| {
+        let (zones, _) =
+            get_zones_and_stats(&parsed_pbf, config).expect("get_zones_and_stats failed");
+        assert_eq!(zones.len(), 1);
+        zones.into_iter().next().unwrap()
+    };
+
+    // by default, the admin_level of the label node is not used
+    let default = zone(&BuildConfig::default());
+    assert_eq!(default.admin_level, None);
+    assert_eq!(default.admin_level_source, None);
+
+    let labelville = zone(&BuildConfig::default().admin_level_from_label_node(true));
+    assert_eq!(labelville.admin_level, Some(8));
+    assert_eq!(
+        labelville.admin_level_source,
+        Some(AdminLevelSource::LabelNode)
+    );
+
+    // the clamped admin_levels are defaulted
+    let clamped = zone(
+        &BuildConfig::default()
+            .admin_level_from_label_node(true)
+            .max_raw_admin_level(6)
+            .clamp_raw_admin_level(true),
+    );
+    assert_eq!(clamped.admin_level, Some(6));
+    assert_eq!(
+        clamped.admin_level_source,
+        Some(AdminLevelSource::Defaulted)
+    );
+}
+
+#[test]
+fn test_create_ontology_without_pbf() {
+    // zones built without osm: a country containing a city
+    let zone = |index: usize, name: &str, admin_level: u32, size: f64| {
+        let boundary = MultiPolygon(vec![Polygon::new(
+            LineString::from(vec![
+                (0., 0.),
+                (0., size),
+                (size, size),
+                (size, 0.),
+                (0., 0.),
+            ]),
+            vec![],
+        )]);
+        let mut tags = osmpbfreader::Tags::new();
+        tags.insert("name".into(), name.into());
+        Zone {
+            id: ZoneIndex { index },
+            osm_id: format!("custom:{}", index),
+            admin_level: Some(admin_level),
+            name: name.into(),
+            bbox: boundary.bounding_rect(),
+            boundary: Some(boundary),
+            tags,
+            is_generated: false,
+            ..Default::default()
+        }
+    };
+    let mut zones = vec![zone(0, "Bigland", 2, 10.), zone(1, "Smalltown", 8, 1.)];
+    let mut stats = CosmogonyStats::default();
+
+    // the osm objects are needed for the voronoi zones
+    let config = BuildConfig::default().country_code("lu");
+    assert!(create_ontology(&mut zones.clone(), &mut stats, None, &config).is_err());
+
+    let config = config.disable_voronoi(true);
+    create_ontology(&mut zones, &mut stats, None, &config).expect("create_ontology failed");
+
+    assert_eq!(zones.len(), 2);
+    assert_eq!(zones[0].zone_type, Some(ZoneType::Country));
+    assert_eq!(zones[0].parent, None);
+    assert_eq!(zones[1].zone_type, Some(ZoneType::City));
+    assert_eq!(zones[1].parent, Some(zones[0].id));
+    assert_eq!(zones[1].label, "Smalltown, Bigland");
+    // a 1°x1° square on the equator, about 111km x 111km
+    let area = zones[1].area.expect("no area");
+    assert!((area - 1.23e10).abs() < 1e8, "{}", area);
+}
+
+#[test]
+fn test_create_ontology_center_outside_boundary() {
+    // a city whose admin_centre node has been put outside of its boundary
+    let boundary = MultiPolygon(vec![Polygon::new(
+        LineString::from(vec![(0., 0.), (0., 1.), (1., 1.), (1., 0.), (0., 0.)]),
+        vec![],
+    )]);
+    let mut tags = osmpbfreader::Tags::new();
+    tags.insert("name".into(), "Smalltown".into());
+    let mut center_tags = osmpbfreader::Tags::new();
+    center_tags.insert("place".into(), "city".into());
+    let zone = Zone {
+        id: ZoneIndex { index: 0 },
+        osm_id: "custom:0".into(),
+        admin_level: Some(8),
+        name: "Smalltown".into(),
+        bbox: boundary.bounding_rect(),
+        boundary: Some(boundary),
+        center: Some(Coord::new(5., 5.)),
+        tags,
+        center_tags,
+        ..Default::default()
+    };
+    let config = lux_config();
+
+    let mut zones = vec![zone.clone()];
+    let mut stats = CosmogonyStats::default();
+    create_ontology(&mut zones, &mut stats, None, &config).expect("create_ontology failed");
+    assert_eq!(stats.center_outside_boundary, vec!["custom:0".to_string()]);
+    // by default the center is only reported
+    assert_eq!(zones[0].center, Some(Coord::new(5., 5.)));
+
+    let config = config.relocate_outside_centers(true);
+    let mut zones = vec![zone];
+    let mut stats = CosmogonyStats::default();
+    create_ontology(&mut zones, &mut stats, None, &config).expect("create_ontology failed");
+    assert_eq!(stats.center_outside_boundary, vec!["custom:0".to_string()]);
+    let center = zones[0].center.expect("no center");
+    assert!(zones[0].contains_point(center.x(), center.y()));
+}
+
+#[test]
+fn test_is_place_of_types() {
+    let place_node = |place: &str| {
+        let mut tags = osmpbfreader::Tags::new();
+        tags.insert("place".into(), place.into());
+        osmpbfreader::OsmObj::Node(osmpbfreader::Node {
+            id: osmpbfreader::NodeId(1),
+            tags,
+            decimicro_lat: 0,
+            decimicro_lon: 0,
+        })
+    };
+
+    // by default, the hamlets are not places
+    assert!(is_place(&place_node("village")));
+    assert!(is_place(&place_node("suburb")));
+    assert!(!is_place(&place_node("hamlet")));
+
+    let place_types = vec!["city".to_string(), "hamlet".to_string()];
+    assert!(is_place_of_types(&place_node("hamlet"), &place_types));
+    assert!(is_place_of_types(&place_node("city"), &place_types));
+    assert!(!is_place_of_types(&place_node("village"), &place_types));
+}
+
+#[test]
+fn test_lux_cosmogony_only_country() {
+    let full = create_cosmogony_for_lux();
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(
+        &BuildConfig::new(LUX_PBF)
+            .disable_voronoi(true)
+            .only_country("lu"),
+    )
+    .expect("invalid cosmogony");
+
+    assert!(!cosmogony.zones.is_empty());
+    assert!(cosmogony.zones.len() <= full.zones.len());
+    let country = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.zone_type == Some(ZoneType::Country))
+        .unwrap();
+    assert_eq!(country.osm_id, "relation:2171347");
+    for (index, z) in cosmogony.zones.iter().enumerate() {
+        assert_eq!(z.id.index, index);
+        assert_eq!(z.country_code, Some("LU".into()));
+    }
+
+    // the country must be in the pbf
+    assert!(cosmogony_builder::build_cosmogony_with_config(
+        &BuildConfig::new(LUX_PBF)
+            .disable_voronoi(true)
+            .only_country("fr"),
+    )
+    .is_err());
+}
+
+#[test]
+fn test_lux_cosmogony_clip() {
+    // the south-west of Luxembourg, around Esch-sur-Alzette
+    let clip = concat!(env!("OUT_DIR"), "/clip_esch.geojson");
+    std::fs::write(
+        clip,
+        r#"{"type": "Feature", "properties": {}, "geometry": {"type": "Polygon", "coordinates":
+            [[[5.9, 49.44], [6.1, 49.44], [6.1, 49.55], [5.9, 49.55], [5.9, 49.44]]]}}"#,
+    )
+    .unwrap();
+    let full = create_cosmogony_for_lux();
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(&lux_config().clip(clip))
+        .expect("invalid cosmogony");
+
+    assert!(cosmogony.zones.len() < full.zones.len());
+    assert!(cosmogony.meta.stats.clipped_out_zones > 0);
+    for z in &cosmogony.zones {
+        use geo::Contains;
+        if let (Some(boundary), Some(bbox)) = (&z.boundary, z.bbox) {
+            assert!(bbox.min().x >= 5.9 - 1e-9 && bbox.max().x <= 6.1 + 1e-9);
+            assert!(bbox.min().y >= 49.44 - 1e-9 && bbox.max().y <= 49.55 + 1e-9);
+            assert!(boundary.contains(&z.center.unwrap()));
+        }
+        // the hierarchy only references kept zones
+        if let Some(parent) = z.parent {
+            assert!(cosmogony.zones.iter().any(|p| p.id == parent));
+        }
+    }
+
+    // the zones partially inside are kept, clipped
+    let country = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.zone_type == Some(ZoneType::Country))
+        .unwrap();
+    assert_eq!(country.osm_id, "relation:2171347");
+    let esch = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.name == "Esch-sur-Alzette" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    assert!(esch.label.contains(&country.name));
+}
+
+#[test]
+fn test_lux_cosmogony_center_from_capital() {
+    let default = create_cosmogony_for_lux();
+    let cosmogony =
+        cosmogony_builder::build_cosmogony_with_config(&lux_config().center_from_capital(true))
+            .expect("invalid cosmogony");
+    assert_eq!(cosmogony.zones.len(), default.zones.len());
+
+    // the center of the country is its capital
+    let country = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.zone_type == Some(ZoneType::Country))
+        .unwrap();
+    assert_eq!(country.osm_id, "relation:2171347");
+    assert_eq!(country.center_tags.get("capital"), Some(&"yes".into()));
+
+    // the center of the zones which are neither countries nor states is not changed,
+    // whatever their admin_level
+    for (z, default_z) in cosmogony.zones.iter().zip(default.zones.iter()) {
+        assert_eq!(z.osm_id, default_z.osm_id);
+        if !matches!(z.zone_type, Some(ZoneType::Country | ZoneType::State)) {
+            assert_eq!(z.center, default_z.center);
+            assert_eq!(z.center_tags, default_z.center_tags);
+        }
+    }
+}
+
+#[test]
+fn test_lux_cosmogony_name_filter() {
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(
+        &lux_config().name_filter(regex::Regex::new("Esch").unwrap()),
+    )
+    .expect("invalid cosmogony");
+
+    assert!(!cosmogony.zones.is_empty());
+    for (index, z) in cosmogony.zones.iter().enumerate() {
+        assert_eq!(z.id.index, index);
+        assert!(
+            z.name.contains("Esch")
+                || z.tags
+                    .iter()
+                    .any(|(k, v)| k.starts_with("name:") && v.contains("Esch")),
+            "{} does not match",
+            z.name
+        );
+        // the parents are kept zones
+        assert!(z.parent.is_none_or(|p| p.index < cosmogony.zones.len()));
+    }
+    let esch = cosmogony
+        .zones
+        .iter()
+        .find(|z| z.name == "Esch-sur-Alzette" && z.zone_type == Some(ZoneType::City))
+        .unwrap();
+    // the labels still contain all the ancestors
+    assert!(esch.label.contains("Lëtzebuerg"));
+}
+
+#[test]
+fn test_cmd_invalid_name_filter() {
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "--name-filter",
+        "Esch(",
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("invalid name filter"));
+}
+
+#[test]
+fn test_lux_cosmogony_exclude_osm_ids() {
+    let cosmogony = cosmogony_builder::build_cosmogony_with_config(
+        &lux_config().exclude_osm_ids(vec!["relation:407489".into(), "relation:0".into()]),
+    )
+    .expect("invalid cosmogony");
+
+    assert_eq!(cosmogony.meta.stats.excluded_zones, 1);
+    assert!(!cosmogony
+        .zones
+        .iter()
+        .any(|z| z.osm_id == "relation:407489"));
+    assert!(cosmogony.zones.iter().any(|z| z.name == "Esch-sur-Alzette"));
+}
+
+#[test]
+fn test_cmd_invalid_exclude_osm_ids() {
+    let output = launch_command_line(vec![
+        "-i",
+        "./tests/data/luxembourg_filtered.osm.pbf",
+        "--exclude-osm-ids",
+        "407489",
+    ]);
+    assert!(!output.status.success());
+}